@@ -3,7 +3,7 @@ use std::collections::BTreeMap;
 use std::mem;
 
 use notty::datatypes::{Color, ConfigStyle};
-use notty::terminal::Styles;
+use notty::terminal::{Palette, Styles};
 
 pub type TrueColor = (u8, u8, u8);
 pub type GtkColor = (f64, f64, f64);
@@ -35,6 +35,21 @@ impl Config {
             palette: *palette,
         }
     }
+    /// The colors of this configuration, to be given to the terminal as its default palette.
+    pub fn palette(&self) -> Palette {
+        Palette::new(self.fg_color, self.bg_color, self.cursor_color, &self.palette)
+    }
+
+    /// Draw with the colors of the terminal's palette, which programs may have changed.
+    pub fn set_palette(&mut self, palette: &Palette) {
+        self.fg_color = palette.fg_color;
+        self.bg_color = palette.bg_color;
+        self.cursor_color = palette.cursor_color;
+        for n in 0..256 {
+            self.palette[n] = palette.color(n as u8);
+        }
+    }
+
     pub fn fg_color(&self, color: Color) -> TrueColor {
         self.color(color, self.fg_color)
     }
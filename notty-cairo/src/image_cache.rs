@@ -0,0 +1,104 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Weak};
+
+use cairo;
+
+use notty::datatypes::MediaPosition;
+use notty::terminal::ImageData;
+
+use image_renderer::{ImageRenderer, draw_placeholder};
+
+/// The memory decoded images may use if no other limit has been set.
+pub const DEFAULT_CACHE_LIMIT: usize = 0x8000000;
+
+// Images are cached by the address of the data the terminal holds and the size they are drawn
+// at, so equal images placed separately or drawn at other sizes are not confused, and finding
+// an image does not hash its contents. The weak reference keeps the address from being reused
+// while the image is cached.
+type ImageKey = (usize, u32, u32);
+
+struct CachedImage {
+    data: Weak<ImageData>,
+    pos: MediaPosition,
+    renderer: ImageRenderer,
+    last_used: u64,
+}
+
+/// Images are decoded and scaled once, then cached until they are removed from the grid or the
+/// cache grows beyond its limit, when the least recently drawn images are dropped first.
+pub struct ImageCache {
+    images: HashMap<ImageKey, CachedImage>,
+    clock: u64,
+    size: usize,
+    limit: usize,
+}
+
+impl ImageCache {
+    pub fn new(limit: usize) -> ImageCache {
+        ImageCache {
+            images: HashMap::new(),
+            clock: 0,
+            size: 0,
+            limit: limit,
+        }
+    }
+
+    pub fn set_limit(&mut self, limit: usize) {
+        self.limit = limit;
+        self.shrink();
+    }
+
+    /// Remove images which are no longer held by the terminal.
+    pub fn remove_dead(&mut self) {
+        let dead = self.images.iter().filter(|&(_, image)| image.data.upgrade().is_none())
+                       .map(|(&key, _)| key).collect::<Vec<_>>();
+        for key in dead {
+            self.remove(&key);
+        }
+    }
+
+    pub fn draw(&mut self, canvas: &cairo::Context, data: &Arc<ImageData>, x_pix: f64,
+                y_pix: f64, w_pix: f64, h_pix: f64, pos: MediaPosition) {
+        if data.is_placeholder() {
+            return draw_placeholder(canvas, x_pix, y_pix, w_pix, h_pix);
+        }
+        self.clock += 1;
+        let key = (&**data as *const ImageData as usize, w_pix as u32, h_pix as u32);
+        if let Some(image) = self.images.get_mut(&key) {
+            if image.pos == pos {
+                image.last_used = self.clock;
+                return image.renderer.draw(canvas, x_pix, y_pix, data.frame());
+            }
+        }
+        self.remove(&key);
+        let renderer = ImageRenderer::new(data, w_pix, h_pix, pos);
+        renderer.draw(canvas, x_pix, y_pix, data.frame());
+        self.size += renderer.size();
+        self.images.insert(key, CachedImage {
+            data: Arc::downgrade(data),
+            pos: pos,
+            renderer: renderer,
+            last_used: self.clock,
+        });
+        self.shrink();
+    }
+
+    // Drop the least recently drawn images until the cache is within its limit. The image drawn
+    // most recently is always kept, even if it alone is larger than the limit.
+    fn shrink(&mut self) {
+        while self.size > self.limit && self.images.len() > 1 {
+            let oldest = self.images.iter().min_by_key(|&(_, image)| image.last_used)
+                             .map(|(&key, _)| key);
+            match oldest {
+                Some(key)   => self.remove(&key),
+                None        => break,
+            }
+        }
+    }
+
+    fn remove(&mut self, key: &ImageKey) {
+        if let Some(image) = self.images.remove(key) {
+            self.size -= image.renderer.size();
+        }
+    }
+}
@@ -1,62 +1,303 @@
+use std::cmp;
 use std::mem;
 use std::ptr;
 
-use cairo;
+use cairo::{self, Format, ImageSurface, Operator};
 
+use gdk;
 use gdk::prelude::ContextExt;
 use glib::translate::FromGlibPtr;
-use notty::datatypes::MediaPosition;
+use notty::datatypes::{MediaAlignment, MediaPosition};
+use notty::datatypes::MediaAlignment::*;
 use notty::datatypes::MediaPosition::*;
 use pixbuf::{InterpType, Pixbuf};
 use gio;
 use pixbuf_sys;
 use libc;
 
-use notty::terminal::Styles;
+use notty::terminal::{Animation, Blend, Dispose, ImageData, Styles};
+
+// The most frames of an animation which are composited, and the most memory the composited
+// frames may use together. Animations beyond these limits stop at the last frame kept, and
+// those with frames too large for even one are shown as a still image.
+const MAX_FRAMES: usize = 0x400;
+const MAX_FRAMES_SIZE: usize = 0x8000000;
 
 pub struct ImageRenderer {
-    pixbuf: Pixbuf,
-    x_pos: f64,
-    y_pos: f64,
+    frames: Vec<Pixbuf>,
+    width: f64,
+    height: f64,
+    layout: Layout,
 }
 
 impl ImageRenderer {
-    pub fn new(data: &[u8], x: f64, y: f64, w: f64, h: f64, pos: MediaPosition) -> ImageRenderer {
-        fn pixbuf_from_data(data: &[u8]) -> Option<Pixbuf> {
-            let null = ptr::null_mut();
-            unsafe {
-                let (data, len) = (mem::transmute(data.as_ptr()), data.len() as libc::ssize_t);
-                let stream = gio::g_memory_input_stream_new_from_data(data, len, None);
-                let pixbuf = pixbuf_sys::gdk_pixbuf_new_from_stream(stream, null, null as *mut _);
-                if pixbuf != null as *mut _ { Some(Pixbuf::from_glib_full(pixbuf)) }
-                else { None }
-            }
-        }
+    pub fn new(image: &ImageData, w: f64, h: f64, pos: MediaPosition) -> ImageRenderer {
         fn empty_pixbuf() -> Pixbuf {
             unsafe { Pixbuf::new(0, false, 0, 1, 1).expect("Could not create empty Pixbuf.") }
         }
 
-        let image = pixbuf_from_data(data).and_then(|img| {
-            match pos {
-                Display(x_align, y_align) => unimplemented!(),
-                Fill => unimplemented!(),
-                Fit => unimplemented!(),
-                Stretch => {
-                    img.scale_simple(w as i32, h as i32, InterpType::Bilinear).ok()
-                }
-                Tile => unimplemented!(),
-            }
-        }).unwrap_or_else(empty_pixbuf);
+        let frames = match image.animation() {
+            Some(animation) => composite_frames(&image.data, animation),
+            None            => pixbuf_from_data(&image.data).into_iter().collect(),
+        };
+
+        let layout = match frames.first() {
+            Some(img)   => Layout::new(img.get_width() as f64, img.get_height() as f64, w, h, pos),
+            None        => Layout::new(1.0, 1.0, w, h, pos),
+        };
+        let scaled_w = layout.width.round().max(1.0) as i32;
+        let scaled_h = layout.height.round().max(1.0) as i32;
+
+        // SVGs are rendered again at the size they are drawn, rather than scaled.
+        let vector = if image.is_vector() {
+            pixbuf_from_data_at_scale(&image.data, scaled_w, scaled_h)
+        } else { None };
+
+        let mut frames = match vector {
+            Some(img)   => vec![img],
+            None        => frames.into_iter().filter_map(|img| {
+                if img.get_width() == scaled_w && img.get_height() == scaled_h { return Some(img) }
+                img.scale_simple(scaled_w, scaled_h, InterpType::Bilinear).ok()
+            }).collect(),
+        };
+        if frames.is_empty() { frames.push(empty_pixbuf()); }
 
         ImageRenderer {
-            pixbuf: image,
-            x_pos: x,
-            y_pos: y,
+            frames: frames,
+            width: w,
+            height: h,
+            layout: layout,
         }
     }
 
-    pub fn draw(&self, canvas: &cairo::Context) {
-        canvas.set_source_pixbuf(&self.pixbuf, self.x_pos, self.y_pos);
-        canvas.paint();
+    /// Draw a frame of the image with the top left corner of its rectangle at a position, which
+    /// changes as the image scrolls. Images which are not animated have only one frame.
+    pub fn draw(&self, canvas: &cairo::Context, x_pos: f64, y_pos: f64, frame: usize) {
+        let pixbuf = &self.frames[cmp::min(frame, self.frames.len() - 1)];
+        // Images are clipped to the rectangle of the cells they were placed in.
+        canvas.save();
+        canvas.rectangle(x_pos, y_pos, self.width, self.height);
+        canvas.clip();
+        for (x, y) in self.layout.origins(self.width, self.height) {
+            canvas.set_source_pixbuf(pixbuf, x_pos + x, y_pos + y);
+            canvas.paint();
+        }
+        canvas.restore();
     }
+
+    /// The memory used by the decoded image, in bytes.
+    pub fn size(&self) -> usize {
+        self.frames.iter().map(|pixbuf| {
+            pixbuf.get_rowstride() as usize * pixbuf.get_height() as usize
+        }).sum()
+    }
+}
+
+// Draw each frame of an animation over the frames before it, keeping a copy of the whole
+// animation as it looks while each frame is showing.
+fn composite_frames(data: &[u8], animation: &Animation) -> Vec<Pixbuf> {
+    let frame_size = (animation.width as usize).saturating_mul(animation.height as usize)
+                                               .saturating_mul(4);
+    let count = cmp::min(MAX_FRAMES, MAX_FRAMES_SIZE / cmp::max(frame_size, 1));
+    if count == 0 { return pixbuf_from_data(data).into_iter().collect() }
+    let (width, height) = (animation.width as i32, animation.height as i32);
+    let surface = ImageSurface::create(Format::ARgb32, width, height);
+    let canvas = cairo::Context::new(&surface);
+    let mut frames = vec![];
+    for (n, frame) in animation.frames.iter().enumerate().take(count) {
+        let (x, y) = (frame.x as i32, frame.y as i32);
+        let (w, h) = (frame.width as i32, frame.height as i32);
+        let previous = match frame.dispose {
+            Dispose::Previous   => gdk::pixbuf_get_from_surface(&surface, x, y, w, h),
+            _                   => None,
+        };
+        canvas.save();
+        canvas.rectangle(x as f64, y as f64, w as f64, h as f64);
+        canvas.clip();
+        if let Some(image) = animation.frame_image(data, n).and_then(|img| pixbuf_from_data(&img)) {
+            if frame.blend == Blend::Source { canvas.set_operator(Operator::Source); }
+            canvas.set_source_pixbuf(&image, x as f64, y as f64);
+            canvas.paint();
+        }
+        frames.extend(gdk::pixbuf_get_from_surface(&surface, 0, 0, width, height));
+        match (frame.dispose, previous) {
+            (Dispose::Keep, _)                  => { }
+            (Dispose::Previous, Some(previous)) => {
+                canvas.set_operator(Operator::Source);
+                canvas.set_source_pixbuf(&previous, x as f64, y as f64);
+                canvas.paint();
+            }
+            (_, _)                              => {
+                canvas.set_operator(Operator::Clear);
+                canvas.paint();
+            }
+        }
+        canvas.restore();
+    }
+    frames
+}
+
+fn pixbuf_from_data(data: &[u8]) -> Option<Pixbuf> {
+    let null = ptr::null_mut();
+    unsafe {
+        let (data, len) = (mem::transmute(data.as_ptr()), data.len() as libc::ssize_t);
+        let stream = gio::g_memory_input_stream_new_from_data(data, len, None);
+        let pixbuf = pixbuf_sys::gdk_pixbuf_new_from_stream(stream, null, null as *mut _);
+        if pixbuf != null as *mut _ { Some(Pixbuf::from_glib_full(pixbuf)) }
+        else { None }
+    }
+}
+
+fn pixbuf_from_data_at_scale(data: &[u8], w: i32, h: i32) -> Option<Pixbuf> {
+    let null = ptr::null_mut();
+    unsafe {
+        let (data, len) = (mem::transmute(data.as_ptr()), data.len() as libc::ssize_t);
+        let stream = gio::g_memory_input_stream_new_from_data(data, len, None);
+        let pixbuf = pixbuf_sys::gdk_pixbuf_new_from_stream_at_scale(stream, w, h, 0, null,
+                                                                     null as *mut _);
+        if pixbuf != null as *mut _ { Some(Pixbuf::from_glib_full(pixbuf)) }
+        else { None }
+    }
+}
+
+/// Images whose data has been evicted from the terminal are drawn as a faint outline of the
+/// rectangle they covered.
+pub fn draw_placeholder(canvas: &cairo::Context, x_pos: f64, y_pos: f64, w: f64, h: f64) {
+    canvas.save();
+    canvas.set_source_rgba(0.5, 0.5, 0.5, 0.5);
+    canvas.set_line_width(1.0);
+    canvas.rectangle(x_pos + 0.5, y_pos + 0.5, w - 1.0, h - 1.0);
+    canvas.stroke();
+    canvas.restore();
+}
+
+/// Where an image is drawn within the rectangle of its cells.
+#[derive(Copy, Clone, Debug, PartialEq)]
+struct Layout {
+    /// The offset of the image from the top left corner of the rectangle.
+    x: f64,
+    y: f64,
+    /// The size the image is scaled to.
+    width: f64,
+    height: f64,
+    /// Whether the image is repeated to cover the rectangle.
+    tile: bool,
+}
+
+impl Layout {
+    fn new(img_w: f64, img_h: f64, w: f64, h: f64, pos: MediaPosition) -> Layout {
+        match pos {
+            Display(x_align, y_align)   => Layout {
+                x: align(x_align, img_w, w),
+                y: align(y_align, img_h, h),
+                width: img_w,
+                height: img_h,
+                tile: false,
+            },
+            Fill                        => Layout::scaled(img_w, img_h, w, h, f64::max),
+            Fit                         => Layout::scaled(img_w, img_h, w, h, f64::min),
+            Stretch                     => Layout {
+                x: 0.0,
+                y: 0.0,
+                width: w,
+                height: h,
+                tile: false,
+            },
+            Tile                        => Layout {
+                x: 0.0,
+                y: 0.0,
+                width: img_w,
+                height: img_h,
+                tile: true,
+            },
+        }
+    }
+
+    // Scale the image by the same factor in both dimensions, choosing between the factors which
+    // would make it fit the width and the height, and center it.
+    fn scaled<F>(img_w: f64, img_h: f64, w: f64, h: f64, choose: F) -> Layout
+            where F: Fn(f64, f64) -> f64 {
+        let scale = choose(w / img_w, h / img_h);
+        let (width, height) = (img_w * scale, img_h * scale);
+        Layout {
+            x: align(Center, width, w),
+            y: align(Center, height, h),
+            width: width,
+            height: height,
+            tile: false,
+        }
+    }
+
+    /// The offsets at which the image is drawn to cover a rectangle of this size.
+    fn origins(&self, w: f64, h: f64) -> Vec<(f64, f64)> {
+        if !self.tile || self.width <= 0.0 || self.height <= 0.0 {
+            return vec![(self.x, self.y)];
+        }
+        let cols = (w / self.width).ceil() as usize;
+        let rows = (h / self.height).ceil() as usize;
+        (0..rows).flat_map(|row| (0..cols).map(move |col| (col, row)))
+                 .map(|(col, row)| (col as f64 * self.width, row as f64 * self.height))
+                 .collect()
+    }
+}
+
+// The offset of an image of length `img` within a space of length `len`. If the image is
+// larger than the space this is negative, and the image is clipped.
+fn align(alignment: MediaAlignment, img: f64, len: f64) -> f64 {
+    match alignment {
+        LeftTop     => 0.0,
+        Center      => (len - img) / 2.0,
+        RightBottom => len - img,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use notty::datatypes::MediaAlignment::*;
+    use notty::datatypes::MediaPosition::*;
+
+    use super::Layout;
+
+    fn layout(x: f64, y: f64, width: f64, height: f64) -> Layout {
+        Layout { x: x, y: y, width: width, height: height, tile: false }
+    }
+
+    #[test]
+    fn display() {
+        assert_eq!(Layout::new(10.0, 20.0, 40.0, 40.0, Display(LeftTop, LeftTop)),
+                   layout(0.0, 0.0, 10.0, 20.0));
+        assert_eq!(Layout::new(10.0, 20.0, 40.0, 40.0, Display(Center, RightBottom)),
+                   layout(15.0, 20.0, 10.0, 20.0));
+        assert_eq!(Layout::new(80.0, 20.0, 40.0, 40.0, Display(Center, Center)),
+                   layout(-20.0, 10.0, 80.0, 20.0));
+    }
+
+    #[test]
+    fn fill() {
+        assert_eq!(Layout::new(10.0, 20.0, 40.0, 40.0, Fill), layout(0.0, -20.0, 40.0, 80.0));
+        assert_eq!(Layout::new(40.0, 10.0, 20.0, 20.0, Fill), layout(-30.0, 0.0, 80.0, 20.0));
+    }
+
+    #[test]
+    fn fit() {
+        assert_eq!(Layout::new(10.0, 20.0, 40.0, 40.0, Fit), layout(10.0, 0.0, 20.0, 40.0));
+        assert_eq!(Layout::new(40.0, 10.0, 20.0, 20.0, Fit), layout(0.0, 7.5, 20.0, 5.0));
+    }
+
+    #[test]
+    fn stretch() {
+        assert_eq!(Layout::new(10.0, 20.0, 40.0, 30.0, Stretch), layout(0.0, 0.0, 40.0, 30.0));
+    }
+
+    #[test]
+    fn tile() {
+        let tiled = Layout::new(15.0, 20.0, 40.0, 30.0, Tile);
+        assert_eq!(tiled, Layout { tile: true, ..layout(0.0, 0.0, 15.0, 20.0) });
+        assert_eq!(tiled.origins(40.0, 30.0), vec![
+            (0.0, 0.0), (15.0, 0.0), (30.0, 0.0),
+            (0.0, 20.0), (15.0, 20.0), (30.0, 20.0),
+        ]);
+    }
+
 }
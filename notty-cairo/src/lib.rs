@@ -11,29 +11,27 @@ extern crate notty;
 extern crate pangocairo;
 
 mod cfg;
+mod image_cache;
 mod image_renderer;
 mod text_renderer;
 
-use std::collections::HashMap;
-use std::sync::Arc;
-
 use glib::translate::ToGlibPtr;
 
 use itertools::Itertools;
 
-use notty::datatypes::Coords;
-use notty::terminal::{CharData, Terminal, ImageData};
+use notty::datatypes::{ConfigStyle, Coords};
+use notty::terminal::{CharData, ImageTile, Placement, Terminal, Preedit, Styles, UseStyles};
 
 use pangocairo::wrap::{PangoLayout, PangoAttrList};
 
 use self::cfg::gtk_color;
-use self::image_renderer::ImageRenderer;
+use self::image_cache::{ImageCache, DEFAULT_CACHE_LIMIT};
 use self::text_renderer::TextRenderer;
 
 pub use self::cfg::{Config, TrueColor, PALETTE};
 
 pub struct Renderer {
-    images: HashMap<Arc<ImageData>, ImageRenderer>,
+    images: ImageCache,
     char_d: Option<(f64, f64)>,
     cfg: Config,
 }
@@ -41,12 +39,18 @@ pub struct Renderer {
 impl Renderer {
     pub fn new(cfg: Config) -> Renderer {
         Renderer {
-            images: HashMap::new(),
+            images: ImageCache::new(DEFAULT_CACHE_LIMIT),
             char_d: None,
             cfg: cfg,
         }
     }
 
+    /// Set the most memory, in bytes, which decoded images may use. The images which were drawn
+    /// least recently are dropped first, and decoded again if they are drawn again.
+    pub fn set_image_cache_limit(&mut self, limit: usize) {
+        self.images.set_limit(limit);
+    }
+
     pub fn reset_dimensions(&mut self, canvas: &cairo::Context, terminal: &mut Terminal,
                             pix_w: u32, pix_h: u32) {
         let (char_w, char_h) = self.char_d.unwrap_or_else(|| {
@@ -56,12 +60,15 @@ impl Renderer {
         });
         let width = pix_w / (char_w as u32);
         let height = pix_h / (char_h as u32);
-        terminal.set_winsize(Some(width), Some(height)).unwrap_or_else(|e| panic!("{}", e));
+        let cell_size = (char_w as u32, char_h as u32);
+        terminal.set_winsize(Some(width), Some(height), Some(cell_size))
+                .unwrap_or_else(|e| panic!("{}", e));
     }
 
     pub fn draw(&mut self, terminal: &Terminal, canvas: &cairo::Context) {
 
         if self.char_d.is_none() { self.char_d = Some(self.char_dimensions(canvas)); }
+        self.cfg.set_palette(terminal.palette());
         let (r, g, b) = gtk_color(self.cfg.bg_color);
         canvas.set_source_rgb(r, g, b);
         canvas.paint();
@@ -70,15 +77,31 @@ impl Renderer {
         let rows = terminal.cells().chunks_lazy(col_n);
 
         // Remove dead images from the cache.
-        for key in self.images.keys().filter(|k| Arc::strong_count(k) == 1).cloned().collect::<Vec<_>>() {
-            self.images.remove(&key);
-        }
+        self.images.remove_dead();
+
+        // Placed images with a negative z-index are drawn beneath the text, others above it.
+        self.draw_placements(terminal, canvas, |z| z < 0);
 
         for (y_pos, row) in rows.into_iter().enumerate() {
             let y_pix = self.y_pixels(y_pos as u32);
             let mut text = TextRenderer::new(&self.cfg, 0.0, y_pix);
+            let mut previous = None;
             for (x_pos, cell) in row.enumerate() {
-                let style = cell.styles;
+                let style = match cell.link {
+                    Some(_) => self.link_style(cell.styles),
+                    None    => cell.styles,
+                };
+                // Unicode placeholder cells show a tile of an image with a virtual placement.
+                previous = cell.image_tile(previous);
+                let tile = previous.and_then(|tile| {
+                    terminal.virtual_placement(tile.image).map(|placement| (tile, placement))
+                });
+                if let Some((tile, placement)) = tile {
+                    text.draw(canvas);
+                    self.draw_tile(canvas, placement, tile, x_pos as u32, y_pos as u32);
+                    text = TextRenderer::new(&self.cfg, self.x_pixels(x_pos as u32 + 1), y_pix);
+                    continue;
+                }
                 if (Coords { x: x_pos as u32, y: y_pos as u32 } == terminal.cursor_position()) {
                     let cursor_style = terminal.cursor_styles();
                     match cell.content {
@@ -94,28 +117,108 @@ impl Renderer {
                     CharData::Empty             => text.push(' ', style),
                     CharData::Char(ch)          => text.push(ch, style),
                     CharData::Grapheme(ref s)   => text.push_str(s, style),
-                    CharData::Extension(_)      => { }
+                    // Text after the cells covered by wide characters and images begins at
+                    // the next cell, rather than where the text before them ended.
+                    CharData::Extension(_)      => {
+                        text.draw(canvas);
+                        text = TextRenderer::new(&self.cfg, self.x_pixels(x_pos as u32 + 1), y_pix);
+                    }
                     CharData::Image { ref data, ref pos, ref width, ref height, .. } => {
+                        text.draw(canvas);
                         let x_pix = self.x_pixels(x_pos as u32);
-                        if (x_pos + *width as usize) < col_n {
-                            text.draw(canvas);
-                            text = TextRenderer::new(&self.cfg, x_pix, y_pix);
-                        }
-                        if let Some(image) = self.images.get(data) {
-                            image.draw(canvas);
-                            continue;
-                        }
-                        let w_pix = self.x_pixels(*width);
-                        let h_pix = self.y_pixels(*height);
-                        let img = ImageRenderer::new(&data.data, x_pix, y_pix, w_pix, h_pix,
-                                                     *pos);
-                        img.draw(canvas);
-                        self.images.insert(data.clone(), img);
+                        let (w_pix, h_pix) = (self.x_pixels(*width), self.y_pixels(*height));
+                        self.images.draw(canvas, data, x_pix, y_pix, w_pix, h_pix, *pos);
+                        text = TextRenderer::new(&self.cfg, self.x_pixels(x_pos as u32 + 1), y_pix);
                     }
                 }
             }
             text.draw(canvas);
         }
+
+        // Images whose first rows or columns are off the screen are drawn from the part of
+        // them which is on the screen, and clipped to the screen.
+        for (Coords { x, y }, offset, cell) in terminal.partial_images() {
+            if let CharData::Image { ref data, pos, width, height, .. } = cell.content {
+                let x_pix = self.x_pixels(x) - self.x_pixels(offset.x);
+                let y_pix = self.y_pixels(y) - self.y_pixels(offset.y);
+                let (w_pix, h_pix) = (self.x_pixels(width), self.y_pixels(height));
+                self.images.draw(canvas, data, x_pix, y_pix, w_pix, h_pix, pos);
+            }
+        }
+
+        self.draw_placements(terminal, canvas, |z| z >= 0);
+
+        if let Some(preedit) = terminal.preedit() {
+            self.draw_preedit(preedit, terminal, canvas);
+        }
+
+        // The visual bell washes the screen in the foreground color.
+        if terminal.is_flashing() {
+            let (r, g, b) = gtk_color(self.cfg.fg_color);
+            canvas.set_source_rgba(r, g, b, 0.3);
+            canvas.paint();
+        }
+    }
+
+    fn draw_placements<F>(&mut self, terminal: &Terminal, canvas: &cairo::Context, layer: F)
+            where F: Fn(i32) -> bool {
+        for (Coords { x, y }, placement) in terminal.placements() {
+            if !layer(placement.z) { continue }
+            let (x_pix, y_pix) = (self.x_pixels(x), self.y_pixels(y));
+            let (w_pix, h_pix) = (self.x_pixels(placement.width), self.y_pixels(placement.height));
+            self.images.draw(canvas, &placement.data, x_pix, y_pix, w_pix, h_pix,
+                             placement.pos);
+        }
+    }
+
+    // Draw the part of an image with a virtual placement which a placeholder cell shows.
+    fn draw_tile(&mut self, canvas: &cairo::Context, placement: &Placement, tile: ImageTile,
+                 x: u32, y: u32) {
+        let (x_pix, y_pix) = (self.x_pixels(x), self.y_pixels(y));
+        canvas.save();
+        canvas.rectangle(x_pix, y_pix, self.x_pixels(1), self.y_pixels(1));
+        canvas.clip();
+        let (w_pix, h_pix) = (self.x_pixels(placement.width), self.y_pixels(placement.height));
+        self.images.draw(canvas, &placement.data, x_pix - self.x_pixels(tile.column),
+                         y_pix - self.y_pixels(tile.row), w_pix, h_pix, placement.pos);
+        canvas.restore();
+    }
+
+    // Links are drawn underlined, in addition to whatever style they were written with.
+    fn link_style(&self, style: UseStyles) -> UseStyles {
+        let styles = match style {
+            UseStyles::Custom(styles)   => styles,
+            UseStyles::Config(config)   => {
+                self.cfg.styles.get(&config).or_else(|| self.cfg.styles.get(&ConfigStyle::Plain))
+                               .map_or_else(Styles::default, |&s|s)
+            }
+        };
+        UseStyles::Custom(Styles { underline: true, ..styles })
+    }
+
+    fn draw_preedit(&self, preedit: &Preedit, terminal: &Terminal, canvas: &cairo::Context) {
+        let Coords { x, y } = terminal.cursor_position();
+        let (x_pix, y_pix) = (self.x_pixels(x), self.y_pixels(y));
+
+        // Blank out the cells beneath the preedit text, including a cell for the cursor.
+        let (r, g, b) = gtk_color(self.cfg.bg_color);
+        canvas.set_source_rgb(r, g, b);
+        canvas.rectangle(x_pix, y_pix, self.x_pixels(preedit.width() + 1), self.y_pixels(1));
+        canvas.fill();
+
+        let mut text = TextRenderer::new(&self.cfg, x_pix, y_pix);
+        let cursor_style = terminal.cursor_styles();
+        for (n, ch) in preedit.text().chars().enumerate() {
+            if n == preedit.cursor() {
+                text.push_cursor(ch, preedit.styles(), cursor_style);
+            } else {
+                text.push(ch, preedit.styles());
+            }
+        }
+        if preedit.cursor() == preedit.text().chars().count() {
+            text.push_cursor(' ', preedit.styles(), cursor_style);
+        }
+        text.draw(canvas);
     }
 
     fn char_dimensions(&self, canvas: &cairo::Context) -> (f64, f64) {
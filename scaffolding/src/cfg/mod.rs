@@ -2,6 +2,7 @@ use std::borrow::Cow;
 use std::env;
 use std::path::PathBuf;
 
+use notty::terminal::BellSettings;
 use notty_cairo::Config as CairoConfig;
 
 mod toml;
@@ -11,6 +12,7 @@ const CONFIG_FILE: &'static str = "scaffolding.toml";
 pub struct Config {
     pub cairo: CairoConfig,
     pub shell: Cow<'static, str>,
+    pub bell: BellSettings,
 }
 
 impl Config {
@@ -30,6 +32,7 @@ impl Default for Config {
         Config {
             cairo: CairoConfig::default(),
             shell: Cow::Borrowed("sh"),
+            bell: BellSettings::default(),
         }
     }
 }
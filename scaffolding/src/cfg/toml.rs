@@ -22,7 +22,7 @@ use std::{error, fmt, io, result};
 
 use notty::cfg::{SCROLLBACK, TAB_STOP};
 use notty::datatypes::{CodeGroup, Color, ConfigStyle};
-use notty::terminal::Styles;
+use notty::terminal::{BellSettings, Styles};
 use notty_cairo::{Config as CairoConfig, TrueColor, PALETTE};
 
 use super::Config;
@@ -85,6 +85,17 @@ fn update_general(shell: &mut Cow<'static, str>, font: &mut Cow<'static, str>, t
     }
 }
 
+fn update_bell(bell: &mut BellSettings, table: &Table) {
+    for (k, v) in table.iter() {
+        match &k[..] {
+            "audible"   => bell.audible = v.as_bool().unwrap(),
+            "visual"    => bell.visual = v.as_bool().unwrap(),
+            "urgent"    => bell.urgent = v.as_bool().unwrap(),
+            _           => {},
+        };
+    }
+}
+
 fn update_colors(config: &mut CairoConfig, table: &Table) {
     for (k, v) in table.iter() {
         match &k[..] {
@@ -136,6 +147,7 @@ pub fn update_from_file<P: AsRef<Path>>(cfg: &mut Config, path: P) -> Result<()>
 
     for (k, v) in table.iter() {
         match &k[..] {
+            "bell"      => update_bell(&mut cfg.bell, v.as_table().unwrap()),
             "color"     => update_colors(&mut cfg.cairo, v.as_table().unwrap()),
             "general"   => update_general(&mut cfg.shell, &mut cfg.cairo.font, v.as_table().unwrap()),
             "syntax"    => update_syntax(&mut cfg.cairo, v.as_table().unwrap()),
@@ -263,5 +275,6 @@ mod tests {
         let update_path = "resources/update-config.toml".to_string();
         update_from_file(&mut config, &update_path).unwrap();
         assert_eq!(config.cairo.font, "Liberation Mono 8");
+        assert!(config.bell.visual);
     }
 }
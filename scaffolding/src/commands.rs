@@ -1,11 +1,11 @@
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::io;
 use std::rc::Rc;
 use std::result;
 use std::sync::mpsc::Receiver;
 use std::sync::mpsc::TryRecvError::*;
 
-use gtk::{self, WidgetExt};
+use gtk::{self, WidgetExt, WindowExt};
 
 use notty::Command;
 use notty::terminal::Terminal;
@@ -14,6 +14,9 @@ pub struct CommandApplicator {
     rx: Receiver<Command>,
     terminal: Rc<RefCell<Terminal>>,
     canvas: Rc<gtk::DrawingArea>,
+    window: gtk::Window,
+    flashing: Cell<bool>,
+    animating: Cell<bool>,
 }
 
 pub enum CommandError {
@@ -27,8 +30,16 @@ impl CommandApplicator {
 
     pub fn new(rx: Receiver<Command>,
                terminal: Rc<RefCell<Terminal>>,
-               canvas: Rc<gtk::DrawingArea>) -> CommandApplicator {
-        CommandApplicator { rx: rx, terminal: terminal, canvas: canvas }
+               canvas: Rc<gtk::DrawingArea>,
+               window: gtk::Window) -> CommandApplicator {
+        CommandApplicator {
+            rx: rx,
+            terminal: terminal,
+            canvas: canvas,
+            window: window,
+            flashing: Cell::new(false),
+            animating: Cell::new(false),
+        }
     }
 
     pub fn apply(&self) -> Result<()> {
@@ -49,6 +60,19 @@ impl CommandApplicator {
                 Err(Empty)          => break,
             }
         }
+        // Keep redrawing while the visual bell flashes, and once more when it stops.
+        let flashing = terminal.is_flashing();
+        if flashing || self.flashing.get() { redraw = true; }
+        self.flashing.set(flashing);
+        // Animated images are redrawn on every tick until they finish playing.
+        let animating = terminal.is_animating();
+        if animating || self.animating.get() { redraw = true; }
+        self.animating.set(animating);
+        // A bell only needs the user's attention if they are looking at another window.
+        if terminal.is_urgent() {
+            if self.window.is_active() { terminal.clear_urgent(); }
+            else { self.window.set_urgency_hint(true); }
+        }
         if redraw { self.canvas.queue_draw(); }
         Ok(())
     }
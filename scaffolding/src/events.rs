@@ -0,0 +1,115 @@
+use std::cell::Cell;
+use std::cmp;
+use std::rc::Rc;
+use std::sync::mpsc::Sender;
+
+use gdk::{self, Display};
+use gtk::{self, Clipboard, WidgetExt, WindowExt};
+
+use notty::Command;
+use notty::terminal::{Selection, TerminalEvents, WindowOp};
+
+pub struct Events {
+    window: gtk::Window,
+    cell_size: Rc<Cell<(u32, u32)>>,
+    tx: Sender<Command>,
+}
+
+impl Events {
+
+    /// The cell size is shared with the renderer, which sets it whenever the font is measured,
+    /// so that windows can be resized to a number of cells. Answers to clipboard queries are
+    /// sent to the terminal as commands through the sender.
+    pub fn new(window: gtk::Window, cell_size: Rc<Cell<(u32, u32)>>, tx: Sender<Command>)
+            -> Events {
+        Events { window: window, cell_size: cell_size, tx: tx }
+    }
+
+    fn state(&self) -> gdk::WindowState {
+        self.window.get_window().map_or(gdk::WindowState::empty(), |window| window.get_state())
+    }
+
+}
+
+impl TerminalEvents for Events {
+
+    fn bell(&mut self) {
+        gdk::beep();
+    }
+
+    fn title_changed(&mut self, title: &str) {
+        self.window.set_title(title);
+    }
+
+    fn store_clipboard(&mut self, selection: Selection, data: &str) {
+        if let Some(clipboard) = clipboard(selection) {
+            clipboard.set_text(data);
+        }
+    }
+
+    // Waiting for the text would run the GTK main loop while the terminal is borrowed, so it
+    // is requested and sent back to the terminal as a command when it arrives.
+    fn load_clipboard(&mut self, selection: Selection) {
+        if let Some(clipboard) = clipboard(selection) {
+            let tx = self.tx.clone();
+            clipboard.request_text(move |_, text| {
+                let _ = tx.send(Command::report_clipboard(selection, text.to_owned()));
+            });
+        }
+    }
+
+    fn window_op(&mut self, op: WindowOp) {
+        let (width, height) = self.window.get_size();
+        match op {
+            WindowOp::Iconify(true)     => self.window.iconify(),
+            WindowOp::Iconify(false)    => self.window.deiconify(),
+            WindowOp::Move(x, y)        => self.window.move_(x as i32, y as i32),
+            WindowOp::ResizePixels(w, h)    => {
+                let w = w.map_or(width, |w| w as i32);
+                let h = h.map_or(height, |h| h as i32);
+                self.window.resize(w, h);
+            }
+            WindowOp::ResizeCells(cols, rows)   => {
+                let (cell_w, cell_h) = self.cell_size.get();
+                if cell_w == 0 { return }
+                let w = cols.map_or(width, |cols| cols.saturating_mul(cell_w) as i32);
+                let h = rows.map_or(height, |rows| rows.saturating_mul(cell_h) as i32);
+                self.window.resize(w, h);
+            }
+            WindowOp::Raise             => self.window.present(),
+            WindowOp::Lower             => {
+                if let Some(window) = self.window.get_window() { window.lower(); }
+            }
+            WindowOp::Maximize { horizontal: false, vertical: false }   => {
+                self.window.unmaximize();
+            }
+            WindowOp::Maximize { .. }   => self.window.maximize(),
+            WindowOp::Fullscreen(None) if self.state().contains(gdk::WINDOW_STATE_FULLSCREEN) => {
+                self.window.unfullscreen();
+            }
+            WindowOp::Fullscreen(Some(false))   => self.window.unfullscreen(),
+            WindowOp::Fullscreen(_)     => self.window.fullscreen(),
+        }
+    }
+
+    fn window_position(&mut self) -> Option<(u32, u32)> {
+        let (x, y) = self.window.get_position();
+        Some((cmp::max(x, 0) as u32, cmp::max(y, 0) as u32))
+    }
+
+    fn is_iconified(&mut self) -> bool {
+        self.state().contains(gdk::WINDOW_STATE_ICONIFIED)
+    }
+
+    fn child_exited(&mut self, _: Option<i32>) {
+        gtk::main_quit();
+    }
+
+}
+
+fn clipboard(selection: Selection) -> Option<Clipboard> {
+    match selection {
+        Selection::Clipboard    => Display::get_default().as_ref().and_then(Clipboard::get_default),
+        _                       => None,
+    }
+}
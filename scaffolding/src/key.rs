@@ -14,8 +14,10 @@
 //  You should have received a copy of the GNU Affero General Public License
 //  along with this program.  If not, see <http://www.gnu.org/licenses/>.
 
+use std::collections::HashSet;
+
 use gdk::{EventKey, EventType, CONTROL_MASK};
-use notty::datatypes::{Direction, Key};
+use notty::datatypes::{Direction, Key, KeypadKey, MediaKey};
 use notty::Command;
 
 pub enum KeyEvent {
@@ -27,11 +29,18 @@ pub enum KeyEvent {
 }
 
 impl KeyEvent {
-    pub fn new(event: &EventKey) -> KeyEvent {
+    /// Translate a GDK key event. `held` is the set of hardware keycodes currently held down;
+    /// a press of a key which is already held is the keyboard's auto-repeat.
+    pub fn new(event: &EventKey, held: &mut HashSet<u16>) -> KeyEvent {
+        let keycode = event.get_hardware_keycode();
         let ctor: fn(Key) -> Command = match event.get_event_type() {
-            EventType::KeyPress     => Command::key_press,
-            EventType::KeyRelease   => Command::key_release,
-            _                       => unreachable!()
+            EventType::KeyPress if !held.insert(keycode)    => Command::key_repeat,
+            EventType::KeyPress                             => Command::key_press,
+            EventType::KeyRelease                           => {
+                held.remove(&keycode);
+                Command::key_release
+            }
+            _                                               => unreachable!()
         };
         match (event.get_keyval(), shift_ctrl(event)) {
             // Shift+Ctrl+C
@@ -61,17 +70,57 @@ impl KeyEvent {
             (0xff55, _)                 => KeyEvent::Command(ctor(Key::PageUp)),
             (0xff56, _)                 => KeyEvent::Command(ctor(Key::PageDown)),
             (0xff57, _)                 => KeyEvent::Command(ctor(Key::End)),
+            (0xff63, _)                 => KeyEvent::Command(ctor(Key::Insert)),
+            (0xff7f, _)                 => KeyEvent::Command(ctor(Key::NumLock)),
+            (0xff80, _)                 => KeyEvent::Command(ctor(Key::Char(' '))),
+            (0xff89, _)                 => KeyEvent::Command(ctor(Key::Char('\x09'))),
+            (0xff8d, _)                 => KeyEvent::Command(ctor(Key::Keypad(KeypadKey::Enter))),
+            (k @ 0xff91...0xff94, _)    => KeyEvent::Command(ctor(Key::Function((k - 0xff90) as u8))),
+            (0xff95, _)                 => KeyEvent::Command(ctor(Key::Home)),
+            (0xff96, _)                 => KeyEvent::Command(ctor(Key::LeftArrow)),
+            (0xff97, _)                 => KeyEvent::Command(ctor(Key::UpArrow)),
+            (0xff98, _)                 => KeyEvent::Command(ctor(Key::RightArrow)),
+            (0xff99, _)                 => KeyEvent::Command(ctor(Key::DownArrow)),
+            (0xff9a, _)                 => KeyEvent::Command(ctor(Key::PageUp)),
+            (0xff9b, _)                 => KeyEvent::Command(ctor(Key::PageDown)),
+            (0xff9c, _)                 => KeyEvent::Command(ctor(Key::End)),
+            (0xff9d, _)                 => KeyEvent::Command(ctor(Key::Keypad(KeypadKey::Begin))),
+            (0xff9e, _)                 => KeyEvent::Command(ctor(Key::Insert)),
+            (0xff9f, _)                 => KeyEvent::Command(ctor(Key::Delete)),
+            (0xffaa, _)                 => KeyEvent::Command(ctor(Key::Keypad(KeypadKey::Multiply))),
+            (0xffab, _)                 => KeyEvent::Command(ctor(Key::Keypad(KeypadKey::Add))),
+            (0xffac, _)                 => KeyEvent::Command(ctor(Key::Keypad(KeypadKey::Separator))),
+            (0xffad, _)                 => KeyEvent::Command(ctor(Key::Keypad(KeypadKey::Subtract))),
+            (0xffae, _)                 => KeyEvent::Command(ctor(Key::Keypad(KeypadKey::Decimal))),
+            (0xffaf, _)                 => KeyEvent::Command(ctor(Key::Keypad(KeypadKey::Divide))),
+            (k @ 0xffb0...0xffb9, _)    => {
+                KeyEvent::Command(ctor(Key::Keypad(KeypadKey::Digit((k - 0xffb0) as u8))))
+            }
+            (0xffbd, _)                 => KeyEvent::Command(ctor(Key::Keypad(KeypadKey::Equal))),
+            (k @ 0xffbe...0xffd5, _)    => KeyEvent::Command(ctor(Key::Function((k - 0xffbd) as u8))),
             (0xffe1, _)                 => KeyEvent::Command(ctor(Key::ShiftLeft)),
             (0xffe2, _)                 => KeyEvent::Command(ctor(Key::ShiftRight)),
             (0xffe3, _)                 => KeyEvent::Command(ctor(Key::CtrlLeft)),
             (0xffe4, _)                 => KeyEvent::Command(ctor(Key::CtrlRight)),
             (0xffe5, _)                 => KeyEvent::Command(ctor(Key::CapsLock)),
-            (0xffe7, _) | (0xffeb, _)   => KeyEvent::Command(ctor(Key::Meta)),
-            (0xffe8, _) | (0xff67, _)   => KeyEvent::Command(ctor(Key::Menu)),
+            (0xffe7, _) | (0xffe8, _)   => KeyEvent::Command(ctor(Key::Meta)),
+            (0xffeb, _)                 => KeyEvent::Command(ctor(Key::SuperLeft)),
+            (0xffec, _)                 => KeyEvent::Command(ctor(Key::SuperRight)),
+            (0xffed, _) | (0xffee, _)   => KeyEvent::Command(ctor(Key::Hyper)),
+            (0xff67, _)                 => KeyEvent::Command(ctor(Key::Menu)),
             (0xffe9, _)                 => KeyEvent::Command(ctor(Key::AltLeft)),
             (0xffea, _)                 => KeyEvent::Command(ctor(Key::AltGr)),
             (0xffff, _)                 => KeyEvent::Command(ctor(Key::Delete)),
-            (x, _)                      => { panic!("Key press: {:x}", x) }
+            (0xfe03, _)                 => KeyEvent::Command(ctor(Key::AltGr)),
+            (0x1008ff11, _)             => KeyEvent::Command(ctor(Key::Media(MediaKey::VolumeDown))),
+            (0x1008ff12, _)             => KeyEvent::Command(ctor(Key::Media(MediaKey::Mute))),
+            (0x1008ff13, _)             => KeyEvent::Command(ctor(Key::Media(MediaKey::VolumeUp))),
+            (0x1008ff14, _)             => KeyEvent::Command(ctor(Key::Media(MediaKey::Play))),
+            (0x1008ff15, _)             => KeyEvent::Command(ctor(Key::Media(MediaKey::Stop))),
+            (0x1008ff16, _)             => KeyEvent::Command(ctor(Key::Media(MediaKey::Previous))),
+            (0x1008ff17, _)             => KeyEvent::Command(ctor(Key::Media(MediaKey::Next))),
+            (0x1008ff31, _)             => KeyEvent::Command(ctor(Key::Media(MediaKey::Pause))),
+            _                           => KeyEvent::Ignore,
         }
     }
 }
@@ -24,7 +24,8 @@ extern crate tty;
 extern crate notty;
 extern crate notty_cairo;
 
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
+use std::collections::HashSet;
 use std::env;
 use std::io::BufReader;
 use std::sync::Arc;
@@ -42,9 +43,11 @@ use notty_cairo::Renderer;
 
 mod cfg;
 mod commands;
+mod events;
 mod key;
 
 use commands::CommandApplicator;
+use events::Events;
 use key::KeyEvent;
 
 static mut X_PIXELS: Option<u32> = None;
@@ -72,6 +75,7 @@ fn main() {
     // Handle program output (tty -> screen) on separate thread.
     let (tx_out, rx) = mpsc::channel();
     let (tx_key_press, tx_key_release) = (tx_out.clone(), tx_out.clone());
+    let tx_clipboard = tx_out.clone();
 
     let pty_open = Arc::new(AtomicBool::new(true));
     let pty_open_checker = pty_open.clone();
@@ -90,24 +94,30 @@ fn main() {
         pty_open.store(false, Ordering::SeqCst);
     });
 
-    // Quit GTK main loop if the (tty -> screen) output handler thread indicates
-    // pty is no longer open.
+    // Set up logical terminal and renderer.
+    let cell_size = Rc::new(Cell::new((0, 0)));
+    let events = Events::new(window.clone(), cell_size.clone(), tx_clipboard);
+    let terminal = Rc::new(RefCell::new(Terminal::new(COLS, ROWS, tty_w, events)));
+    terminal.borrow_mut().set_default_palette(config.cairo.palette());
+    terminal.borrow_mut().set_bell_settings(config.bell);
+    let renderer = RefCell::new(Renderer::new(config.cairo));
+
+    // Tell the terminal the program has exited if the (tty -> screen) output handler thread
+    // indicates pty is no longer open; this quits the GTK main loop.
+    let exit_terminal = terminal.clone();
     glib::timeout_add(50, move || {
         match pty_open_checker.load(Ordering::SeqCst) {
             true => glib::Continue(true),
             false => {
-                gtk::main_quit();
+                exit_terminal.borrow_mut().child_exited(None);
                 glib::Continue(false)
             }
         }
     });
 
-    // Set up logical terminal and renderer.
-    let terminal = Rc::new(RefCell::new(Terminal::new(COLS, ROWS, tty_w)));
-    let renderer = RefCell::new(Renderer::new(config.cairo));
-
     // Process screen logic every 25 milliseconds.
-    let cmd = CommandApplicator::new(rx, terminal.clone(), canvas.clone());
+    let cmd = CommandApplicator::new(rx, terminal.clone(), canvas.clone(), window.clone());
+    let focus_terminal = terminal.clone();
     glib::timeout_add(25, move || {
         match cmd.apply() {
             Ok(_) => glib::Continue(true),
@@ -123,6 +133,7 @@ fn main() {
         let mut terminal = terminal.borrow_mut();
         if let (Some(x_pix), Some(y_pix)) = unsafe {(X_PIXELS.take(), Y_PIXELS.take())} {
             renderer.borrow_mut().reset_dimensions(&canvas, &mut terminal, x_pix, y_pix);
+            cell_size.set(terminal.cell_size());
         }
         renderer.borrow_mut().draw(&terminal, &canvas);
         gtk::Inhibit(false)
@@ -141,8 +152,11 @@ fn main() {
 
     // Connect signal to receive key presses.
     let clipboard = Display::get_default().as_ref().and_then(Clipboard::get_default);
+    let held_keys = Rc::new(RefCell::new(HashSet::new()));
+    let released_keys = held_keys.clone();
+    let unfocused_keys = held_keys.clone();
     window.connect_key_press_event(move |window, event| {
-        match KeyEvent::new(event) {
+        match KeyEvent::new(event, &mut held_keys.borrow_mut()) {
             KeyEvent::Command(cmd)  => tx_key_press.send(cmd).unwrap(),
             KeyEvent::Scroll(_)     => println!("Scrolling is currently unimplemented"),
             KeyEvent::Copy          => println!("Copying text is currently unimplemented"),
@@ -158,13 +172,27 @@ fn main() {
 
     // Connect signal to receive key releases.
     window.connect_key_release_event(move |window, event| {
-        match KeyEvent::new(event) {
+        match KeyEvent::new(event, &mut released_keys.borrow_mut()) {
             KeyEvent::Command(cmd)  => tx_key_release.send(cmd).unwrap(),
             _                       => window.queue_draw(),
         }
         gtk::Inhibit(false)
     });
 
+    // Clear the urgency hint set by the bell when the window is focused.
+    window.connect_focus_in_event(move |window, _| {
+        focus_terminal.borrow_mut().clear_urgent();
+        window.set_urgency_hint(false);
+        gtk::Inhibit(false)
+    });
+
+    // Keys released while the window is not focused send no release event, so forget them all
+    // when focus is lost, or their next press would be taken for a repeat.
+    window.connect_focus_out_event(move |_, _| {
+        unfocused_keys.borrow_mut().clear();
+        gtk::Inhibit(false)
+    });
+
     window.connect_delete_event(|_, _| {
         gtk::main_quit();
         gtk::Inhibit(false)
@@ -120,20 +120,22 @@ impl io::Write for Writer {
 }
 
 impl Tty for Writer {
-    fn set_winsize(&mut self, width: u16, height: u16) -> io::Result<()> {
-        (**self).set_winsize(width, height)
+    fn set_winsize(&mut self, width: u16, height: u16, x_pixels: u16, y_pixels: u16)
+            -> io::Result<()> {
+        (**self).set_winsize(width, height, x_pixels, y_pixels)
     }
 }
 
 pub struct Handle(libc::c_int);
 
 impl Handle {
-    pub fn set_winsize(&self, width: u16, height: u16) -> io::Result<()> {
+    pub fn set_winsize(&self, width: u16, height: u16, x_pixels: u16, y_pixels: u16)
+            -> io::Result<()> {
         let winsize = Winsize {
             ws_row: height as libc::c_ushort,
             ws_col: width as libc::c_ushort,
-            ws_xpixel: 0,
-            ws_ypixel: 0
+            ws_xpixel: x_pixels as libc::c_ushort,
+            ws_ypixel: y_pixels as libc::c_ushort,
         };
         match unsafe {
             libc::ioctl(**self,
@@ -0,0 +1,129 @@
+//  notty is a new kind of terminal emulator.
+//  Copyright (C) 2015 without boats
+//  
+//  This program is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU Affero General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//  
+//  This program is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU Affero General Public License for more details.
+//  
+//  You should have received a copy of the GNU Affero General Public License
+//  along with this program.  If not, see <http://www.gnu.org/licenses/>.
+use std::borrow::Cow;
+use std::cell::RefCell;
+
+use command::prelude::*;
+use datatypes::{Key, KeyAction};
+use terminal::{DeleteSpec, PlaceParams, StoredImage};
+
+/// The ids a kitty graphics command was sent with, which are echoed back in the answer to it,
+/// and how quiet the program asked the answer to be.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct GraphicsReply {
+    pub id: u32,
+    pub number: u32,
+    pub placement: u32,
+    /// 1 suppresses answers to commands which succeed, 2 suppresses all answers.
+    pub quiet: u32,
+}
+
+impl GraphicsReply {
+    fn send(&self, terminal: &mut Terminal, result: Result<(), &str>) -> io::Result<()> {
+        // Commands which name no image are never answered.
+        if self.id == 0 && self.number == 0 { return Ok(()) }
+        let message = match result {
+            Ok(()) if self.quiet == 0   => "OK",
+            Err(err) if self.quiet < 2  => err,
+            _                           => return Ok(()),
+        };
+        let mut cmd = format!("\x1b_Gi={}", self.id);
+        if self.number != 0 { cmd.push_str(&format!(",I={}", self.number)); }
+        if self.placement != 0 { cmd.push_str(&format!(",p={}", self.placement)); }
+        cmd.push_str(&format!(";{}\x1b\\", message));
+        terminal.send_input(Key::Cmd(Cow::Owned(cmd)), KeyAction::Press)
+    }
+}
+
+/// Store an image, and place it at the cursor if placement parameters are given.
+pub struct TransmitImage {
+    image: RefCell<Option<StoredImage>>,
+    place: Option<PlaceParams>,
+    reply: GraphicsReply,
+}
+
+impl TransmitImage {
+    pub fn new(image: StoredImage, place: Option<PlaceParams>, reply: GraphicsReply)
+            -> TransmitImage {
+        TransmitImage { image: RefCell::new(Some(image)), place: place, reply: reply }
+    }
+}
+
+impl Command for TransmitImage {
+    fn apply(&self, terminal: &mut Terminal) -> io::Result<()> {
+        if let Some(image) = self.image.borrow_mut().take() {
+            let id = terminal.transmit_image(self.reply.id, self.reply.number, image);
+            let result = match self.place {
+                Some(params)    => terminal.place_image(id, self.reply.placement, params),
+                None            => Ok(()),
+            };
+            try!(GraphicsReply { id: id, ..self.reply }.send(terminal, result));
+        }
+        Ok(())
+    }
+    fn repr(&self) -> String {
+        match self.place {
+            Some(_) => String::from("TRANSMIT AND PLACE IMAGE"),
+            None    => String::from("TRANSMIT IMAGE"),
+        }
+    }
+}
+
+/// Place an image which has already been transmitted at the cursor.
+pub struct PlaceImage(pub PlaceParams, pub GraphicsReply);
+
+impl Command for PlaceImage {
+    fn apply(&self, terminal: &mut Terminal) -> io::Result<()> {
+        let id = match self.1.id {
+            0   => terminal.image_by_number(self.1.number).unwrap_or(0),
+            id  => id,
+        };
+        let result = terminal.place_image(id, self.1.placement, self.0);
+        GraphicsReply { id: id, ..self.1 }.send(terminal, result)
+    }
+    fn repr(&self) -> String {
+        String::from("PLACE IMAGE")
+    }
+}
+
+/// Delete placed images, and remove them from storage as well if the flag is set.
+pub struct DeleteImages(pub DeleteSpec, pub bool);
+
+impl Command for DeleteImages {
+    fn apply(&self, terminal: &mut Terminal) -> io::Result<()> {
+        terminal.delete_images(self.0, self.1);
+        Ok(())
+    }
+    fn repr(&self) -> String {
+        String::from("DELETE IMAGES")
+    }
+}
+
+/// Answer a kitty graphics command without doing anything else, such as a query whether an
+/// image could be loaded, or a command which could not be parsed.
+pub struct ReportGraphics(pub GraphicsReply, pub Result<(), &'static str>);
+
+impl Command for ReportGraphics {
+    fn apply(&self, terminal: &mut Terminal) -> io::Result<()> {
+        self.0.send(terminal, self.1)
+    }
+    fn repr(&self) -> String {
+        match self.1 {
+            Ok(())      => String::from("REPORT GRAPHICS OK"),
+            Err(err)    => format!("REPORT GRAPHICS {}", err),
+        }
+    }
+}
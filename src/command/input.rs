@@ -14,30 +14,65 @@
 //  You should have received a copy of the GNU Affero General Public License
 //  along with this program.  If not, see <http://www.gnu.org/licenses/>.
 use command::prelude::*;
-use datatypes::Key;
+use datatypes::{Key, KeyAction};
+use terminal::Preedit;
 
 pub struct KeyPress(pub Key);
 
 impl Command for KeyPress {
     fn apply(&self, terminal: &mut Terminal) -> io::Result<()> {
-        terminal.send_input(self.0.clone(), true)
+        terminal.send_input(self.0.clone(), KeyAction::Press)
     }
     fn repr(&self) -> String {
         String::from("KEY PRESS")
     }
 }
 
+pub struct KeyRepeat(pub Key);
+
+impl Command for KeyRepeat {
+    fn apply(&self, terminal: &mut Terminal) -> io::Result<()> {
+        terminal.send_input(self.0.clone(), KeyAction::Repeat)
+    }
+    fn repr(&self) -> String {
+        String::from("KEY REPEAT")
+    }
+}
+
 pub struct KeyRelease(pub Key);
 
 impl Command for KeyRelease {
     fn apply(&self, terminal: &mut Terminal) -> io::Result<()> {
-        terminal.send_input(self.0.clone(), false)
+        terminal.send_input(self.0.clone(), KeyAction::Release)
     }
     fn repr(&self) -> String {
         String::from("KEY RELEASE")
     }
 }
 
+pub struct SetPreedit(pub String, pub usize);
+
+impl Command for SetPreedit {
+    fn apply(&self, terminal: &mut Terminal) -> io::Result<()> {
+        terminal.set_preedit(Preedit::new(self.0.clone(), self.1));
+        Ok(())
+    }
+    fn repr(&self) -> String {
+        String::from("SET PREEDIT")
+    }
+}
+
+pub struct CommitText(pub String);
+
+impl Command for CommitText {
+    fn apply(&self, terminal: &mut Terminal) -> io::Result<()> {
+        terminal.commit_text(&self.0)
+    }
+    fn repr(&self) -> String {
+        String::from("COMMIT TEXT")
+    }
+}
+
 pub struct Paste(pub String);
 
 impl Command for Paste {
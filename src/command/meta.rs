@@ -19,6 +19,7 @@ use notty_encoding::cmds::SetInputMode;
 
 use command::prelude::*;
 use datatypes::InputSettings;
+use terminal::{Mark, Notification, Selection, WorkingDir};
 
 pub struct SetTitle(pub RefCell<Option<String>>);
 
@@ -34,6 +35,20 @@ impl Command for SetTitle {
     }
 }
 
+pub struct SetIcon(pub RefCell<Option<String>>);
+
+impl Command for SetIcon {
+    fn apply(&self, terminal: &mut Terminal) -> io::Result<()> {
+        if let Some(icon) = self.0.borrow_mut().take() {
+            terminal.set_icon(icon);
+        }
+        Ok(())
+    }
+    fn repr(&self) -> String {
+        String::from("SET ICON")
+    }
+}
+
 impl Command for SetInputMode {
     fn apply(&self, terminal: &mut Terminal) -> io::Result<()> {
         terminal.set_input_mode(self.0);
@@ -51,6 +66,162 @@ impl Command for SetInputMode {
     }
 }
 
+pub struct SetKeypadMode(pub bool);
+
+impl Command for SetKeypadMode {
+    fn apply(&self, terminal: &mut Terminal) -> io::Result<()> {
+        terminal.set_keypad_mode(self.0);
+        Ok(())
+    }
+    fn repr(&self) -> String {
+        match self.0 {
+            true    => String::from("SET KEYPAD MODE APPLICATION"),
+            false   => String::from("SET KEYPAD MODE NUMERIC"),
+        }
+    }
+}
+
+pub struct SetAltSendsEscape(pub bool);
+
+impl Command for SetAltSendsEscape {
+    fn apply(&self, terminal: &mut Terminal) -> io::Result<()> {
+        terminal.set_alt_sends_esc(self.0);
+        Ok(())
+    }
+    fn repr(&self) -> String {
+        match self.0 {
+            true    => String::from("SET ALT SENDS ESCAPE"),
+            false   => String::from("SET ALT SETS EIGHTH BIT"),
+        }
+    }
+}
+
+pub struct SetModifyOtherKeys(pub u8);
+
+impl Command for SetModifyOtherKeys {
+    fn apply(&self, terminal: &mut Terminal) -> io::Result<()> {
+        terminal.set_modify_other_keys(self.0);
+        Ok(())
+    }
+    fn repr(&self) -> String {
+        format!("SET MODIFY OTHER KEYS {}", self.0)
+    }
+}
+
+pub struct PushKeyboardFlags(pub u8);
+
+impl Command for PushKeyboardFlags {
+    fn apply(&self, terminal: &mut Terminal) -> io::Result<()> {
+        terminal.push_keyboard_flags(self.0);
+        Ok(())
+    }
+    fn repr(&self) -> String {
+        format!("PUSH KEYBOARD FLAGS {}", self.0)
+    }
+}
+
+pub struct PopKeyboardFlags(pub u32);
+
+impl Command for PopKeyboardFlags {
+    fn apply(&self, terminal: &mut Terminal) -> io::Result<()> {
+        terminal.pop_keyboard_flags(self.0);
+        Ok(())
+    }
+    fn repr(&self) -> String {
+        format!("POP KEYBOARD FLAGS {}", self.0)
+    }
+}
+
+pub struct SetKeyboardFlags(pub u8, pub u8);
+
+impl Command for SetKeyboardFlags {
+    fn apply(&self, terminal: &mut Terminal) -> io::Result<()> {
+        terminal.set_keyboard_flags(self.0, self.1);
+        Ok(())
+    }
+    fn repr(&self) -> String {
+        format!("SET KEYBOARD FLAGS {} {}", self.0, self.1)
+    }
+}
+
+pub struct SetClipboard(pub Vec<Selection>, pub String);
+
+impl Command for SetClipboard {
+    fn apply(&self, terminal: &mut Terminal) -> io::Result<()> {
+        terminal.store_clipboard(&self.0, &self.1);
+        Ok(())
+    }
+    fn repr(&self) -> String {
+        String::from("SET CLIPBOARD")
+    }
+}
+
+pub struct QueryClipboard(pub Selection);
+
+impl Command for QueryClipboard {
+    fn apply(&self, terminal: &mut Terminal) -> io::Result<()> {
+        terminal.load_clipboard(self.0);
+        Ok(())
+    }
+    fn repr(&self) -> String {
+        String::from("QUERY CLIPBOARD")
+    }
+}
+
+/// The contents of a selection, given by the embedding application in answer to a query.
+pub struct ReportClipboard(pub Selection, pub String);
+
+impl Command for ReportClipboard {
+    fn apply(&self, terminal: &mut Terminal) -> io::Result<()> {
+        terminal.report_clipboard(self.0, &self.1)
+    }
+    fn repr(&self) -> String {
+        String::from("REPORT CLIPBOARD")
+    }
+}
+
+pub struct AddMark(pub Mark);
+
+impl Command for AddMark {
+    fn apply(&self, terminal: &mut Terminal) -> io::Result<()> {
+        terminal.add_mark(self.0);
+        Ok(())
+    }
+    fn repr(&self) -> String {
+        match self.0 {
+            Mark::PromptStart           => String::from("MARK PROMPT START"),
+            Mark::CommandStart          => String::from("MARK COMMAND START"),
+            Mark::OutputStart           => String::from("MARK OUTPUT START"),
+            Mark::CommandEnd(Some(n))   => format!("MARK COMMAND END {}", n),
+            Mark::CommandEnd(None)      => String::from("MARK COMMAND END"),
+        }
+    }
+}
+
+pub struct SetCwd(pub WorkingDir);
+
+impl Command for SetCwd {
+    fn apply(&self, terminal: &mut Terminal) -> io::Result<()> {
+        terminal.set_cwd(self.0.clone());
+        Ok(())
+    }
+    fn repr(&self) -> String {
+        format!("SET CWD {}", self.0.path().display())
+    }
+}
+
+pub struct Notify(pub Notification);
+
+impl Command for Notify {
+    fn apply(&self, terminal: &mut Terminal) -> io::Result<()> {
+        terminal.notify(self.0.clone());
+        Ok(())
+    }
+    fn repr(&self) -> String {
+        String::from("NOTIFY")
+    }
+}
+
 #[derive(Default, Copy, Clone)]
 pub struct Bell;
 
@@ -19,14 +19,17 @@ use terminal::Terminal;
 use Command;
 
 mod erase;
+mod graphics;
 mod input;
 mod meta;
 mod movement;
+mod palette;
 mod panel;
 mod put;
 mod respond;
 mod style;
 mod tooltip;
+mod window;
 
 pub use notty_encoding::cmds::{
     Erase, RemoveChars, RemoveRows, InsertBlank, InsertRows,
@@ -39,11 +42,20 @@ pub use notty_encoding::cmds::{
     SetStyleInArea, DefaultStyleInArea,
 };
 
-pub use self::input::{KeyPress, KeyRelease, Paste};
-pub use self::meta::{SetTitle, Bell};
-pub use self::put::{Put, PutAt};
-pub use self::respond::{StaticResponse, ReportPosition};
+pub use self::graphics::{GraphicsReply, TransmitImage, PlaceImage, DeleteImages};
+pub use self::graphics::ReportGraphics;
+pub use self::input::{KeyPress, KeyRepeat, KeyRelease, SetPreedit, CommitText, Paste};
+pub use self::meta::{SetTitle, SetIcon, SetKeypadMode, SetAltSendsEscape, SetModifyOtherKeys};
+pub use self::meta::{PushKeyboardFlags, PopKeyboardFlags, SetKeyboardFlags};
+pub use self::meta::{SetClipboard, QueryClipboard, ReportClipboard};
+pub use self::meta::{AddMark, SetCwd, Notify, Bell};
+pub use self::palette::{SetPaletteColor, ResetPaletteColor, SetDynamicColor, ResetDynamicColor};
+pub use self::put::{Put, PutAt, PutPixels, PutInline, InlineSize, UpdateImage};
+pub use self::style::SetLink;
+pub use self::respond::{StaticResponse, ReportPosition, ReportKeyboardFlags, ReportNottyError};
+pub use self::respond::{ReportPaletteColor, ReportDynamicColor, ReportWindow};
 pub use self::tooltip::{AddToolTip, RemoveToolTip, AddDropDown};
+pub use self::window::{WindowOperation, PushTitle, PopTitle};
 
 mod prelude {
     pub use std::io;
@@ -0,0 +1,68 @@
+//  notty is a new kind of terminal emulator.
+//  Copyright (C) 2015 without boats
+//  
+//  This program is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU Affero General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//  
+//  This program is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU Affero General Public License for more details.
+//  
+//  You should have received a copy of the GNU Affero General Public License
+//  along with this program.  If not, see <http://www.gnu.org/licenses/>.
+use command::prelude::*;
+use terminal::{DynamicColor, Rgb};
+
+pub struct SetPaletteColor(pub u8, pub Rgb);
+
+impl Command for SetPaletteColor {
+    fn apply(&self, terminal: &mut Terminal) -> io::Result<()> {
+        terminal.set_palette_color(self.0, self.1);
+        Ok(())
+    }
+    fn repr(&self) -> String {
+        format!("SET PALETTE COLOR {}", self.0)
+    }
+}
+
+pub struct ResetPaletteColor(pub Option<u8>);
+
+impl Command for ResetPaletteColor {
+    fn apply(&self, terminal: &mut Terminal) -> io::Result<()> {
+        terminal.reset_palette_color(self.0);
+        Ok(())
+    }
+    fn repr(&self) -> String {
+        match self.0 {
+            Some(n) => format!("RESET PALETTE COLOR {}", n),
+            None    => String::from("RESET PALETTE"),
+        }
+    }
+}
+
+pub struct SetDynamicColor(pub DynamicColor, pub Rgb);
+
+impl Command for SetDynamicColor {
+    fn apply(&self, terminal: &mut Terminal) -> io::Result<()> {
+        terminal.set_dynamic_color(self.0, self.1);
+        Ok(())
+    }
+    fn repr(&self) -> String {
+        format!("SET DYNAMIC COLOR {}", self.0.osc())
+    }
+}
+
+pub struct ResetDynamicColor(pub DynamicColor);
+
+impl Command for ResetDynamicColor {
+    fn apply(&self, terminal: &mut Terminal) -> io::Result<()> {
+        terminal.reset_dynamic_color(self.0);
+        Ok(())
+    }
+    fn repr(&self) -> String {
+        format!("RESET DYNAMIC COLOR {}", self.0.osc())
+    }
+}
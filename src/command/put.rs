@@ -16,10 +16,12 @@
 use mime::Mime;
 
 use std::cell::RefCell;
+use std::cmp;
 
 use command::prelude::*;
-use datatypes::{CellData, Coords, MediaPosition};
-use datatypes::Movement::Position;
+use datatypes::{CellData, Coords, MediaAlignment, MediaPosition};
+use datatypes::Movement::{NextLine, Position};
+use terminal::DEFAULT_CELL_SIZE;
 
 pub struct Put(RefCell<Option<CellData>>);
 
@@ -60,6 +62,145 @@ impl Command for Put {
 
 }
 
+/// An image sized in pixels rather than cells, which covers as many cells as it needs at the
+/// current size of a cell.
+pub struct PutPixels(RefCell<Option<Vec<u8>>>, Mime, u32, u32);
+
+impl PutPixels {
+    pub fn new(data: Vec<u8>, mime: Mime, width: u32, height: u32) -> PutPixels {
+        PutPixels(RefCell::new(Some(data)), mime, width, height)
+    }
+}
+
+impl Command for PutPixels {
+
+    fn apply(&self, terminal: &mut Terminal) -> io::Result<()> {
+        if let Some(data) = self.0.borrow_mut().take() {
+            // If the size of a cell is unknown, the image is scaled to fit a guess at it.
+            let ((cell_w, cell_h), pos) = match terminal.cell_size() {
+                (0, _) | (_, 0) => (DEFAULT_CELL_SIZE, MediaPosition::Fit),
+                size            => {
+                    (size, MediaPosition::Display(MediaAlignment::LeftTop, MediaAlignment::LeftTop))
+                }
+            };
+            let origin = terminal.cursor_position();
+            let height = cells(self.3, cell_h);
+            terminal.write(CellData::Image {
+                pos: pos,
+                width: cells(self.2, cell_w),
+                height: height,
+                data: data,
+                mime: self.1.clone(),
+            });
+            // As with sixel scrolling in xterm, text continues on the line below the image.
+            terminal.move_cursor(Position(origin));
+            terminal.move_cursor(NextLine(height));
+        }
+        Ok(())
+    }
+
+    fn repr(&self) -> String {
+        format!("PUT PIXELS {}x{}", self.2, self.3)
+    }
+
+}
+
+/// A dimension of an inline image sent with the iTerm2 protocol.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum InlineSize {
+    /// The size of the image itself, or in proportion to the other dimension.
+    Auto,
+    Cells(u32),
+    Pixels(u32),
+    /// A percentage of the size of the screen.
+    Percent(u32),
+}
+
+impl InlineSize {
+    fn pixels(&self, cell: u32, screen: u32) -> Option<u32> {
+        match *self {
+            InlineSize::Auto        => None,
+            InlineSize::Cells(n)    => Some(n.saturating_mul(cell)),
+            InlineSize::Pixels(n)   => Some(n),
+            InlineSize::Percent(n)  => Some(scale(screen, n, 100)),
+        }
+    }
+}
+
+/// An image sent with the iTerm2 protocol, whose size in cells depends on the size of the
+/// image in pixels, the size of a cell and the size of the screen.
+pub struct PutInline {
+    data: RefCell<Option<Vec<u8>>>,
+    mime: Mime,
+    size: (u32, u32),
+    width: InlineSize,
+    height: InlineSize,
+    preserve_aspect: bool,
+}
+
+impl PutInline {
+    pub fn new(data: Vec<u8>, mime: Mime, size: (u32, u32), width: InlineSize, height: InlineSize,
+               preserve_aspect: bool) -> PutInline {
+        PutInline {
+            data: RefCell::new(Some(data)),
+            mime: mime,
+            size: size,
+            width: width,
+            height: height,
+            preserve_aspect: preserve_aspect,
+        }
+    }
+}
+
+impl Command for PutInline {
+
+    fn apply(&self, terminal: &mut Terminal) -> io::Result<()> {
+        if let Some(data) = self.data.borrow_mut().take() {
+            let (cell_w, cell_h) = match terminal.cell_size() {
+                (0, _) | (_, 0) => DEFAULT_CELL_SIZE,
+                size            => size,
+            };
+            let area = terminal.area();
+            let screen_w = area.width().saturating_mul(cell_w);
+            let screen_h = area.height().saturating_mul(cell_h);
+            let (img_w, img_h) = (cmp::max(self.size.0, 1), cmp::max(self.size.1, 1));
+            let width = self.width.pixels(cell_w, screen_w);
+            let height = self.height.pixels(cell_h, screen_h);
+            let (width, height) = match (width, height) {
+                (Some(w), Some(h))                  => (w, h),
+                (Some(w), None)                     => (w, scale(w, img_h, img_w)),
+                (None, Some(h))                     => (scale(h, img_w, img_h), h),
+                // Images wider than the screen are shrunk to fit it.
+                (None, None) if img_w > screen_w    => (screen_w, scale(screen_w, img_h, img_w)),
+                (None, None)                        => (img_w, img_h),
+            };
+            terminal.write(CellData::Image {
+                pos: if self.preserve_aspect { MediaPosition::Fit } else { MediaPosition::Stretch },
+                width: cells(width, cell_w),
+                height: cells(height, cell_h),
+                data: data,
+                mime: self.mime.clone(),
+            });
+        }
+        Ok(())
+    }
+
+    fn repr(&self) -> String {
+        String::from("PUT INLINE")
+    }
+
+}
+
+fn scale(n: u32, numerator: u32, denominator: u32) -> u32 {
+    cmp::min(n as u64 * numerator as u64 / cmp::max(denominator, 1) as u64, !0u32 as u64) as u32
+}
+
+// The number of cells a size in pixels covers, which is at least one.
+fn cells(n: u32, cell: u32) -> u32 {
+    let cell = cmp::max(cell, 1) as u64;
+    cmp::max(((n as u64 + cell - 1) / cell) as u32, 1)
+}
+
 pub struct PutAt(RefCell<Option<CellData>>, Coords);
 
 impl PutAt {
@@ -93,3 +234,65 @@ impl Command for PutAt {
     }
 
 }
+
+/// Replace the data of an image already written to the grid, keeping its size and position.
+pub struct UpdateImage(RefCell<Option<(Vec<u8>, Mime)>>, Coords);
+
+impl UpdateImage {
+    pub fn new(data: Vec<u8>, mime: Mime, coords: Coords) -> UpdateImage {
+        UpdateImage(RefCell::new(Some((data, mime))), coords)
+    }
+}
+
+impl Command for UpdateImage {
+
+    fn apply(&self, terminal: &mut Terminal) -> io::Result<()> {
+        if let Some((data, mime)) = self.0.borrow_mut().take() {
+            terminal.update_image(self.1, data, mime);
+        }
+        Ok(())
+    }
+
+    fn repr(&self) -> String {
+        String::from("UPDATE IMAGE")
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::io::{self, Write};
+
+    use mime::Mime;
+
+    use command::prelude::*;
+    use datatypes::Coords;
+    use datatypes::Movement::Position;
+    use terminal::{NoEvents, Tty, DEFAULT_CELL_SIZE};
+
+    use super::PutPixels;
+
+    struct NullTty;
+
+    impl Write for NullTty {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> { Ok(buf.len()) }
+        fn flush(&mut self) -> io::Result<()> { Ok(()) }
+    }
+
+    impl Tty for NullTty {
+        fn set_winsize(&mut self, _: u16, _: u16, _: u16, _: u16) -> io::Result<()> { Ok(()) }
+    }
+
+    #[test]
+    fn pixels_leave_cursor_below_image() {
+        let mut terminal = Terminal::new(10, 10, NullTty, NoEvents);
+        let (cell_w, cell_h) = DEFAULT_CELL_SIZE;
+        let mime: Mime = "image/png".parse().unwrap();
+        terminal.move_cursor(Position(Coords { x: 2, y: 1 }));
+        let cmd = PutPixels::new(vec![], mime, cell_w * 3, cell_h * 2 - 1);
+        cmd.apply(&mut terminal).unwrap();
+        assert_eq!(terminal.cursor_position(), Coords { x: 0, y: 3 });
+    }
+
+}
@@ -16,13 +16,14 @@
 use std::borrow::Cow;
 
 use command::prelude::*;
-use datatypes::{Key, Coords, Code};
+use datatypes::{Key, KeyAction, Coords, Code};
+use terminal::{DynamicColor, WindowReport, format_color};
 
 pub struct StaticResponse(pub &'static str);
 
 impl Command for StaticResponse {
     fn apply(&self, terminal: &mut Terminal) -> io::Result<()> {
-        terminal.send_input(Key::Cmd(Cow::Borrowed(self.0)), true)
+        terminal.send_input(Key::Cmd(Cow::Borrowed(self.0)), KeyAction::Press)
     }
     fn repr(&self) -> String {
         String::from("RESPOND ") + self.0
@@ -38,9 +39,106 @@ impl Command for ReportPosition {
             Code::ANSI  => Cow::Owned(format!("\x1b[{};{}R", y, x)),
             _           => unimplemented!(),
         };
-        terminal.send_input(Key::Cmd(cmd), true)
+        terminal.send_input(Key::Cmd(cmd), KeyAction::Press)
     }
     fn repr(&self) -> String {
         String::from("REPORT POSITION")
     }
 }
+
+/// An error in a notty command, such as invalid or oversized attachments, reported to the
+/// program with the opcode of the command and an error code.
+pub struct ReportNottyError(pub u32, pub u32);
+
+impl Command for ReportNottyError {
+    fn apply(&self, terminal: &mut Terminal) -> io::Result<()> {
+        let cmd = Cow::Owned(format!("\x1b_[90;{:x};{:x}\u{{9c}}", self.0, self.1));
+        terminal.send_input(Key::Cmd(cmd), KeyAction::Press)
+    }
+    fn repr(&self) -> String {
+        format!("REPORT NOTTY ERROR {:x} {}", self.0, self.1)
+    }
+}
+
+pub struct ReportKeyboardFlags;
+
+impl Command for ReportKeyboardFlags {
+    fn apply(&self, terminal: &mut Terminal) -> io::Result<()> {
+        let flags = terminal.keyboard_flags();
+        terminal.send_input(Key::Cmd(Cow::Owned(format!("\x1b[?{}u", flags))), KeyAction::Press)
+    }
+    fn repr(&self) -> String {
+        String::from("REPORT KEYBOARD FLAGS")
+    }
+}
+
+pub struct ReportPaletteColor(pub u8);
+
+impl Command for ReportPaletteColor {
+    fn apply(&self, terminal: &mut Terminal) -> io::Result<()> {
+        let color = format_color(terminal.palette().color(self.0));
+        let cmd = Cow::Owned(format!("\x1b]4;{};{}\x1b\\", self.0, color));
+        terminal.send_input(Key::Cmd(cmd), KeyAction::Press)
+    }
+    fn repr(&self) -> String {
+        format!("REPORT PALETTE COLOR {}", self.0)
+    }
+}
+
+pub struct ReportDynamicColor(pub DynamicColor);
+
+impl Command for ReportDynamicColor {
+    fn apply(&self, terminal: &mut Terminal) -> io::Result<()> {
+        let color = format_color(terminal.palette().dynamic_color(self.0));
+        let cmd = Cow::Owned(format!("\x1b]{};{}\x1b\\", self.0.osc(), color));
+        terminal.send_input(Key::Cmd(cmd), KeyAction::Press)
+    }
+    fn repr(&self) -> String {
+        format!("REPORT DYNAMIC COLOR {}", self.0.osc())
+    }
+}
+
+pub struct ReportWindow(pub WindowReport);
+
+impl Command for ReportWindow {
+    fn apply(&self, terminal: &mut Terminal) -> io::Result<()> {
+        let area = terminal.area();
+        let ((width, height), (cell_width, cell_height)) = (terminal.pixel_size(),
+                                                            terminal.cell_size());
+        let cmd = match self.0 {
+            // The size in pixels is unknown until the renderer gives it.
+            WindowReport::PixelSize | WindowReport::CellSize if cell_width == 0 => return Ok(()),
+            WindowReport::PixelSize     => format!("\x1b[4;{};{}t", height, width),
+            WindowReport::CellSize      => format!("\x1b[6;{};{}t", cell_height, cell_width),
+            WindowReport::TextSize      => format!("\x1b[8;{};{}t", area.height(), area.width()),
+            WindowReport::ScreenSize    => format!("\x1b[9;{};{}t", area.height(), area.width()),
+            WindowReport::Icon if terminal.window_policy().allows()     =>
+                format!("\x1b]L{}\x1b\\", terminal.icon()),
+            WindowReport::Title if terminal.window_policy().allows()    =>
+                format!("\x1b]l{}\x1b\\", terminal.title()),
+            WindowReport::Iconified     => {
+                String::from(if terminal.is_iconified() { "\x1b[2t" } else { "\x1b[1t" })
+            }
+            WindowReport::Position if terminal.window_policy().allows() => {
+                match terminal.window_position() {
+                    Some((x, y))    => format!("\x1b[3;{};{}t", x, y),
+                    None            => return Ok(()),
+                }
+            }
+            _                           => return Ok(()),
+        };
+        terminal.send_input(Key::Cmd(Cow::Owned(cmd)), KeyAction::Press)
+    }
+    fn repr(&self) -> String {
+        match self.0 {
+            WindowReport::PixelSize     => String::from("REPORT TEXT AREA PIXEL SIZE"),
+            WindowReport::CellSize      => String::from("REPORT CELL PIXEL SIZE"),
+            WindowReport::TextSize      => String::from("REPORT TEXT AREA SIZE"),
+            WindowReport::ScreenSize    => String::from("REPORT SCREEN SIZE"),
+            WindowReport::Icon          => String::from("REPORT ICON"),
+            WindowReport::Title         => String::from("REPORT TITLE"),
+            WindowReport::Iconified     => String::from("REPORT ICONIFIED"),
+            WindowReport::Position      => String::from("REPORT WINDOW POSITION"),
+        }
+    }
+}
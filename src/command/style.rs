@@ -20,6 +20,7 @@ use notty_encoding::cmds::{
 };
 
 use command::prelude::*;
+use terminal::Hyperlink;
 
 impl Command for SetCursorStyle {
     fn apply(&self, terminal: &mut Terminal) -> io::Result<()> {
@@ -80,3 +81,18 @@ impl Command for DefaultStyleInArea {
         String::from("DEFAULT STYLE IN AREA")
     }
 }
+
+pub struct SetLink(pub Option<Hyperlink>);
+
+impl Command for SetLink {
+    fn apply(&self, terminal: &mut Terminal) -> io::Result<()> {
+        terminal.set_link(self.0.clone());
+        Ok(())
+    }
+    fn repr(&self) -> String {
+        match self.0 {
+            Some(ref link)  => format!("SET LINK {}", link.uri),
+            None            => String::from("END LINK"),
+        }
+    }
+}
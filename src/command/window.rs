@@ -0,0 +1,65 @@
+//  notty is a new kind of terminal emulator.
+//  Copyright (C) 2015 without boats
+//  
+//  This program is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU Affero General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//  
+//  This program is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU Affero General Public License for more details.
+//  
+//  You should have received a copy of the GNU Affero General Public License
+//  along with this program.  If not, see <http://www.gnu.org/licenses/>.
+use command::prelude::*;
+use terminal::{TitleKind, WindowOp};
+
+pub struct WindowOperation(pub WindowOp);
+
+impl Command for WindowOperation {
+    fn apply(&self, terminal: &mut Terminal) -> io::Result<()> {
+        terminal.window_op(self.0);
+        Ok(())
+    }
+    fn repr(&self) -> String {
+        match self.0 {
+            WindowOp::Iconify(true)         => String::from("ICONIFY WINDOW"),
+            WindowOp::Iconify(false)        => String::from("DEICONIFY WINDOW"),
+            WindowOp::Move(..)              => String::from("MOVE WINDOW"),
+            WindowOp::ResizePixels(..)      => String::from("RESIZE WINDOW PIXELS"),
+            WindowOp::ResizeCells(..)       => String::from("RESIZE WINDOW CELLS"),
+            WindowOp::Raise                 => String::from("RAISE WINDOW"),
+            WindowOp::Lower                 => String::from("LOWER WINDOW"),
+            WindowOp::Maximize { .. }       => String::from("MAXIMIZE WINDOW"),
+            WindowOp::Fullscreen(_)         => String::from("FULLSCREEN WINDOW"),
+        }
+    }
+}
+
+#[derive(Copy, Clone)]
+pub struct PushTitle(pub TitleKind);
+
+impl Command for PushTitle {
+    fn apply(&self, terminal: &mut Terminal) -> io::Result<()> {
+        terminal.push_title(self.0);
+        Ok(())
+    }
+    fn repr(&self) -> String {
+        String::from("PUSH TITLE")
+    }
+}
+
+#[derive(Copy, Clone)]
+pub struct PopTitle(pub TitleKind);
+
+impl Command for PopTitle {
+    fn apply(&self, terminal: &mut Terminal) -> io::Result<()> {
+        terminal.pop_title(self.0);
+        Ok(())
+    }
+    fn repr(&self) -> String {
+        String::from("POP TITLE")
+    }
+}
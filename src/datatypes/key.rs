@@ -36,6 +36,9 @@ pub enum Key {
     AltLeft,
     AltGr,
     Meta,
+    SuperLeft,
+    SuperRight,
+    Hyper,
     Menu,
     PageUp,
     PageDown,
@@ -46,8 +49,13 @@ pub enum Key {
     CapsLock,
     NumLock,
     ScrollLock,
-    /// A function; the byte value indicates the key number.
+    /// A function key; the byte value indicates the key number, so that F1 is `Function(1)`.
+    /// Keys from F1 through F24 are supported.
     Function(u8),
+    /// A key on the numeric keypad.
+    Keypad(KeypadKey),
+    /// A media control key.
+    Media(MediaKey),
     /// This key is not generated by the keyboard but as a response to some escape code sent
     /// to the output.
     Cmd(Cow<'static, str>),
@@ -55,11 +63,66 @@ pub enum Key {
     MenuSelection(usize),
 }
 
+/// The kind of key event being reported.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum KeyAction {
+    /// The key was pressed.
+    Press,
+    /// The key is being held down and has been repeated by the keyboard's auto-repeat.
+    Repeat,
+    /// The key was released.
+    Release,
+}
+
+impl KeyAction {
+    /// True for presses and repeats, which generate input in encodings that do not report
+    /// releases.
+    pub fn is_press(&self) -> bool {
+        *self != KeyAction::Release
+    }
+}
+
+/// The keys of the numeric keypad. These are distinguished from the equivalent keys on the main
+/// keyboard because the keypad can be put into application mode, in which they generate
+/// different escape codes.
+///
+/// Keypad keys which are not affected by num lock (such as KP_Home when num lock is off) should
+/// be reported as the equivalent key on the main keyboard.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum KeypadKey {
+    /// A digit key; the byte value is the digit from 0 to 9.
+    Digit(u8),
+    Decimal,
+    Separator,
+    Divide,
+    Multiply,
+    Subtract,
+    Add,
+    Equal,
+    Enter,
+    /// The center key of the keypad (KP_5 when num lock is off).
+    Begin,
+}
+
+/// Media control keys found on many keyboards.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum MediaKey {
+    Play,
+    Pause,
+    PlayPause,
+    Stop,
+    Next,
+    Previous,
+    VolumeUp,
+    VolumeDown,
+    Mute,
+}
 
 impl Key {
     pub fn is_modifier(&self) -> bool {
         match *self {
             ShiftLeft | ShiftRight | CtrlLeft | CtrlRight | AltLeft | AltGr | CapsLock => true,
+            Meta | SuperLeft | SuperRight | Hyper   => true,
             _   => false
         }
     }
@@ -72,3 +135,22 @@ impl Key {
         }
     }
 }
+
+impl KeypadKey {
+    /// The character this key generates when the keypad is in numeric mode, if any.
+    pub fn as_char(&self) -> Option<char> {
+        match *self {
+            KeypadKey::Digit(n @ 0...9) => Some((b'0' + n) as char),
+            KeypadKey::Digit(_)         => None,
+            KeypadKey::Decimal          => Some('.'),
+            KeypadKey::Separator        => Some(','),
+            KeypadKey::Divide           => Some('/'),
+            KeypadKey::Multiply         => Some('*'),
+            KeypadKey::Subtract         => Some('-'),
+            KeypadKey::Add              => Some('+'),
+            KeypadKey::Equal            => Some('='),
+            KeypadKey::Enter            => Some('\r'),
+            KeypadKey::Begin            => None,
+        }
+    }
+}
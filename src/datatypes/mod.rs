@@ -28,7 +28,7 @@ mod key;
 use cfg::TAB_STOP;
 
 pub use self::iter::CoordsIter;
-pub use self::key::Key;
+pub use self::key::{Key, KeyAction, KeypadKey, MediaKey};
 
 pub use notty_encoding::args::*;
 
@@ -29,8 +29,10 @@ pub mod terminal;
 
 pub use output::Output;
 
-use command::{KeyPress, KeyRelease, Paste, CommandTrait};
+use command::{KeyPress, KeyRepeat, KeyRelease, SetPreedit, CommitText, Paste, CommandTrait};
+use command::ReportClipboard;
 use datatypes::Key;
+use terminal::Selection;
 
 /// A command to be applied to the terminal.
 ///
@@ -52,6 +54,12 @@ impl Command {
             inner: Box::new(KeyPress(key)) as Box<CommandTrait>,
         }
     }
+    /// Create a command representing a key repeat event, generated while a key is held down.
+    pub fn key_repeat(key: Key) -> Command {
+        Command {
+            inner: Box::new(KeyRepeat(key)) as Box<CommandTrait>,
+        }
+    }
     /// Create a command representing a key release event.
     pub fn key_release(key: Key) -> Command {
         Command {
@@ -59,16 +67,39 @@ impl Command {
         }
     }
 
+    /// Create a command updating the text being composed by an input method. The cursor is an
+    /// offset in chars into the text; an empty string ends the composition.
+    pub fn preedit(text: String, cursor: usize) -> Command {
+        Command {
+            inner: Box::new(SetPreedit(text, cursor)) as Box<CommandTrait>,
+        }
+    }
+
+    /// Create a command committing text composed by an input method.
+    pub fn commit(text: String) -> Command {
+        Command {
+            inner: Box::new(CommitText(text)) as Box<CommandTrait>,
+        }
+    }
+
     /// Create a command representing a paste from a clipboard.
     pub fn paste(data: String) -> Command {
         Command {
             inner: Box::new(Paste(data)) as Box<CommandTrait>,
         }
     }
+
+    /// Create a command sending the contents of a selection to the program which queried it.
+    pub fn report_clipboard(selection: Selection, data: String) -> Command {
+        Command {
+            inner: Box::new(ReportClipboard(selection, data)) as Box<CommandTrait>,
+        }
+    }
 }
 
 pub mod cfg {
-    use std::sync::atomic::{AtomicIsize, AtomicUsize, ATOMIC_ISIZE_INIT, ATOMIC_USIZE_INIT};
+    use std::sync::atomic::{AtomicBool, AtomicIsize, AtomicUsize};
+    use std::sync::atomic::{ATOMIC_BOOL_INIT, ATOMIC_ISIZE_INIT, ATOMIC_USIZE_INIT};
 
     /// The amount of scrollback to save in terminal grids which save their scrollback. None and
     /// 0 mean that an infinite scrollback will be saved. If this is not set, it will be treated as
@@ -78,4 +109,17 @@ pub mod cfg {
     /// The distance between each tab stop. If you do not set this to a non-zero value, it will be
     /// set to 4 when the terminal is initialized.
     pub static TAB_STOP:    AtomicUsize = ATOMIC_USIZE_INIT;
+
+    /// If this is set, holding Alt while typing a character sets the eighth bit of that
+    /// character instead of prefixing it with an escape. Programs can change this behavior with
+    /// the xterm altSendsEscape mode.
+    pub static ALT_SETS_EIGHTH_BIT: AtomicBool = ATOMIC_BOOL_INIT;
+
+    /// The most bytes of attachment data a notty command may be sent with, including all of the
+    /// chunks of a transfer. If this is not set, the limit is 64 MiB.
+    pub static ATTACHMENT_LIMIT: AtomicUsize = ATOMIC_USIZE_INIT;
+
+    /// The most bytes the data of images may use before the oldest images which are not on the
+    /// screen are replaced with placeholders. If this is not set, the budget is 256 MiB.
+    pub static IMAGE_BUDGET: AtomicUsize = ATOMIC_USIZE_INIT;
 }
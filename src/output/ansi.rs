@@ -16,10 +16,18 @@
 use std::cell::RefCell;
 use std::mem;
 
+use base64;
+
 use Command;
 use command::*;
 use datatypes::Code;
 use datatypes::args::*;
+use terminal::{DynamicColor, Hyperlink, Mark, Notification, Selection, WorkingDir, parse_color};
+use terminal::{TitleKind, WindowOp, WindowReport};
+
+use super::bitmap::Bitmap;
+use super::iterm;
+use super::sixel;
 
 #[derive(Debug)]
 pub struct AnsiData {
@@ -116,7 +124,7 @@ impl AnsiData {
                 30      => wrap(NoFeature(self.csi_code(terminal))),
                 41      => wrap(NoFeature(self.csi_code(terminal))),
                 47      => wrap(NoFeature(self.csi_code(terminal))),
-                66      => wrap(NoFeature(self.csi_code(terminal))),
+                66      => wrap(SetKeypadMode(true)),
                 69      => wrap(NoFeature(self.csi_code(terminal))),
                 1000    => wrap(NoFeature(self.csi_code(terminal))),
                 1001    => wrap(NoFeature(self.csi_code(terminal))),
@@ -128,9 +136,9 @@ impl AnsiData {
                 1007    => wrap(NoFeature(self.csi_code(terminal))),
                 1034    => wrap(NoFeature(self.csi_code(terminal))),
                 1035    => wrap(NoFeature(self.csi_code(terminal))),
-                1036    => wrap(NoFeature(self.csi_code(terminal))),
+                1036    => wrap(SetAltSendsEscape(true)),
                 1037    => wrap(NoFeature(self.csi_code(terminal))),
-                1039    => wrap(NoFeature(self.csi_code(terminal))),
+                1039    => wrap(SetAltSendsEscape(true)),
                 1040    => wrap(NoFeature(self.csi_code(terminal))),
                 1041    => wrap(NoFeature(self.csi_code(terminal))),
                 1042    => wrap(NoFeature(self.csi_code(terminal))),
@@ -159,7 +167,7 @@ impl AnsiData {
                 30      => wrap(NoFeature(self.csi_code(terminal))),
                 41      => wrap(NoFeature(self.csi_code(terminal))),
                 47      => wrap(NoFeature(self.csi_code(terminal))),
-                66      => wrap(NoFeature(self.csi_code(terminal))),
+                66      => wrap(SetKeypadMode(false)),
                 69      => wrap(NoFeature(self.csi_code(terminal))),
                 1000    => wrap(NoFeature(self.csi_code(terminal))),
                 1001    => wrap(NoFeature(self.csi_code(terminal))),
@@ -171,9 +179,9 @@ impl AnsiData {
                 1007    => wrap(NoFeature(self.csi_code(terminal))),
                 1034    => wrap(NoFeature(self.csi_code(terminal))),
                 1035    => wrap(NoFeature(self.csi_code(terminal))),
-                1036    => wrap(NoFeature(self.csi_code(terminal))),
+                1036    => wrap(SetAltSendsEscape(false)),
                 1037    => wrap(NoFeature(self.csi_code(terminal))),
-                1039    => wrap(NoFeature(self.csi_code(terminal))),
+                1039    => wrap(SetAltSendsEscape(false)),
                 1040    => wrap(NoFeature(self.csi_code(terminal))),
                 1041    => wrap(NoFeature(self.csi_code(terminal))),
                 1042    => wrap(NoFeature(self.csi_code(terminal))),
@@ -233,13 +241,19 @@ impl AnsiData {
                 })
                 }
             },
-            ('m', '>', '\0')     => wrap(NoFeature(self.csi_code(terminal))),
+            ('m', '>', '\0')     => match self.arg(0, 0) {
+                4   => wrap(SetModifyOtherKeys(self.arg(1, 0) as u8)),
+                _   => wrap(NoFeature(self.csi_code(terminal))),
+            },
             ('n', '\0', '\0')        => match self.arg(0,5) {
                 5   => wrap(StaticResponse("\x1b[0n")),
                 6   => wrap(ReportPosition(Code::ANSI)),
                 _   => None
             },
-            ('n', '>', '\0')     => wrap(NoFeature(self.csi_code(terminal))),
+            ('n', '>', '\0')     => match self.arg(0, 0) {
+                4   => wrap(SetModifyOtherKeys(0)),
+                _   => wrap(NoFeature(self.csi_code(terminal))),
+            },
             ('n', '?', '\0')     => wrap(NoFeature(self.csi_code(terminal))),
             ('p', '\0', '!')     => wrap(NoFeature(self.csi_code(terminal))),
             ('p', '\0', '$')     => wrap(NoFeature(self.csi_code(terminal))),
@@ -286,12 +300,16 @@ impl AnsiData {
             ('r', '?', '\0')     => wrap(NoFeature(self.csi_code(terminal))),
             ('s', '\0', '\0')        => wrap(NoFeature(self.csi_code(terminal))), //left and right margins
             ('s', '?', '\0')     => wrap(NoFeature(self.csi_code(terminal))),
-            ('t', '\0', '\0')        => wrap(NoFeature(self.csi_code(terminal))), //window manipulation
+            ('t', '\0', '\0')        => self.window_op(terminal),
             ('t', '\0', ' ')     => wrap(NoFeature(self.csi_code(terminal))),
             ('t', '\0', '$')     => wrap(NoFeature(self.csi_code(terminal))), // DECRARA
             ('t', '>', '\0')     => wrap(NoFeature(self.csi_code(terminal))),
             ('u', '\0', '\0')        => wrap(NoFeature(self.csi_code(terminal))), // Restore cursor?
             ('u', '\0', ' ')     => wrap(NoFeature(self.csi_code(terminal))),
+            ('u', '>', '\0')     => wrap(PushKeyboardFlags(self.arg(0, 0) as u8)),
+            ('u', '<', '\0')     => wrap(PopKeyboardFlags(self.arg(0, 1))),
+            ('u', '=', '\0')     => wrap(SetKeyboardFlags(self.arg(0, 0) as u8, self.arg(1, 1) as u8)),
+            ('u', '?', '\0')     => wrap(ReportKeyboardFlags),
             ('v', '\0', '$')     => wrap(NoFeature(self.csi_code(terminal))), // Copy an area
             ('w', '\0', '\'')    => wrap(NoFeature(self.csi_code(terminal))),
             ('x', '\0', '\0')        => wrap(NoFeature(self.csi_code(terminal))),
@@ -309,38 +327,199 @@ impl AnsiData {
         }
     }
 
-    #[allow(unused, dead_code)]
-    pub fn dcs(&self, strarg: &str) -> Option<Command> {
-        match (self.private_mode, self.preterminal) {
-            ('|', '\0')   => unimplemented!(),
-            ('$', 'q')    => unimplemented!(),
-            ('+', 'p')    => unimplemented!(),
-            ('+', 'q')    => unimplemented!(),
-            _               => unreachable!(),
+    pub fn dcs(&self, terminal: char, data: &str) -> Option<Command> {
+        match (terminal, self.private_mode, self.preterminal) {
+            ('q', '\0', '\0')  => sixel::decode(&self.args, data).and_then(|bitmap| {
+                wrap(PutPixels::new(bitmap.png(), Bitmap::mime(), bitmap.width, bitmap.height))
+            }),
+            ('|', '\0', '\0')  => wrap(NoFeature(String::from("^[P|"))),
+            ('q', '\0', '$')    => wrap(NoFeature(String::from("^[P$q"))),
+            ('p', '\0', '+')    => wrap(NoFeature(String::from("^[P+p"))),
+            ('q', '\0', '+')    => wrap(NoFeature(String::from("^[P+q"))),
+            _                   => None,
         }
     }
 
     pub fn osc(&mut self) -> Option<Command> {
         match self.arg(0, 0) {
-            0...2   =>  {
+            0   =>  {
+                let title = mem::replace(&mut self.arg_buf, String::new());
+                series(vec![
+                    wrap(SetIcon(RefCell::new(Some(title.clone())))).unwrap(),
+                    wrap(SetTitle(RefCell::new(Some(title)))).unwrap(),
+                ])
+            }
+            1   =>  {
+                let icon = mem::replace(&mut self.arg_buf, String::new());
+                wrap(SetIcon(RefCell::new(Some(icon))))
+            }
+            2   =>  {
                 let title = mem::replace(&mut self.arg_buf, String::new());
                 wrap(SetTitle(RefCell::new(Some(title))))
             }
-            3   => unimplemented!(),
-            4   => unimplemented!(),
-            5   => unimplemented!(),
-            6   => unimplemented!(),
-            46  => unimplemented!(),
-            50  => unimplemented!(),
-            51  => unimplemented!(),
-            52  => unimplemented!(),
-            104 => unimplemented!(),
-            105 => unimplemented!(),
-            106 => unimplemented!(),
+            3   => None,
+            4   => {
+                let buf = mem::replace(&mut self.arg_buf, String::new());
+                let params = buf.split(';').collect::<Vec<_>>();
+                series(params.chunks(2).filter_map(|pair| {
+                    match (pair[0].parse::<u8>(), pair.get(1)) {
+                        (Ok(n), Some(&"?"))     => wrap(ReportPaletteColor(n)),
+                        (Ok(n), Some(spec))     => parse_color(spec).and_then(|color| {
+                            wrap(SetPaletteColor(n, color))
+                        }),
+                        _                       => None,
+                    }
+                }).collect())
+            }
+            5   => None,
+            6   => None,
+            7   => {
+                let buf = mem::replace(&mut self.arg_buf, String::new());
+                WorkingDir::from_url(&buf).and_then(|cwd| wrap(SetCwd(cwd)))
+            }
+            8   => {
+                let buf = mem::replace(&mut self.arg_buf, String::new());
+                let mut parts = buf.splitn(2, ';');
+                let params = parts.next().unwrap_or("");
+                match parts.next() {
+                    Some(uri) if !uri.is_empty()    => {
+                        let id = params.split(':').find(|param| param.starts_with("id="))
+                                                  .map(|param| param[3..].to_owned());
+                        wrap(SetLink(Some(Hyperlink { uri: uri.to_owned(), id: id })))
+                    }
+                    _                               => wrap(SetLink(None)),
+                }
+            }
+            9   => {
+                let body = mem::replace(&mut self.arg_buf, String::new());
+                wrap(Notify(Notification::new(body)))
+            }
+            46  => None,
+            50  => None,
+            51  => None,
+            52  => {
+                let buf = mem::replace(&mut self.arg_buf, String::new());
+                let mut parts = buf.splitn(2, ';');
+                let mut selections = parts.next().unwrap_or("").chars()
+                                          .filter_map(Selection::from_char).collect::<Vec<_>>();
+                if selections.is_empty() { selections.push(Selection::Clipboard) }
+                match parts.next() {
+                    Some("?")   => wrap(QueryClipboard(selections[0])),
+                    Some(data)  => base64::u8de(data.as_bytes()).ok().and_then(|data| {
+                        let data = String::from_utf8_lossy(&data).into_owned();
+                        wrap(SetClipboard(selections, data))
+                    }),
+                    None        => None,
+                }
+            }
+            n @ 10...12 => {
+                // Each parameter sets the next dynamic color, so OSC 10 can set all three.
+                let buf = mem::replace(&mut self.arg_buf, String::new());
+                series(buf.split(';').zip(n..13).filter_map(|(spec, n)| {
+                    let color = DynamicColor::from_osc(n).unwrap();
+                    match spec {
+                        "?"     => wrap(ReportDynamicColor(color)),
+                        spec    => parse_color(spec).and_then(|rgb| {
+                            wrap(SetDynamicColor(color, rgb))
+                        }),
+                    }
+                }).collect())
+            }
+            104 => {
+                let buf = mem::replace(&mut self.arg_buf, String::new());
+                match buf.is_empty() {
+                    true    => wrap(ResetPaletteColor(None)),
+                    false   => series(buf.split(';').filter_map(|n| n.parse::<u8>().ok())
+                                         .filter_map(|n| wrap(ResetPaletteColor(Some(n))))
+                                         .collect()),
+                }
+            }
+            105 => None,
+            106 => None,
+            n @ 110...112   => wrap(ResetDynamicColor(DynamicColor::from_osc(n - 100).unwrap())),
+            133 => {
+                let buf = mem::replace(&mut self.arg_buf, String::new());
+                let mut params = buf.split(';');
+                match params.next() {
+                    Some("A")   => wrap(AddMark(Mark::PromptStart)),
+                    Some("B")   => wrap(AddMark(Mark::CommandStart)),
+                    Some("C")   => wrap(AddMark(Mark::OutputStart)),
+                    Some("D")   => {
+                        let status = params.next().and_then(|n| n.parse().ok());
+                        wrap(AddMark(Mark::CommandEnd(status)))
+                    }
+                    _           => None,
+                }
+            }
+            1337    => {
+                let buf = mem::replace(&mut self.arg_buf, String::new());
+                if buf.starts_with("File=") { iterm::file(&buf[5..]) } else { None }
+            }
+            777 => {
+                let buf = mem::replace(&mut self.arg_buf, String::new());
+                let mut params = buf.splitn(3, ';');
+                match (params.next(), params.next(), params.next()) {
+                    (Some("notify"), Some(title), body) => {
+                        let body = body.unwrap_or("").to_owned();
+                        wrap(Notify(Notification::with_title(title.to_owned(), body)))
+                    }
+                    _                                   => None,
+                }
+            }
             _   => None
         }
     }
 
+    fn window_op(&self, terminal: char) -> Option<Command> {
+        // A size of zero leaves that dimension unchanged.
+        let size = |idx| match self.arg(idx, 0) { 0 => None, n => Some(n) };
+        match self.arg(0, 0) {
+            1   => wrap(WindowOperation(WindowOp::Iconify(false))),
+            2   => wrap(WindowOperation(WindowOp::Iconify(true))),
+            3   => wrap(WindowOperation(WindowOp::Move(self.arg(1, 0), self.arg(2, 0)))),
+            4   => wrap(WindowOperation(WindowOp::ResizePixels(size(2), size(1)))),
+            5   => wrap(WindowOperation(WindowOp::Raise)),
+            6   => wrap(WindowOperation(WindowOp::Lower)),
+            8   => wrap(WindowOperation(WindowOp::ResizeCells(size(2), size(1)))),
+            9   => {
+                let (horizontal, vertical) = match self.arg(1, 0) {
+                    0   => (false, false),
+                    1   => (true, true),
+                    2   => (false, true),
+                    3   => (true, false),
+                    _   => return None,
+                };
+                let op = WindowOp::Maximize { horizontal: horizontal, vertical: vertical };
+                wrap(WindowOperation(op))
+            }
+            10  => match self.arg(1, 0) {
+                0   => wrap(WindowOperation(WindowOp::Fullscreen(Some(false)))),
+                1   => wrap(WindowOperation(WindowOp::Fullscreen(Some(true)))),
+                2   => wrap(WindowOperation(WindowOp::Fullscreen(None))),
+                _   => None,
+            },
+            11  => wrap(ReportWindow(WindowReport::Iconified)),
+            13  => wrap(ReportWindow(WindowReport::Position)),
+            14  => wrap(ReportWindow(WindowReport::PixelSize)),
+            16  => wrap(ReportWindow(WindowReport::CellSize)),
+            18  => wrap(ReportWindow(WindowReport::TextSize)),
+            19  => wrap(ReportWindow(WindowReport::ScreenSize)),
+            20  => wrap(ReportWindow(WindowReport::Icon)),
+            21  => wrap(ReportWindow(WindowReport::Title)),
+            22 | 23 => {
+                let kind = match self.arg(1, 0) {
+                    0   => TitleKind::Both,
+                    1   => TitleKind::Icon,
+                    2   => TitleKind::Title,
+                    _   => return None,
+                };
+                if self.arg(0, 0) == 22 { wrap(PushTitle(kind)) } else { wrap(PopTitle(kind)) }
+            }
+            n if n >= 24    => wrap(WindowOperation(WindowOp::ResizeCells(None, Some(n)))),
+            _   => wrap(NoFeature(self.csi_code(terminal))),
+        }
+    }
+
     fn arg(&self, idx: usize, default: u32) -> u32 {
         self.args.get(idx).map_or(default, |&x|x)
     }
@@ -355,3 +534,12 @@ impl AnsiData {
 fn wrap<T: CommandTrait>(cmd: T) -> Option<Command> {
     Some(Command { inner: Box::new(cmd) as Box<CommandTrait> })
 }
+
+// Several commands produced by a single escape code.
+fn series(mut cmds: Vec<Command>) -> Option<Command> {
+    match cmds.len() {
+        0   => None,
+        1   => cmds.pop(),
+        _   => wrap(CommandSeries(cmds)),
+    }
+}
@@ -0,0 +1,166 @@
+//  notty is a new kind of terminal emulator.
+//  Copyright (C) 2015 without boats
+//  
+//  This program is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU Affero General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//  
+//  This program is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU Affero General Public License for more details.
+//  
+//  You should have received a copy of the GNU Affero General Public License
+//  along with this program.  If not, see <http://www.gnu.org/licenses/>.
+use mime::{Mime, TopLevel, SubLevel};
+
+/// An image decoded from an escape code which sends raw pixels, such as Sixel. Renderers load
+/// images from encoded data, so bitmaps are encoded as uncompressed PNGs before being placed
+/// on the screen.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Bitmap {
+    pub width: u32,
+    pub height: u32,
+    /// The pixels of the image, row by row, as 8-bit RGBA.
+    pub data: Vec<u8>,
+}
+
+impl Bitmap {
+
+    /// A transparent bitmap.
+    pub fn new(width: u32, height: u32) -> Bitmap {
+        Bitmap {
+            width: width,
+            height: height,
+            data: vec![0; (width * height * 4) as usize],
+        }
+    }
+
+    pub fn set_pixel(&mut self, x: u32, y: u32, rgba: [u8; 4]) {
+        if x >= self.width || y >= self.height { return }
+        let idx = ((y * self.width + x) * 4) as usize;
+        self.data[idx..idx + 4].copy_from_slice(&rgba);
+    }
+
+    /// Grow the bitmap to at least this size, filling new pixels with a color.
+    pub fn grow(&mut self, width: u32, height: u32, fill: [u8; 4]) {
+        if width <= self.width && height <= self.height { return }
+        let (new_w, new_h) = (width.max(self.width), height.max(self.height));
+        let mut data = Vec::with_capacity((new_w * new_h * 4) as usize);
+        for y in 0..new_h {
+            if y < self.height {
+                let start = (y * self.width * 4) as usize;
+                data.extend_from_slice(&self.data[start..start + (self.width * 4) as usize]);
+                for _ in self.width..new_w { data.extend_from_slice(&fill) }
+            } else {
+                for _ in 0..new_w { data.extend_from_slice(&fill) }
+            }
+        }
+        self.width = new_w;
+        self.height = new_h;
+        self.data = data;
+    }
+
+    pub fn mime() -> Mime {
+        Mime(TopLevel::Image, SubLevel::Png, vec![])
+    }
+
+    /// Encode the bitmap as a PNG, using stored deflate blocks rather than compressing it.
+    pub fn png(&self) -> Vec<u8> {
+        let mut raw = Vec::with_capacity(self.data.len() + self.height as usize);
+        for row in self.data.chunks((self.width * 4) as usize) {
+            raw.push(0);
+            raw.extend_from_slice(row);
+        }
+
+        let mut ihdr = Vec::with_capacity(13);
+        push_u32(&mut ihdr, self.width);
+        push_u32(&mut ihdr, self.height);
+        ihdr.extend_from_slice(&[8, 6, 0, 0, 0]);
+
+        let mut zlib = vec![0x78, 0x01];
+        let blocks = raw.chunks(0xffff).collect::<Vec<_>>();
+        for (n, block) in blocks.iter().enumerate() {
+            let len = block.len() as u16;
+            zlib.push(if n + 1 == blocks.len() { 1 } else { 0 });
+            zlib.extend_from_slice(&[len as u8, (len >> 8) as u8, !len as u8, (!len >> 8) as u8]);
+            zlib.extend_from_slice(block);
+        }
+        if blocks.is_empty() { zlib.extend_from_slice(&[1, 0, 0, 0xff, 0xff]); }
+        push_u32(&mut zlib, adler32(&raw));
+
+        let mut png = vec![0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+        push_chunk(&mut png, b"IHDR", &ihdr);
+        push_chunk(&mut png, b"IDAT", &zlib);
+        push_chunk(&mut png, b"IEND", &[]);
+        png
+    }
+
+}
+
+fn push_u32(buf: &mut Vec<u8>, n: u32) {
+    buf.extend_from_slice(&[(n >> 24) as u8, (n >> 16) as u8, (n >> 8) as u8, n as u8]);
+}
+
+fn push_chunk(buf: &mut Vec<u8>, kind: &[u8], data: &[u8]) {
+    push_u32(buf, data.len() as u32);
+    let start = buf.len();
+    buf.extend_from_slice(kind);
+    buf.extend_from_slice(data);
+    let crc = crc32(&buf[start..]);
+    push_u32(buf, crc);
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xedb88320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in data {
+        a = (a + byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::{Bitmap, adler32, crc32};
+
+    #[test]
+    fn grow() {
+        let mut bitmap = Bitmap::new(1, 1);
+        bitmap.set_pixel(0, 0, [1, 2, 3, 4]);
+        bitmap.grow(2, 2, [0, 0, 0, 0xff]);
+        assert_eq!(bitmap.data, vec![1, 2, 3, 4, 0, 0, 0, 0xff, 0, 0, 0, 0xff, 0, 0, 0, 0xff]);
+    }
+
+    #[test]
+    fn png() {
+        let mut bitmap = Bitmap::new(2, 1);
+        bitmap.set_pixel(1, 0, [0xff, 0, 0, 0xff]);
+        let png = bitmap.png();
+        assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+        assert_eq!(&png[12..16], b"IHDR");
+        assert_eq!(&png[16..24], &[0, 0, 0, 2, 0, 0, 0, 1]);
+        // The IEND chunk is always the same.
+        assert_eq!(&png[png.len() - 12..], b"\0\0\0\0IEND\xae\x42\x60\x82");
+    }
+
+    #[test]
+    fn checksums() {
+        assert_eq!(crc32(b"123456789"), 0xcbf43926);
+        assert_eq!(adler32(b"Wikipedia"), 0x11e60398);
+    }
+
+}
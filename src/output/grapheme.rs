@@ -0,0 +1,106 @@
+//  notty is a new kind of terminal emulator.
+//  Copyright (C) 2015 without boats
+//  
+//  This program is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU Affero General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//  
+//  This program is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU Affero General Public License for more details.
+//  
+//  You should have received a copy of the GNU Affero General Public License
+//  along with this program.  If not, see <http://www.gnu.org/licenses/>.
+use grapheme_tables::GraphemeCat;
+use grapheme_tables::GraphemeCat::*;
+
+/// The grapheme cluster the most recently written character belongs to, which decides whether
+/// the next character extends it or begins a new cluster (following UAX #29).
+#[derive(Default)]
+pub struct GraphemeData {
+    previous: Option<GraphemeCat>,
+    regional_indicators: u32,
+    pictographic: bool,
+}
+
+impl GraphemeData {
+
+    /// Record the next character written, returning whether it extends the current cluster.
+    pub fn extends(&mut self, cat: GraphemeCat) -> bool {
+        let extends = match (self.previous, cat) {
+            (None, _) | (Some(GC_Control), _) | (_, GC_Control)         => false,
+            (_, GC_Extend) | (_, GC_ZWJ) | (_, GC_SpacingMark)          => true,
+            (Some(GC_Prepend), _)                                       => true,
+            (Some(GC_L), GC_L) | (Some(GC_L), GC_V)
+                | (Some(GC_L), GC_LV) | (Some(GC_L), GC_LVT)            => true,
+            (Some(GC_LV), GC_V) | (Some(GC_LV), GC_T)
+                | (Some(GC_V), GC_V) | (Some(GC_V), GC_T)               => true,
+            (Some(GC_LVT), GC_T) | (Some(GC_T), GC_T)                   => true,
+            (Some(GC_ZWJ), GC_Extended_Pictographic)                    => self.pictographic,
+            (Some(GC_Regional_Indicator), GC_Regional_Indicator)        => {
+                self.regional_indicators % 2 == 1
+            }
+            _                                                           => false,
+        };
+        // An emoji can only be joined to the cluster before it by a ZWJ if that cluster is itself
+        // an emoji followed by any number of extending characters.
+        self.pictographic = match cat {
+            GC_Extended_Pictographic    => true,
+            GC_Extend | GC_ZWJ          => self.pictographic && self.previous != Some(GC_ZWJ),
+            _                           => false,
+        };
+        self.regional_indicators = match cat {
+            GC_Regional_Indicator   => self.regional_indicators + 1,
+            _                       => 0,
+        };
+        self.previous = Some(cat);
+        extends
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+
+    use grapheme_tables::grapheme_category;
+    use super::*;
+
+    fn clusters(s: &str) -> Vec<String> {
+        let mut grapheme = GraphemeData::default();
+        let mut clusters: Vec<String> = vec![];
+        for ch in s.chars() {
+            if grapheme.extends(grapheme_category(ch)) {
+                clusters.last_mut().unwrap().push(ch);
+            } else { clusters.push(ch.to_string()); }
+        }
+        clusters
+    }
+
+    #[test]
+    fn flags() {
+        assert_eq!(clusters("\u{1f1fa}\u{1f1f8}\u{1f1eb}\u{1f1f7}\u{1f1e9}"),
+                   vec!["\u{1f1fa}\u{1f1f8}", "\u{1f1eb}\u{1f1f7}", "\u{1f1e9}"]);
+    }
+
+    #[test]
+    fn emoji_sequences() {
+        assert_eq!(clusters("\u{1f469}\u{1f3fd}\u{200d}\u{1f4bb}\u{2764}\u{fe0f}"),
+                   vec!["\u{1f469}\u{1f3fd}\u{200d}\u{1f4bb}", "\u{2764}\u{fe0f}"]);
+        assert_eq!(clusters("A\u{200d}\u{1f4bb}"), vec!["A\u{200d}", "\u{1f4bb}"]);
+    }
+
+    #[test]
+    fn hangul() {
+        assert_eq!(clusters("\u{1100}\u{1161}\u{11a8}\u{ac00}\u{11a8}\u{ac01}\u{1161}"),
+                   vec!["\u{1100}\u{1161}\u{11a8}", "\u{ac00}\u{11a8}", "\u{ac01}", "\u{1161}"]);
+    }
+
+    #[test]
+    fn prepend_and_marks() {
+        assert_eq!(clusters("\u{600}1E\u{301}\u{915}\u{93f}\n\u{301}"),
+                   vec!["\u{600}1", "E\u{301}", "\u{915}\u{93f}", "\n", "\u{301}"]);
+    }
+
+}
@@ -0,0 +1,132 @@
+//  notty is a new kind of terminal emulator.
+//  Copyright (C) 2015 without boats
+//  
+//  This program is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU Affero General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//  
+//  This program is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU Affero General Public License for more details.
+//  
+//  You should have received a copy of the GNU Affero General Public License
+//  along with this program.  If not, see <http://www.gnu.org/licenses/>.
+use mime::{Mime, TopLevel, SubLevel};
+
+use base64;
+
+use Command;
+use command::*;
+
+use super::kitty::png_size;
+
+/// Parse the arguments of an iTerm2 `File=` sequence (OSC 1337), which are followed by a
+/// colon and the base64 encoded contents of the file. Only files to be shown inline in a
+/// format notty can display are supported.
+pub fn file(args: &str) -> Option<Command> {
+    let mut parts = args.splitn(2, ':');
+    let (params, data) = match (parts.next(), parts.next()) {
+        (Some(params), Some(data))  => (params, data),
+        _                           => return None,
+    };
+    let (mut width, mut height) = (InlineSize::Auto, InlineSize::Auto);
+    let (mut preserve_aspect, mut inline) = (true, false);
+    for param in params.split(';') {
+        let mut param = param.splitn(2, '=');
+        match (param.next(), param.next()) {
+            (Some("width"), Some(size))                 => width = inline_size(size),
+            (Some("height"), Some(size))                => height = inline_size(size),
+            (Some("preserveAspectRatio"), Some(flag))   => preserve_aspect = flag != "0",
+            (Some("inline"), Some(flag))                => inline = flag == "1",
+            _                                           => { }
+        }
+    }
+    if !inline { return wrap(NoFeature(String::from("^[]1337;File= download"))) }
+    let data = match base64::u8de(data.as_bytes()) {
+        Ok(data)    => data,
+        Err(_)      => return None,
+    };
+    image_info(&data).and_then(|(mime, size)| {
+        wrap(PutInline::new(data, mime, size, width, height, preserve_aspect))
+    })
+}
+
+fn inline_size(size: &str) -> InlineSize {
+    let parse = |n: &str| u32::from_str_radix(n, 10).ok();
+    let size = if size.ends_with("px") {
+        parse(&size[..size.len() - 2]).map(InlineSize::Pixels)
+    } else if size.ends_with('%') {
+        parse(&size[..size.len() - 1]).map(InlineSize::Percent)
+    } else { parse(size).map(InlineSize::Cells) };
+    size.unwrap_or(InlineSize::Auto)
+}
+
+// The type and size in pixels of a PNG, GIF or JPEG image, read from its header.
+fn image_info(data: &[u8]) -> Option<(Mime, (u32, u32))> {
+    let mime = |sub| Mime(TopLevel::Image, sub, vec![]);
+    if let Some(size) = png_size(data) {
+        Some((mime(SubLevel::Png), size))
+    } else if data.starts_with(b"GIF8") && data.len() >= 10 {
+        let size = (read_le16(&data[6..8]), read_le16(&data[8..10]));
+        Some((mime(SubLevel::Gif), size))
+    } else if data.starts_with(b"\xff\xd8") {
+        jpeg_size(data).map(|size| (mime(SubLevel::Jpeg), size))
+    } else { None }
+}
+
+// The size of a JPEG image is in its first start of frame segment.
+fn jpeg_size(data: &[u8]) -> Option<(u32, u32)> {
+    let mut idx = 2;
+    while idx + 9 <= data.len() {
+        if data[idx] != 0xff { return None }
+        match data[idx + 1] {
+            0xff                        => idx += 1,
+            0xc4 | 0xc8 | 0xcc          => idx += 2 + read_be16(&data[idx + 2..]) as usize,
+            0xc0...0xcf                 => {
+                return Some((read_be16(&data[idx + 7..]), read_be16(&data[idx + 5..])))
+            }
+            _                           => idx += 2 + read_be16(&data[idx + 2..]) as usize,
+        }
+    }
+    None
+}
+
+fn read_be16(data: &[u8]) -> u32 {
+    (data[0] as u32) << 8 | data[1] as u32
+}
+
+fn read_le16(data: &[u8]) -> u32 {
+    (data[1] as u32) << 8 | data[0] as u32
+}
+
+fn wrap<T: CommandTrait>(cmd: T) -> Option<Command> {
+    Some(Command { inner: Box::new(cmd) as Box<CommandTrait> })
+}
+
+#[cfg(test)]
+mod tests {
+
+    use command::InlineSize;
+    use super::{image_info, inline_size};
+
+    #[test]
+    fn inline_sizes() {
+        assert_eq!(inline_size("auto"), InlineSize::Auto);
+        assert_eq!(inline_size("12"), InlineSize::Cells(12));
+        assert_eq!(inline_size("300px"), InlineSize::Pixels(300));
+        assert_eq!(inline_size("50%"), InlineSize::Percent(50));
+        assert_eq!(inline_size("wide"), InlineSize::Auto);
+    }
+
+    #[test]
+    fn image_sizes() {
+        let gif = b"GIF89a\x20\x01\x10\x00";
+        assert_eq!(image_info(gif).map(|(_, size)| size), Some((288, 16)));
+        let jpeg = b"\xff\xd8\xff\xe0\x00\x04\x00\x00\xff\xc0\x00\x11\x08\x00\x30\x00\x40\x03";
+        assert_eq!(image_info(jpeg).map(|(_, size)| size), Some((64, 48)));
+        assert_eq!(image_info(b"BM"), None);
+    }
+
+}
@@ -0,0 +1,328 @@
+//  notty is a new kind of terminal emulator.
+//  Copyright (C) 2015 without boats
+//  
+//  This program is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU Affero General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//  
+//  This program is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU Affero General Public License for more details.
+//  
+//  You should have received a copy of the GNU Affero General Public License
+//  along with this program.  If not, see <http://www.gnu.org/licenses/>.
+use std::mem;
+use std::sync::Arc;
+
+use base64;
+
+use Command;
+use command::*;
+use datatypes::Coords;
+use terminal::{DeleteSpec, PlaceParams, StoredImage};
+
+use super::bitmap::Bitmap;
+
+// The most data a single image may be sent with, to bound the memory it can use.
+const MAX_PAYLOAD: usize = 0x4000000;
+
+// The largest raw image which will be converted to a PNG.
+const MAX_SIZE: u32 = 10000;
+
+/// A kitty graphics command (`APC G`) being parsed, and the image data of any earlier
+/// chunks of an image sent in several commands. The data is `None` once the image has grown
+/// too large, and the rest of its chunks are dropped.
+#[derive(Default)]
+pub struct KittyData {
+    buf: String,
+    chunked: Option<(Control, Option<String>)>,
+}
+
+impl KittyData {
+
+    pub fn push(&mut self, ch: char) {
+        if self.buf.len() < MAX_PAYLOAD { self.buf.push(ch); }
+    }
+
+    pub fn clear(&mut self) {
+        self.buf.clear();
+    }
+
+    /// Parse the command which has just ended. A chunk with `m=1` is held until the final chunk
+    /// of its image, which takes its control data from the first one. An image which grows too
+    /// large is reported once its final chunk arrives.
+    pub fn parse(&mut self) -> Option<Command> {
+        let buf = mem::replace(&mut self.buf, String::new());
+        let (control, payload) = match buf.find(';') {
+            Some(idx)   => (&buf[..idx], &buf[idx + 1..]),
+            None        => (&buf[..], ""),
+        };
+        let control = Control::parse(control);
+        let (control, payload) = match self.chunked.take() {
+            Some((first, data)) => {
+                let data = data.and_then(|mut data| {
+                    data.push_str(payload);
+                    if data.len() > MAX_PAYLOAD { None } else { Some(data) }
+                });
+                if control.more {
+                    self.chunked = Some((first, data));
+                    return None
+                }
+                match data {
+                    Some(data)  => (first, data),
+                    None        => {
+                        return wrap(ReportGraphics(first.reply(), Err("EFBIG:image is too large")))
+                    }
+                }
+            }
+            None if control.more    => {
+                self.chunked = Some((control, Some(payload.to_owned())));
+                return None
+            }
+            None                    => (control, payload.to_owned()),
+        };
+        control.command(&payload)
+    }
+
+}
+
+// The keys of a kitty graphics command which notty understands.
+#[derive(Clone, Debug)]
+struct Control {
+    action: char,
+    format: u32,
+    medium: char,
+    compression: Option<char>,
+    width: u32,
+    height: u32,
+    id: u32,
+    number: u32,
+    placement: u32,
+    more: bool,
+    quiet: u32,
+    cols: u32,
+    rows: u32,
+    x: u32,
+    y: u32,
+    z: i32,
+    move_cursor: bool,
+    virtual_placement: bool,
+    delete: char,
+}
+
+impl Control {
+
+    fn parse(control: &str) -> Control {
+        let mut ctrl = Control {
+            action: 't',
+            format: 32,
+            medium: 'd',
+            compression: None,
+            width: 0,
+            height: 0,
+            id: 0,
+            number: 0,
+            placement: 0,
+            more: false,
+            quiet: 0,
+            cols: 0,
+            rows: 0,
+            x: 0,
+            y: 0,
+            z: 0,
+            move_cursor: true,
+            virtual_placement: false,
+            delete: 'a',
+        };
+        for pair in control.split(',') {
+            let mut pair = pair.splitn(2, '=');
+            let key = pair.next().and_then(|key| key.chars().next());
+            let value = pair.next().unwrap_or("");
+            let n = u32::from_str_radix(value, 10).unwrap_or(0);
+            let ch = value.chars().next();
+            match (key, ch) {
+                (Some('a'), Some(ch))   => ctrl.action = ch,
+                (Some('f'), _)          => ctrl.format = n,
+                (Some('t'), Some(ch))   => ctrl.medium = ch,
+                (Some('o'), ch)         => ctrl.compression = ch,
+                (Some('s'), _)          => ctrl.width = n,
+                (Some('v'), _)          => ctrl.height = n,
+                (Some('i'), _)          => ctrl.id = n,
+                (Some('I'), _)          => ctrl.number = n,
+                (Some('p'), _)          => ctrl.placement = n,
+                (Some('m'), _)          => ctrl.more = n == 1,
+                (Some('q'), _)          => ctrl.quiet = n,
+                (Some('c'), _)          => ctrl.cols = n,
+                (Some('r'), _)          => ctrl.rows = n,
+                (Some('x'), _)          => ctrl.x = n,
+                (Some('y'), _)          => ctrl.y = n,
+                (Some('z'), _)          => ctrl.z = i32::from_str_radix(value, 10).unwrap_or(0),
+                (Some('C'), _)          => ctrl.move_cursor = n != 1,
+                (Some('U'), _)          => ctrl.virtual_placement = n == 1,
+                (Some('d'), Some(ch))   => ctrl.delete = ch,
+                _                       => { }
+            }
+        }
+        ctrl
+    }
+
+    fn reply(&self) -> GraphicsReply {
+        GraphicsReply {
+            id: self.id,
+            number: self.number,
+            placement: self.placement,
+            quiet: self.quiet,
+        }
+    }
+
+    fn place_params(&self) -> PlaceParams {
+        PlaceParams {
+            cols: self.cols,
+            rows: self.rows,
+            z: self.z,
+            move_cursor: self.move_cursor,
+            virtual_placement: self.virtual_placement,
+        }
+    }
+
+    fn command(&self, payload: &str) -> Option<Command> {
+        let reply = self.reply();
+        match self.action {
+            't' | 'T' | 'q' => {
+                let image = match self.image(payload) {
+                    Ok(image)   => image,
+                    Err(err)    => return wrap(ReportGraphics(reply, Err(err))),
+                };
+                match self.action {
+                    't' => wrap(TransmitImage::new(image, None, reply)),
+                    'T' => wrap(TransmitImage::new(image, Some(self.place_params()), reply)),
+                    _   => wrap(ReportGraphics(reply, Ok(()))),
+                }
+            }
+            'p'             => wrap(PlaceImage(self.place_params(), reply)),
+            'd'             => {
+                // Upper case specifiers also free the images whose placements are deleted.
+                let spec = match self.delete {
+                    'a' | 'A'   => DeleteSpec::All,
+                    'i' | 'I'   => DeleteSpec::Image(self.id, self.placement),
+                    'n' | 'N'   => DeleteSpec::Number(self.number),
+                    'c' | 'C'   => DeleteSpec::Cursor,
+                    'p' | 'P'   => DeleteSpec::Cell(Coords {
+                        x: self.x.saturating_sub(1),
+                        y: self.y.saturating_sub(1),
+                    }),
+                    'x' | 'X'   => DeleteSpec::Column(self.x.saturating_sub(1)),
+                    'y' | 'Y'   => DeleteSpec::Row(self.y.saturating_sub(1)),
+                    'z' | 'Z'   => DeleteSpec::ZIndex(self.z),
+                    _           => return None,
+                };
+                wrap(DeleteImages(spec, self.delete.is_uppercase()))
+            }
+            _               => wrap(ReportGraphics(reply, Err("EINVAL:unknown action"))),
+        }
+    }
+
+    // Decode the image sent with a command. PNG images are stored as they are, and raw pixel
+    // data is converted to PNG.
+    fn image(&self, payload: &str) -> Result<StoredImage, &'static str> {
+        if self.medium != 'd' { return Err("EINVAL:unsupported transmission medium") }
+        if self.compression.is_some() { return Err("EINVAL:unsupported compression") }
+        let data = match base64::u8de(payload.as_bytes()) {
+            Ok(data)    => data,
+            Err(_)      => return Err("EINVAL:bad base64 data"),
+        };
+        match self.format {
+            100     => match png_size(&data) {
+                Some((w, h))    => Ok(StoredImage { data: Arc::new(data), width: w, height: h }),
+                None            => Err("EBADPNG:not a PNG image"),
+            },
+            24 | 32 => {
+                let (w, h) = (self.width, self.height);
+                let depth = (self.format / 8) as usize;
+                if w == 0 || h == 0 || w > MAX_SIZE || h > MAX_SIZE
+                        || data.len() != w as usize * h as usize * depth {
+                    return Err("EINVAL:wrong amount of pixel data")
+                }
+                let bitmap = Bitmap {
+                    width: w,
+                    height: h,
+                    data: if depth == 4 { data } else {
+                        data.chunks(3).flat_map(|px| px.iter().cloned().chain(Some(0xff)))
+                            .collect()
+                    },
+                };
+                Ok(StoredImage { data: Arc::new(bitmap.png()), width: w, height: h })
+            }
+            _       => Err("EINVAL:unsupported format"),
+        }
+    }
+
+}
+
+// Read the size of a PNG image from its header.
+pub fn png_size(data: &[u8]) -> Option<(u32, u32)> {
+    fn read_u32(data: &[u8]) -> u32 {
+        data.iter().fold(0, |n, &byte| n << 8 | byte as u32)
+    }
+    if data.len() < 24 || &data[..8] != b"\x89PNG\r\n\x1a\n" || &data[12..16] != b"IHDR" {
+        return None
+    }
+    Some((read_u32(&data[16..20]), read_u32(&data[20..24])))
+}
+
+fn wrap<T: CommandTrait>(cmd: T) -> Option<Command> {
+    Some(Command { inner: Box::new(cmd) as Box<CommandTrait> })
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::iter;
+
+    use super::{Control, KittyData, MAX_PAYLOAD, png_size};
+    use output::bitmap::Bitmap;
+
+    fn chunk(kitty: &mut KittyData, data: &str) -> Option<String> {
+        for ch in data.chars() { kitty.push(ch); }
+        kitty.parse().map(|cmd| cmd.inner.repr())
+    }
+
+    #[test]
+    fn control() {
+        let ctrl = Control::parse("a=T,f=24,s=2,v=3,i=31,q=1,z=-2,C=1,m=1,U=1");
+        assert_eq!(ctrl.action, 'T');
+        assert_eq!((ctrl.format, ctrl.width, ctrl.height), (24, 2, 3));
+        assert_eq!((ctrl.id, ctrl.quiet, ctrl.z), (31, 1, -2));
+        assert!(ctrl.more && !ctrl.move_cursor && ctrl.place_params().virtual_placement);
+    }
+
+    #[test]
+    fn oversized_chunks() {
+        let mut kitty = KittyData::default();
+        let data = iter::repeat('A').take(MAX_PAYLOAD).collect::<String>();
+        kitty.chunked = Some((Control::parse("a=T,f=100,i=1,m=1"), Some(data)));
+        assert_eq!(chunk(&mut kitty, "m=1;AAAA"), None);
+        assert_eq!(chunk(&mut kitty, "m=1;AAAA"), None);
+        assert_eq!(chunk(&mut kitty, "m=0;AAAA"),
+                   Some(String::from("REPORT GRAPHICS EFBIG:image is too large")));
+        assert!(kitty.chunked.is_none());
+    }
+
+    #[test]
+    fn raw_pixels() {
+        let ctrl = Control::parse("f=24,s=2,v=1");
+        let image = ctrl.image("AAAA////").unwrap();
+        assert_eq!((image.width, image.height), (2, 1));
+        assert_eq!(png_size(&image.data), Some((2, 1)));
+        assert!(ctrl.image("AAAA").is_err());
+    }
+
+    #[test]
+    fn png() {
+        assert_eq!(png_size(&Bitmap::new(5, 7).png()), Some((5, 7)));
+        assert_eq!(png_size(b"GIF89a"), None);
+    }
+
+}
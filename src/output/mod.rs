@@ -14,6 +14,7 @@
 //  You should have received a copy of the GNU Affero General Public License
 //  along with this program.  If not, see <http://www.gnu.org/licenses/>.
 use std::io;
+use std::mem;
 
 use command::*;
 use datatypes::args::*;
@@ -21,18 +22,32 @@ use grapheme_tables as gr;
 use super::Command;
 
 mod ansi;
+mod bitmap;
+mod grapheme;
+mod iterm;
+mod kitty;
 mod notty;
+mod sixel;
 
 use self::ansi::AnsiData;
+use self::grapheme::GraphemeData;
+use self::kitty::KittyData;
 use self::notty::NottyData;
+use self::notty::AttachmentError::BadEncoding;
 use self::State::*;
 
+// The most data a DCS or OSC string may hold, to bound the memory a sixel or inline image can
+// use.
+const MAX_STRING: usize = 0x4000000;
+
 /// The `Output` struct processes data written to the terminal from the controlling process,
 /// parsing it into structured commands. It is implemented as an `Iterator`.
 pub struct Output<R: io::BufRead> {
     tty: io::Chars<R>,
     state: State,
     ansi: AnsiData,
+    grapheme: GraphemeData,
+    kitty: KittyData,
     notty: NottyData,
 }
 
@@ -44,14 +59,17 @@ impl<R: io::BufRead> Output<R> {
             tty: tty.chars(),
             state: Character,
             ansi: AnsiData::default(),
+            grapheme: GraphemeData::default(),
+            kitty: KittyData::default(),
             notty: NottyData::default(),
         }
     }
 
     fn character(&mut self, ch: char) -> (State, Option<Command>) {
         use grapheme_tables::GraphemeCat::*;
-        match gr::grapheme_category(ch) {
-            GC_Any                      => (Character, wrap(Put::new_char(ch))),
+        let cat = gr::grapheme_category(ch);
+        let extends = self.grapheme.extends(cat);
+        match cat {
             GC_Control                  => match ch {
                 '\x07'      => (Character, wrap(Bell)),
                 '\x08'      => (Character, wrap(Move::new(To(Left, 1, true)))),
@@ -67,8 +85,8 @@ impl<R: io::BufRead> Output<R> {
                 '\u{9f}'    => (ApcCode, None),
                 _           => (Character, None),
             },
-            GC_Extend | GC_SpacingMark  => (Character, wrap(Put::new_extension(ch))),
-            _                           => unimplemented!(),
+            _ if extends                => (Character, wrap(Put::new_extension(ch))),
+            _                           => (Character, wrap(Put::new_char(ch))),
         }
     }
 
@@ -93,6 +111,8 @@ impl<R: io::BufRead> Output<R> {
                 ];
                 (Ignore(IGNORE), None)
             }
+            '=' => (Character, wrap(SetKeypadMode(true))),
+            '>' => (Character, wrap(SetKeypadMode(false))),
             'E' => (Character, wrap(Move::new(NextLine(1)))),
             'P' => (DcsCode, None),
             '[' => (CsiCode, None),
@@ -104,7 +124,7 @@ impl<R: io::BufRead> Output<R> {
     }
 
     fn csi_code(&mut self, ch: char) -> (State, Option<Command>) {
-        static CSI_PRIVATE_MODES:   &'static [char] = &['>', '?'];
+        static CSI_PRIVATE_MODES:   &'static [char] = &['<', '=', '>', '?'];
         static CSI_PRETERMINALS:    &'static [char] = &[' ', '!', '"', '$', '\'', '*'];
         static CSI_TERMINALS:       &'static [char] = &[
             '@', 'A', 'B', 'C', 'D', 'E', 'F', 'G', 'H', 'I', 'J', 'K', 'L', 'M', 'P', 'S',
@@ -158,9 +178,70 @@ impl<R: io::BufRead> Output<R> {
         }
     }
 
-    #[allow(unused)]
     fn dcs_code(&mut self, ch: char) -> (State, Option<Command>) {
-        unimplemented!()
+        match ch {
+            '<'...'?' if self.ansi.args.is_empty() && self.ansi.arg_buf.is_empty() => {
+                self.ansi.private_mode = ch;
+                (DcsCode, None)
+            }
+            '0'...'9' if self.ansi.preterminal == '\0'    => {
+                self.ansi.arg_buf.push(ch);
+                (DcsCode, None)
+            }
+            ';' if self.ansi.preterminal == '\0'          => {
+                let n = u32::from_str_radix(&self.ansi.arg_buf, 10).unwrap_or(0);
+                self.ansi.args.push(n);
+                self.ansi.arg_buf.clear();
+                (DcsCode, None)
+            }
+            ' '...'/'                                       => {
+                self.ansi.preterminal = ch;
+                (DcsCode, None)
+            }
+            '@'...'~'                                       => {
+                if !self.ansi.arg_buf.is_empty() {
+                    let n = u32::from_str_radix(&self.ansi.arg_buf, 10).unwrap_or(0);
+                    self.ansi.args.push(n);
+                    self.ansi.arg_buf.clear();
+                }
+                (DcsString(ch), None)
+            }
+            _                                               => {
+                self.ansi.clear();
+                self.ansi.arg_buf.clear();
+                (Character, None)
+            }
+        }
+    }
+
+    // The data of a DCS sequence continues until the string terminator.
+    fn dcs_string(&mut self, terminal: char, ch: char) -> (State, Option<Command>) {
+        match ch {
+            '\x1b'      => (DcsEscape(terminal), None),
+            '\u{9c}'    => self.end_dcs(terminal),
+            _           => {
+                if self.ansi.arg_buf.len() < MAX_STRING { self.ansi.arg_buf.push(ch); }
+                (DcsString(terminal), None)
+            }
+        }
+    }
+
+    fn dcs_escape(&mut self, terminal: char, ch: char) -> (State, Option<Command>) {
+        match ch {
+            '\\'    => self.end_dcs(terminal),
+            _       => {
+                self.ansi.clear();
+                self.ansi.arg_buf.clear();
+                self.esc_code(ch)
+            }
+        }
+    }
+
+    fn end_dcs(&mut self, terminal: char) -> (State, Option<Command>) {
+        let data = mem::replace(&mut self.ansi.arg_buf, String::new());
+        let ret = (Character, self.ansi.dcs(terminal, &data));
+        self.ansi.clear();
+        ret
     }
 
     fn osc_code(&mut self, ch: char) -> (State, Option<Command>) {
@@ -176,15 +257,36 @@ impl<R: io::BufRead> Output<R> {
             (OscCode, None)
         }
         else if ch == '\x1b' && self.ansi.preterminal == '\0' {
-            self.ansi.preterminal == '\x1b';
+            self.ansi.preterminal = '\x1b';
             (OscCode, None)
         }
         else if ch == '\u{9c}' || ch == '\x07' || (ch == '\\' && self.ansi.preterminal == '\x1b') {
+            // Codes such as OSC 104 may end without any parameters after the number. Sequences
+            // whose number is too large are dropped.
+            if self.ansi.private_mode == '\0' && !self.ansi.arg_buf.is_empty() {
+                match u32::from_str_radix(&self.ansi.arg_buf, 10).ok() {
+                    Some(n) => {
+                        self.ansi.args.push(n);
+                        self.ansi.arg_buf.clear();
+                    }
+                    None    => {
+                        self.ansi.clear();
+                        self.ansi.arg_buf.clear();
+                        return (Character, None)
+                    }
+                }
+            }
             let ret = (Character, self.ansi.osc());
             self.ansi.clear();
             ret
         }
         else if self.ansi.private_mode == ';' {
+            // A string which grows too large is dropped, ignoring the rest of it.
+            if self.ansi.arg_buf.len() >= MAX_STRING {
+                self.ansi.clear();
+                self.ansi.arg_buf.clear();
+                return self.privacy_message(ch)
+            }
             if self.ansi.preterminal == '\x1b' {
                 self.ansi.arg_buf.push('\x1b');
                 self.ansi.preterminal = '\0';
@@ -201,17 +303,43 @@ impl<R: io::BufRead> Output<R> {
     fn apc_code(&mut self, ch: char) -> (State, Option<Command>) {
         match ch {
             '[' => (NottyCode, None),
+            'G' => (KittyCode, None),
             _   => self.privacy_message(ch),
         }
     }
 
+    // The data of a kitty graphics command continues until the string terminator.
+    fn kitty_code(&mut self, ch: char) -> (State, Option<Command>) {
+        match ch {
+            '\x1b'      => (KittyEscape, None),
+            '\u{9c}'    => (Character, self.kitty.parse()),
+            _           => {
+                self.kitty.push(ch);
+                (KittyCode, None)
+            }
+        }
+    }
+
+    fn kitty_escape(&mut self, ch: char) -> (State, Option<Command>) {
+        match ch {
+            '\\'    => (Character, self.kitty.parse()),
+            _       => {
+                self.kitty.clear();
+                self.esc_code(ch)
+            }
+        }
+    }
+
     fn privacy_message(&mut self, ch: char) -> (State, Option<Command>) {
         match (self.ansi.preterminal, ch) {
             ('\0', '\x1b')                                  => {
                 self.ansi.preterminal = ch;
                 (PrivMsg, None)
             }
-            (_, '\u{9c}') | (_, '\x07') | ('\x1b', '\\')    => (Character, None),
+            (_, '\u{9c}') | (_, '\x07') | ('\x1b', '\\')    => {
+                self.ansi.preterminal = '\0';
+                (Character, None)
+            }
             ('\0', _)                                       => (PrivMsg, None),
             (_, _)                                          => self.character(ch),
         }
@@ -223,6 +351,7 @@ impl<R: io::BufRead> Output<R> {
             (NottyCode, None)
         }
         else if ch == '#' {
+            self.notty.start_attachments();
             (NottyAttach, None)
         }
         else if ch == '\u{9c}' {
@@ -249,18 +378,26 @@ impl<R: io::BufRead> Iterator for super::Output<R> {
                         EscCode         => self.esc_code(ch),
                         CsiCode         => self.csi_code(ch),
                         DcsCode         => self.dcs_code(ch),
+                        DcsString(t)    => self.dcs_string(t, ch),
+                        DcsEscape(t)    => self.dcs_escape(t, ch),
                         OscCode         => self.osc_code(ch),
                         ApcCode         => self.apc_code(ch),
                         PrivMsg         => self.privacy_message(ch),
+                        KittyCode       => self.kitty_code(ch),
+                        KittyEscape     => self.kitty_escape(ch),
                         NottyCode       => self.notty_code(ch),
                         NottyAttach     => {
-                            match self.notty.attachments.append(ch) {
+                            match self.notty.append(ch) {
                                 Some(true)  => {
                                     let ret = (Character, self.notty.parse());
                                     self.notty.clear();
                                     ret
                                 }
-                                Some(false) => (Character, None),
+                                Some(false) => {
+                                    let ret = (Character, self.notty.fail(BadEncoding));
+                                    self.notty.clear();
+                                    ret
+                                }
                                 None        => (NottyAttach, None),
                             }
                         }
@@ -287,11 +424,14 @@ enum State {
     Character,
     EscCode,
     CsiCode,
-    #[allow(dead_code)]
     DcsCode,
+    DcsString(char),
+    DcsEscape(char),
     OscCode,
     ApcCode,
     PrivMsg,
+    KittyCode,
+    KittyEscape,
     NottyCode,
     NottyAttach,
     Ignore(&'static [char]),
@@ -343,14 +483,155 @@ mod tests {
         assert_eq!(&output.next().unwrap().unwrap().inner.repr(), "SERIES: SET CURSOR STYLE");
     }
 
+    #[test]
+    fn keypad_mode() {
+        let mut output = setup(b"\x1b=A\x1b>");
+        assert_eq!(&output.next().unwrap().unwrap().inner.repr(), "SET KEYPAD MODE APPLICATION");
+        assert_eq!(&output.next().unwrap().unwrap().inner.repr(), "A");
+        assert_eq!(&output.next().unwrap().unwrap().inner.repr(), "SET KEYPAD MODE NUMERIC");
+    }
+
+    #[test]
+    fn modify_other_keys() {
+        let mut output = setup(b"\x1b[>4;2m\x1b[>4n");
+        assert_eq!(&output.next().unwrap().unwrap().inner.repr(), "SET MODIFY OTHER KEYS 2");
+        assert_eq!(&output.next().unwrap().unwrap().inner.repr(), "SET MODIFY OTHER KEYS 0");
+    }
+
+    #[test]
+    fn keyboard_flags() {
+        let mut output = setup(b"\x1b[>1u\x1b[=5;2u\x1b[?u\x1b[<u");
+        assert_eq!(&output.next().unwrap().unwrap().inner.repr(), "PUSH KEYBOARD FLAGS 1");
+        assert_eq!(&output.next().unwrap().unwrap().inner.repr(), "SET KEYBOARD FLAGS 5 2");
+        assert_eq!(&output.next().unwrap().unwrap().inner.repr(), "REPORT KEYBOARD FLAGS");
+        assert_eq!(&output.next().unwrap().unwrap().inner.repr(), "POP KEYBOARD FLAGS 1");
+    }
+
     #[test]
     fn osc_code() {
-        let mut output = setup(b"A\x1b]0;Hello, world!\x07B");
+        let mut output = setup(b"A\x1b]0;Hello, world!\x07B\x1b]1;icon\x07\x1b]2;title\x07");
         assert_eq!(&output.next().unwrap().unwrap().inner.repr(), "A");
+        assert_eq!(&output.next().unwrap().unwrap().inner.repr(), "SERIES: SET ICON; SET TITLE");
+        assert_eq!(&output.next().unwrap().unwrap().inner.repr(), "B");
+        assert_eq!(&output.next().unwrap().unwrap().inner.repr(), "SET ICON");
         assert_eq!(&output.next().unwrap().unwrap().inner.repr(), "SET TITLE");
+    }
+
+    #[test]
+    fn osc_without_parameters() {
+        let mut output = setup(b"]99999999999]5]106\]104A");
+        assert_eq!(&output.next().unwrap().unwrap().inner.repr(), "RESET PALETTE");
+        assert_eq!(&output.next().unwrap().unwrap().inner.repr(), "A");
+    }
+
+    #[test]
+    fn cwd() {
+        let mut output = setup(b"\x1b]7;file://host/home/a%20b\x07\x1b]7;/tmp\x07A");
+        assert_eq!(&output.next().unwrap().unwrap().inner.repr(), "SET CWD /home/a b");
+        assert_eq!(&output.next().unwrap().unwrap().inner.repr(), "A");
+    }
+
+    #[test]
+    fn notifications() {
+        let mut output = setup(b"\x1b]9;Done\x07\x1b]777;notify;make;Done\x07\x1b]777;x\x07A");
+        assert_eq!(&output.next().unwrap().unwrap().inner.repr(), "NOTIFY");
+        assert_eq!(&output.next().unwrap().unwrap().inner.repr(), "NOTIFY");
+        assert_eq!(&output.next().unwrap().unwrap().inner.repr(), "A");
+    }
+
+    #[test]
+    fn window_ops() {
+        let mut output = setup(b"\x1b[8;24;80t\x1b[9;1t\x1b[18t\x1b[21t\x1b[22;0t\x1b[23;2t\
+                                 \x1b[11t\x1b[13t\x1b[22;3tA");
+        assert_eq!(&output.next().unwrap().unwrap().inner.repr(), "RESIZE WINDOW CELLS");
+        assert_eq!(&output.next().unwrap().unwrap().inner.repr(), "MAXIMIZE WINDOW");
+        assert_eq!(&output.next().unwrap().unwrap().inner.repr(), "REPORT TEXT AREA SIZE");
+        assert_eq!(&output.next().unwrap().unwrap().inner.repr(), "REPORT TITLE");
+        assert_eq!(&output.next().unwrap().unwrap().inner.repr(), "PUSH TITLE");
+        assert_eq!(&output.next().unwrap().unwrap().inner.repr(), "POP TITLE");
+        assert_eq!(&output.next().unwrap().unwrap().inner.repr(), "REPORT ICONIFIED");
+        assert_eq!(&output.next().unwrap().unwrap().inner.repr(), "REPORT WINDOW POSITION");
+        assert_eq!(&output.next().unwrap().unwrap().inner.repr(), "A");
+    }
+
+    #[test]
+    fn pixel_size_reports() {
+        let mut output = setup(b"\x1b[14t\x1b[16t\x1b[14;2t");
+        assert_eq!(&output.next().unwrap().unwrap().inner.repr(), "REPORT TEXT AREA PIXEL SIZE");
+        assert_eq!(&output.next().unwrap().unwrap().inner.repr(), "REPORT CELL PIXEL SIZE");
+        assert_eq!(&output.next().unwrap().unwrap().inner.repr(), "REPORT TEXT AREA PIXEL SIZE");
+    }
+
+    #[test]
+    fn sixel() {
+        let mut output = setup(b"\x1bPq#1;2;100;0;0!2~\x1b\\A\x1bP$qm\x1b\\B");
+        assert_eq!(&output.next().unwrap().unwrap().inner.repr(), "PUT PIXELS 2x6");
+        assert_eq!(&output.next().unwrap().unwrap().inner.repr(), "A");
+        assert_eq!(&output.next().unwrap().unwrap().inner.repr(), "NO FEATURE: ^[P$q");
+        assert_eq!(&output.next().unwrap().unwrap().inner.repr(), "B");
+    }
+
+    #[test]
+    fn kitty_graphics() {
+        let mut output = setup(b"\x1b_Ga=T,f=24,s=1,v=1,m=1;AAAA\x1b\\\x1b_Gm=0;\x1b\\A\
+                                 \x1b_Ga=p,i=1,p=2\x1b\\\x1b_Ga=d,d=I,i=1\x1b\\\
+                                 \x1b_Ga=q,i=3,f=100;AAAA\x1b\\\x1b_Ga=t,t=f;AAAA\x1b\\B");
+        assert_eq!(&output.next().unwrap().unwrap().inner.repr(), "TRANSMIT AND PLACE IMAGE");
+        assert_eq!(&output.next().unwrap().unwrap().inner.repr(), "A");
+        assert_eq!(&output.next().unwrap().unwrap().inner.repr(), "PLACE IMAGE");
+        assert_eq!(&output.next().unwrap().unwrap().inner.repr(), "DELETE IMAGES");
+        assert_eq!(&output.next().unwrap().unwrap().inner.repr(),
+                   "REPORT GRAPHICS EBADPNG:not a PNG image");
+        assert_eq!(&output.next().unwrap().unwrap().inner.repr(),
+                   "REPORT GRAPHICS EINVAL:unsupported transmission medium");
         assert_eq!(&output.next().unwrap().unwrap().inner.repr(), "B");
     }
 
+    #[test]
+    fn iterm_images() {
+        let mut output = setup(b"\x1b]1337;File=name=eA==;width=50%;inline=1:R0lGODlhAQABAA==\x07\
+                                 \x1b]1337;File=size=4:R0lGODlhAQABAA==\x07\
+                                 \x1b]1337;File=inline=1:AAAA\x07A");
+        assert_eq!(&output.next().unwrap().unwrap().inner.repr(), "PUT INLINE");
+        assert_eq!(&output.next().unwrap().unwrap().inner.repr(),
+                   "NO FEATURE: ^[]1337;File= download");
+        assert_eq!(&output.next().unwrap().unwrap().inner.repr(), "A");
+    }
+
+    #[test]
+    fn hyperlink() {
+        let mut output = setup(b"\x1b]8;id=1;http://x.org\x1b\\A\x1b]8;;\x1b\\");
+        assert_eq!(&output.next().unwrap().unwrap().inner.repr(), "SET LINK http://x.org");
+        assert_eq!(&output.next().unwrap().unwrap().inner.repr(), "A");
+        assert_eq!(&output.next().unwrap().unwrap().inner.repr(), "END LINK");
+    }
+
+    #[test]
+    fn palette() {
+        let mut output = setup(b"\x1b]4;1;rgb:ff/00/00;2;?\x07\x1b]11;?\x07\x1b]104\x07");
+        assert_eq!(&output.next().unwrap().unwrap().inner.repr(),
+                   "SERIES: SET PALETTE COLOR 1; REPORT PALETTE COLOR 2");
+        assert_eq!(&output.next().unwrap().unwrap().inner.repr(), "REPORT DYNAMIC COLOR 11");
+        assert_eq!(&output.next().unwrap().unwrap().inner.repr(), "RESET PALETTE");
+    }
+
+    #[test]
+    fn clipboard() {
+        let mut output = setup(b"\x1b]52;c;aGVsbG8=\x07\x1b]52;p;?\x07\x1b]52;c;*\x07A");
+        assert_eq!(&output.next().unwrap().unwrap().inner.repr(), "SET CLIPBOARD");
+        assert_eq!(&output.next().unwrap().unwrap().inner.repr(), "QUERY CLIPBOARD");
+        assert_eq!(&output.next().unwrap().unwrap().inner.repr(), "A");
+    }
+
+    #[test]
+    fn shell_integration() {
+        let mut output = setup(b"\x1b]133;A\x07$\x1b]133;B\x07\x1b]133;D;1\x07");
+        assert_eq!(&output.next().unwrap().unwrap().inner.repr(), "MARK PROMPT START");
+        assert_eq!(&output.next().unwrap().unwrap().inner.repr(), "$");
+        assert_eq!(&output.next().unwrap().unwrap().inner.repr(), "MARK COMMAND START");
+        assert_eq!(&output.next().unwrap().unwrap().inner.repr(), "MARK COMMAND END 1");
+    }
+
     #[test]
     fn notty_code() {
         let mut output = setup("A\x1b_[30;8.2.ff.ff.ff\u{9c}\x1b_[19;1;2\u{9c}B".as_bytes());
@@ -360,4 +641,22 @@ mod tests {
         assert_eq!(&output.next().unwrap().unwrap().inner.repr(), "B");
     }
 
+    #[test]
+    fn notty_transfers() {
+        let mut output = setup("\x1b_[10;1#SGVsbG8g\u{9c}\x1b_[10;1#d29ybGQ=\u{9c}\
+                                \x1b_[11;1;40\u{9c}\x1b_[11;2;40\u{9c}\x1b_[40#~B".as_bytes());
+        assert_eq!(&output.next().unwrap().unwrap().inner.repr(), "SET TITLE");
+        assert_eq!(&output.next().unwrap().unwrap().inner.repr(), "REPORT NOTTY ERROR 11 3");
+        assert_eq!(&output.next().unwrap().unwrap().inner.repr(), "REPORT NOTTY ERROR 40 1");
+        assert_eq!(&output.next().unwrap().unwrap().inner.repr(), "B");
+    }
+
+    #[test]
+    fn notty_update_image() {
+        let mut output = setup("\x1b_[16;1.1#aW1hZ2Uvc3ZnK3htbA==#PHN2Zy8+\u{9c}\
+                                \x1b_[16;1.1#dGV4dC9wbGFpbg==#PHN2Zy8+\u{9c}".as_bytes());
+        assert_eq!(&output.next().unwrap().unwrap().inner.repr(), "UPDATE IMAGE");
+        assert_eq!(&output.next().unwrap().unwrap().inner.repr(), "REPORT NOTTY ERROR 16 4");
+    }
+
 }
@@ -13,65 +13,164 @@
 //  
 //  You should have received a copy of the GNU Affero General Public License
 //  along with this program.  If not, see <http://www.gnu.org/licenses/>.
-use base64;
+use std::mem;
+use std::sync::atomic::Ordering::Relaxed;
+use std::vec;
+
+use cfg::ATTACHMENT_LIMIT;
+
+use self::AttachmentError::*;
+
+// The most attachment data a command may be sent with if no limit has been configured.
+const DEFAULT_LIMIT: usize = 0x4000000;
+
+/// An error in the attachments of a notty command, which is reported to the program instead of
+/// applying the command.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum AttachmentError {
+    /// The attachments were not valid base64.
+    BadEncoding,
+    /// The attachments were larger than the configured limit.
+    TooLarge,
+    /// The command completed a transfer which had not been started.
+    UnknownTransfer,
+    /// The attachments were not those the command expects.
+    Invalid,
+}
+
+impl AttachmentError {
+    pub fn code(&self) -> u32 {
+        match *self {
+            BadEncoding     => 1,
+            TooLarge        => 2,
+            UnknownTransfer => 3,
+            Invalid         => 4,
+        }
+    }
+}
 
+/// The attachments of a notty command, which are decoded from base64 as each character arrives
+/// rather than buffered as text.
 pub struct Attachments {
-    data: Vec<String>,
+    data: Vec<Vec<u8>>,
+    len: usize,
+    limit: usize,
+    bits: u32,
+    sextets: u32,
+    error: Option<AttachmentError>,
 }
 
 impl Default for Attachments {
     fn default() -> Attachments {
-        Attachments { data: vec![String::new()] }
+        Attachments {
+            data: vec![Vec::new()],
+            len: 0,
+            limit: limit(),
+            bits: 0,
+            sextets: 0,
+            error: None,
+        }
     }
 }
 
 impl Attachments {
 
     pub fn clear(&mut self) {
-        self.data.truncate(1);
-        self.data.last_mut().unwrap().clear();
-    }
-
-    pub fn iter(&self) -> AttachmentIter {
-        self.into_iter()
+        *self = Attachments::default();
     }
 
+    /// Append a character of the attachments section of a command. Returns `Some(true)` at the
+    /// end of the command, `Some(false)` if the character cannot appear in the section, and
+    /// `None` otherwise. Once an error has occurred, the rest of the data is discarded.
     pub fn append(&mut self, ch: char) -> Option<bool> {
         match ch {
-            '0'...'9' | 'A'...'Z' | 'a'...'z' | '+' | '/' | '=' => {
-                self.data.last_mut().unwrap().push(ch);
-                None
-            }
-            '#'                                                 => {
-                self.data.push(String::new());
-                None
+            'A'...'Z'   => self.push_sextet(ch as u32 - 'A' as u32),
+            'a'...'z'   => self.push_sextet(ch as u32 - 'a' as u32 + 26),
+            '0'...'9'   => self.push_sextet(ch as u32 - '0' as u32 + 52),
+            '+'         => self.push_sextet(62),
+            '/'         => self.push_sextet(63),
+            '='         => self.flush(),
+            '#'         => {
+                self.flush();
+                if self.error.is_none() { self.data.push(Vec::new()); }
             }
-            '\u{9c}'                                            => Some(true),
-            _                                                   => Some(false),
+            '\u{9c}'    => return Some(true),
+            _           => return Some(false),
         }
+        None
     }
 
-}
+    /// The number of bytes decoded so far.
+    pub fn len(&self) -> usize {
+        self.len
+    }
 
-impl<'a> IntoIterator for &'a Attachments {
-    type Item = Vec<u8>;
-    type IntoIter = AttachmentIter<'a>;
-    fn into_iter(self) -> AttachmentIter<'a> {
-        AttachmentIter {
-            attachments: self.data.iter(),
+    pub fn set_limit(&mut self, limit: usize) {
+        self.limit = limit;
+    }
+
+    /// The first error in the attachments, if there has been one.
+    pub fn error(&self) -> Option<AttachmentError> {
+        self.error
+    }
+
+    pub fn fail(&mut self, error: AttachmentError) {
+        if self.error.is_none() {
+            self.error = Some(error);
+            self.data = vec![Vec::new()];
         }
     }
-}
 
-pub struct AttachmentIter<'a>{
-    attachments: <&'a Vec<String> as IntoIterator>::IntoIter,
-}
+    /// Take the decoded attachments, or the error which occurred while decoding them.
+    pub fn take(&mut self) -> Result<vec::IntoIter<Vec<u8>>, AttachmentError> {
+        self.flush();
+        let attachments = mem::replace(self, Attachments::default());
+        match attachments.error {
+            Some(error) => Err(error),
+            None        => Ok(attachments.data.into_iter()),
+        }
+    }
 
-impl<'a> Iterator for AttachmentIter<'a> {
-    type Item = Vec<u8>;
-    fn next(&mut self) -> Option<Vec<u8>> {
-        self.attachments.next().and_then(|data| base64::u8de(data.as_bytes()).ok())
+    fn push_sextet(&mut self, sextet: u32) {
+        self.bits = self.bits << 6 | sextet;
+        self.sextets += 1;
+        if self.sextets == 4 {
+            let bits = self.bits;
+            self.push_bytes(&[(bits >> 16) as u8, (bits >> 8) as u8, bits as u8]);
+            self.bits = 0;
+            self.sextets = 0;
+        }
     }
+
+    // A group of fewer than four characters at the end of an attachment holds fewer than three
+    // bytes, whether or not it is padded.
+    fn flush(&mut self) {
+        let bits = self.bits;
+        match self.sextets {
+            0   => { }
+            1   => self.fail(BadEncoding),
+            2   => self.push_bytes(&[(bits >> 4) as u8]),
+            _   => self.push_bytes(&[(bits >> 10) as u8, (bits >> 2) as u8]),
+        }
+        self.bits = 0;
+        self.sextets = 0;
+    }
+
+    fn push_bytes(&mut self, bytes: &[u8]) {
+        if self.error.is_some() { return }
+        self.len += bytes.len();
+        if self.len > self.limit {
+            self.fail(TooLarge);
+        } else {
+            self.data.last_mut().unwrap().extend_from_slice(bytes);
+        }
+    }
+
+}
+
+/// The most attachment data a command, or all the transfers in progress together, may hold.
+pub fn limit() -> usize {
+    match ATTACHMENT_LIMIT.load(Relaxed) { 0 => DEFAULT_LIMIT, n => n }
 }
 
 #[cfg(test)]
@@ -88,14 +187,10 @@ mod tests {
         "Happy familie are all alike; every unhappy family is unhappy in its own way.",
         "--A little more test data.--"
     ];
-    
-    #[test]
-    fn iterates() {
-        let attachments = Attachments {
-            data: BLOCKS.iter().map(|s| base64::encode(s).unwrap()).collect()
-        };
-        for (attachment, block) in (&attachments).into_iter().zip(BLOCKS) {
-            assert_eq!(attachment, block.as_bytes());
+
+    fn append_str(attachments: &mut Attachments, data: &str) {
+        for ch in data.chars() {
+            assert_eq!(attachments.append(ch), None);
         }
     }
 
@@ -103,22 +198,41 @@ mod tests {
     fn appends() {
         let mut attachments = Attachments::default();
         for data in BLOCKS.iter().map(|s| base64::encode(s).unwrap()) {
-            for ch in data.chars() {
-                assert_eq!(attachments.append(ch), None);
-            }
+            append_str(&mut attachments, &data);
             assert_eq!(attachments.append('#'), None);
         }
         assert_eq!(attachments.append('\u{9c}'), Some(true));
-        for (attachment, block) in (&attachments).into_iter().zip(BLOCKS) {
+        for (attachment, block) in attachments.take().unwrap().zip(BLOCKS) {
             assert_eq!(attachment, block.as_bytes());
         }
     }
 
+    #[test]
+    fn decodes_across_chunks() {
+        let mut attachments = Attachments::default();
+        let data = base64::encode(BLOCKS[2]).unwrap();
+        let (first, second) = data.split_at(7);
+        append_str(&mut attachments, first);
+        assert_eq!(attachments.append('\u{9c}'), Some(true));
+        append_str(&mut attachments, second);
+        assert_eq!(attachments.take().unwrap().next().unwrap(), BLOCKS[2].as_bytes());
+    }
+
+    #[test]
+    fn enforces_limit() {
+        let mut attachments = Attachments { limit: 8, ..Attachments::default() };
+        append_str(&mut attachments, &base64::encode(BLOCKS[0]).unwrap());
+        assert_eq!(attachments.error(), Some(AttachmentError::TooLarge));
+        assert_eq!(attachments.take().err(), Some(AttachmentError::TooLarge));
+    }
+
     #[test]
     fn wont_append_invalid_chars() {
         let mut attachments = Attachments::default();
         assert_eq!(attachments.append('~'), Some(false));
         assert_eq!(attachments.data.last().unwrap().len(), 0);
+        append_str(&mut attachments, "A");
+        assert_eq!(attachments.take().err(), Some(AttachmentError::BadEncoding));
     }
 
 }
@@ -14,6 +14,8 @@
 //  You should have received a copy of the GNU Affero General Public License
 //  along with this program.  If not, see <http://www.gnu.org/licenses/>.
 use std::cell::RefCell;
+use std::collections::HashMap;
+use std::mem;
 use std::str::FromStr;
 
 use mime::{Mime, SubLevel};
@@ -24,152 +26,245 @@ use datatypes::args::*;
 
 mod attachment;
 
+pub use self::attachment::AttachmentError;
+
 use self::attachment::Attachments;
+use self::attachment::AttachmentError::*;
+
+// The most transfers which may be in progress at once.
+const MAX_TRANSFERS: usize = 16;
 
+/// A notty command being parsed. Large attachments can be sent in several chunks as a
+/// transfer, with the `0x10` command whose attachments continue those of the transfer with
+/// the given id. The `0x11` command then applies the command in its remaining arguments with
+/// the attachments of the transfer. All the transfers in progress share one limit on the
+/// data they hold, which is the configured attachment limit unless `limit` is set.
 #[derive(Default)]
 pub struct NottyData {
     pub args: String,
-    pub attachments: Attachments,
+    attachments: Attachments,
+    transfers: HashMap<u64, Attachments>,
+    transfer: Option<u64>,
+    limit: usize,
 }
 
 impl NottyData {
 
-    pub fn parse(&self) -> Option<Command> {
+    /// Begin the attachments section of the current command.
+    pub fn start_attachments(&mut self) {
         let mut args = self.args.split(';');
-        match u32::decode(args.next(), None) {
-            Some(0x14)  => {
-                let w = match u32::decode(args.next(), None) { Some(w) => w, None => return None };
-                let h = match u32::decode(args.next(), None) { Some(h) => h, None => return None };
-                let p = MediaPosition::decode(args.next(), Some(MediaPosition::default())).unwrap();
-                if let Some((mime, data)) = image(self.attachments.iter()) {
-                    wrap(Some(Put::new_image(data, mime, p, w, h)))
-                } else { None }
-            }
-            Some(0x15)  => {
-                let w = match u32::decode(args.next(), None) { Some(w) => w, None => return None };
-                let h = match u32::decode(args.next(), None) { Some(h) => h, None => return None };
-                let p = MediaPosition::decode(args.next(), Some(MediaPosition::default())).unwrap();
-                let coords = Coords::decode(args.next(), Some(Coords {x: 0, y: 0})).unwrap();
-                if let Some((mime, data)) = image(self.attachments.iter()) {
-                    wrap(Some(PutAt::new_image(data, mime, p, w, h, coords)))
-                } else { None }
-            }
-            Some(0x18)  => {
-                wrap(Movement::decode(args.next(), Some(To(Right, 1, true))).map(Move::new))
-            }
-            Some(0x19)  => {
-                let dir = Direction::decode(args.next(), Some(Down)).unwrap();
-                let n = u32::decode(args.next(), Some(1)).unwrap();
-                wrap(Some(ScrollScreen::new(dir, n)))
-            }
-            Some(0x20)  => {
-                wrap(Area::decode(args.next(), Some(CursorCell)).map(Erase::new))
-            }
-            Some(0x21)  => {
-                wrap(u32::decode(args.next(), Some(1)).map(RemoveChars::new))
-            }
-            Some(0x22)  => {
-                let n = u32::decode(args.next(), Some(1)).unwrap();
-                wrap(bool::decode(args.next(), Some(true)).map(|f| RemoveRows::new(n, f)))
-            }
-            Some(0x26)  => {
-                wrap(u32::decode(args.next(), Some(1)).map(InsertBlank::new))
-            }
-            Some(0x27)  => {
-                let n = u32::decode(args.next(), Some(1)).unwrap();
-                wrap(bool::decode(args.next(), Some(true)).map(|f| InsertRows::new(n, f)))
+        if u32::decode(args.next(), None) != Some(0x10) { return }
+        let id = match u64::decode(args.next(), None) { Some(id) => id, None => return };
+        if self.transfers.len() >= MAX_TRANSFERS && !self.transfers.contains_key(&id) {
+            // Starting another transfer could use too much memory.
+            self.attachments.fail(TooLarge);
+        } else {
+            // This transfer may only hold what the others have left of the limit.
+            let used = self.transfers.iter().filter(|&(&other, _)| other != id)
+                           .map(|(_, transfer)| transfer.len()).sum::<usize>();
+            let limit = match self.limit { 0 => attachment::limit(), n => n };
+            let transfer = self.transfers.entry(id).or_insert_with(Attachments::default);
+            transfer.set_limit(limit.saturating_sub(used));
+            self.transfer = Some(id);
+        }
+    }
+
+    pub fn append(&mut self, ch: char) -> Option<bool> {
+        if let Some(id) = self.transfer {
+            if let Some(attachments) = self.transfers.get_mut(&id) {
+                return attachments.append(ch)
             }
-            Some(0x30)  => {
-                match Style::decode(args.next(), None) {
-                    Some(style) => wrap(Some(SetTextStyle(style))),
-                    None        => wrap(Some(DefaultTextStyle)),
+        }
+        self.attachments.append(ch)
+    }
+
+    /// Report an error in the current command, abandoning its transfer if it has one.
+    pub fn fail(&mut self, error: AttachmentError) -> Option<Command> {
+        if let Some(id) = self.transfer { self.transfers.remove(&id); }
+        report(u32::decode(self.args.split(';').next(), None).unwrap_or(0), error)
+    }
+
+    pub fn parse(&mut self) -> Option<Command> {
+        let args = mem::replace(&mut self.args, String::new());
+        let mut attachments = mem::replace(&mut self.attachments, Attachments::default());
+        let mut parts = args.splitn(3, ';');
+        match u32::decode(parts.next(), None) {
+            Some(0x10)  => {
+                if let Err(error) = attachments.take() { return report(0x10, error) }
+                let id = match self.transfer { Some(id) => id, None => return None };
+                match self.transfers.get(&id).and_then(|transfer| transfer.error()) {
+                    Some(error) => {
+                        self.transfers.remove(&id);
+                        report(0x10, error)
+                    }
+                    None        => None,
                 }
             }
-            Some(0x31)  => {
-                match Style::decode(args.next(), None) {
-                    Some(style) => wrap(Some(SetCursorStyle(style))),
-                    None        => wrap(Some(DefaultCursorStyle)),
+            Some(0x11)  => {
+                let transfer = u64::decode(parts.next(), None).and_then(|id| {
+                    self.transfers.remove(&id)
+                });
+                match transfer {
+                    Some(transfer)  => command(parts.next().unwrap_or(""), transfer),
+                    None            => report(0x11, UnknownTransfer),
                 }
             }
-            Some(0x32)  => {
-                let area = Area::decode(args.next(), Some(WholeScreen)).unwrap();
-                match Style::decode(args.next(), None) {
-                    Some(style) => wrap(Some(SetStyleInArea(area, style))),
-                    None        => wrap(Some(DefaultStyleInArea(area))),
+            _           => command(&args, attachments),
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.args.clear();
+        self.attachments.clear();
+        self.transfer = None;
+    }
+
+}
+
+fn command(args: &str, mut attachments: Attachments) -> Option<Command> {
+    let mut args = args.split(';');
+    let opcode = u32::decode(args.next(), None);
+    let mut attachments = match attachments.take() {
+        Ok(attachments) => attachments,
+        Err(error)      => return report(opcode.unwrap_or(0), error),
+    };
+    match opcode {
+        Some(0x14)  => {
+            let w = match u32::decode(args.next(), None) { Some(w) => w, None => return None };
+            let h = match u32::decode(args.next(), None) { Some(h) => h, None => return None };
+            let p = MediaPosition::decode(args.next(), Some(MediaPosition::default())).unwrap();
+            if let Some((mime, data)) = image(attachments) {
+                wrap(Some(Put::new_image(data, mime, p, w, h)))
+            } else { report(0x14, Invalid) }
+        }
+        Some(0x15)  => {
+            let w = match u32::decode(args.next(), None) { Some(w) => w, None => return None };
+            let h = match u32::decode(args.next(), None) { Some(h) => h, None => return None };
+            let p = MediaPosition::decode(args.next(), Some(MediaPosition::default())).unwrap();
+            let coords = Coords::decode(args.next(), Some(Coords {x: 0, y: 0})).unwrap();
+            if let Some((mime, data)) = image(attachments) {
+                wrap(Some(PutAt::new_image(data, mime, p, w, h, coords)))
+            } else { report(0x15, Invalid) }
+        }
+        Some(0x16)  => {
+            let coords = Coords::decode(args.next(), None);
+            match (coords, image(attachments)) {
+                (Some(coords), Some((mime, data)))  => {
+                    wrap(Some(UpdateImage::new(data, mime, coords)))
                 }
+                (Some(_), None)                     => report(0x16, Invalid),
+                (None, _)                           => None,
             }
-            Some(0x40)  => {
-                self.attachments.iter().next().and_then(|data| String::from_utf8(data).ok())
-                .and_then(|title| {
-                    wrap(Some(SetTitle(RefCell::new(Some(title)))))
-                })
-            }
-            Some(0x50)  => {
-                let coords = Coords::decode(args.next(), None).unwrap();
-                self.attachments.iter().next().and_then(|data| String::from_utf8(data).ok())
-                .and_then(|string| {
-                    wrap(Some(AddToolTip(coords, RefCell::new(Some(String::from(string))))))
-                })
+        }
+        Some(0x18)  => {
+            wrap(Movement::decode(args.next(), Some(To(Right, 1, true))).map(Move::new))
+        }
+        Some(0x19)  => {
+            let dir = Direction::decode(args.next(), Some(Down)).unwrap();
+            let n = u32::decode(args.next(), Some(1)).unwrap();
+            wrap(Some(ScrollScreen::new(dir, n)))
+        }
+        Some(0x20)  => {
+            wrap(Area::decode(args.next(), Some(CursorCell)).map(Erase::new))
+        }
+        Some(0x21)  => {
+            wrap(u32::decode(args.next(), Some(1)).map(RemoveChars::new))
+        }
+        Some(0x22)  => {
+            let n = u32::decode(args.next(), Some(1)).unwrap();
+            wrap(bool::decode(args.next(), Some(true)).map(|f| RemoveRows::new(n, f)))
+        }
+        Some(0x26)  => {
+            wrap(u32::decode(args.next(), Some(1)).map(InsertBlank::new))
+        }
+        Some(0x27)  => {
+            let n = u32::decode(args.next(), Some(1)).unwrap();
+            wrap(bool::decode(args.next(), Some(true)).map(|f| InsertRows::new(n, f)))
+        }
+        Some(0x30)  => {
+            match Style::decode(args.next(), None) {
+                Some(style) => wrap(Some(SetTextStyle(style))),
+                None        => wrap(Some(DefaultTextStyle)),
             }
-            Some(0x51)  => {
-                let coords = Coords::decode(args.next(), None).unwrap();
-                self.attachments.iter().map(|data| String::from_utf8(data).ok())
-                .collect::<Option<_>>().and_then(|data| wrap(Some(AddDropDown {
-                    coords: coords,
-                    options: RefCell::new(Some(data)),
-                })))
+        }
+        Some(0x31)  => {
+            match Style::decode(args.next(), None) {
+                Some(style) => wrap(Some(SetCursorStyle(style))),
+                None        => wrap(Some(DefaultCursorStyle)),
             }
-            Some(0x54)  => wrap(Coords::decode(args.next(), None).map(RemoveToolTip)),
-            Some(0x60)  => {
-                let tag = u64::decode(args.next(), None);
-                let offscreen_state = bool::decode(args.next(), None);
-                wrap(Some(PushPanel(tag, offscreen_state)))
+        }
+        Some(0x32)  => {
+            let area = Area::decode(args.next(), Some(WholeScreen)).unwrap();
+            match Style::decode(args.next(), None) {
+                Some(style) => wrap(Some(SetStyleInArea(area, style))),
+                None        => wrap(Some(DefaultStyleInArea(area))),
             }
-            Some(0x61)  => wrap(Some(PopPanel(u64::decode(args.next(), None)))),
-            Some(0x62)  => {
-                let l_tag = u64::decode(args.next(), None);
-                let r_tag = u64::decode(args.next(), None);
-                let kind = SplitKind::decode(args.next(), None);
-                let save = SaveGrid::decode(args.next(), Some(SaveGrid::Left));
-                let rule = ResizeRule::decode(args.next(), Some(ResizeRule::Percentage));
-                let split_tag = u64::decode(args.next(), None);
-                let offscreen_state = bool::decode(args.next(), Some(true));
-                match (l_tag, r_tag, kind) {
-                    (Some(l_tag), Some(r_tag), Some(kind)) => {
-                        wrap(Some(SplitPanel::new(l_tag, r_tag, kind, save, rule,
-                                                  split_tag, offscreen_state)))
-                    }
-                    _ => None
+        }
+        Some(0x40)  => {
+            attachments.next().and_then(|data| String::from_utf8(data).ok())
+            .and_then(|title| {
+                wrap(Some(SetTitle(RefCell::new(Some(title)))))
+            }).or_else(|| report(0x40, Invalid))
+        }
+        Some(0x50)  => {
+            let coords = Coords::decode(args.next(), None).unwrap();
+            attachments.next().and_then(|data| String::from_utf8(data).ok())
+            .and_then(|string| {
+                wrap(Some(AddToolTip(coords, RefCell::new(Some(String::from(string))))))
+            }).or_else(|| report(0x50, Invalid))
+        }
+        Some(0x51)  => {
+            let coords = Coords::decode(args.next(), None).unwrap();
+            attachments.map(|data| String::from_utf8(data).ok())
+            .collect::<Option<_>>().and_then(|data| wrap(Some(AddDropDown {
+                coords: coords,
+                options: RefCell::new(Some(data)),
+            }))).or_else(|| report(0x51, Invalid))
+        }
+        Some(0x54)  => wrap(Coords::decode(args.next(), None).map(RemoveToolTip)),
+        Some(0x60)  => {
+            let tag = u64::decode(args.next(), None);
+            let offscreen_state = bool::decode(args.next(), None);
+            wrap(Some(PushPanel(tag, offscreen_state)))
+        }
+        Some(0x61)  => wrap(Some(PopPanel(u64::decode(args.next(), None)))),
+        Some(0x62)  => {
+            let l_tag = u64::decode(args.next(), None);
+            let r_tag = u64::decode(args.next(), None);
+            let kind = SplitKind::decode(args.next(), None);
+            let save = SaveGrid::decode(args.next(), Some(SaveGrid::Left));
+            let rule = ResizeRule::decode(args.next(), Some(ResizeRule::Percentage));
+            let split_tag = u64::decode(args.next(), None);
+            let offscreen_state = bool::decode(args.next(), Some(true));
+            match (l_tag, r_tag, kind) {
+                (Some(l_tag), Some(r_tag), Some(kind)) => {
+                    wrap(Some(SplitPanel::new(l_tag, r_tag, kind, save, rule,
+                                              split_tag, offscreen_state)))
                 }
+                _ => None
             }
-            Some(0x63)  => {
-                let tag = u64::decode(args.next(), None);
-                let save = SaveGrid::decode(args.next(), Some(SaveGrid::Left));
-                if let (Some(tag), Some(save)) = (tag, save) {
-                    wrap(Some(UnsplitPanel::new(save, tag)))
-                } else { None }
-            }
-            Some(0x64)  => {
-                let tag = u64::decode(args.next(), None);
-                let kind = SplitKind::decode(args.next(), None);
-                let rule = ResizeRule::decode(args.next(), Some(ResizeRule::Percentage));
-                if let (Some(tag), Some(kind), Some(rule)) = (tag, kind, rule) {
-                    wrap(Some(AdjustPanelSplit::new(kind, rule, tag)))
-                } else { None }
-            }
-            Some(0x65)  => wrap(Some(RotateSectionDown(u64::decode(args.next(), None)))),
-            Some(0x66)  => wrap(Some(RotateSectionUp(u64::decode(args.next(), None)))),
-            Some(0x67)  => wrap(u64::decode(args.next(), None).map(SwitchActiveSection)),
-            Some(0x80)  => wrap(InputSettings::decode(args.next(), Some(Ansi(false))).map(SetInputMode)),
-            _           => None,
         }
+        Some(0x63)  => {
+            let tag = u64::decode(args.next(), None);
+            let save = SaveGrid::decode(args.next(), Some(SaveGrid::Left));
+            if let (Some(tag), Some(save)) = (tag, save) {
+                wrap(Some(UnsplitPanel::new(save, tag)))
+            } else { None }
+        }
+        Some(0x64)  => {
+            let tag = u64::decode(args.next(), None);
+            let kind = SplitKind::decode(args.next(), None);
+            let rule = ResizeRule::decode(args.next(), Some(ResizeRule::Percentage));
+            if let (Some(tag), Some(kind), Some(rule)) = (tag, kind, rule) {
+                wrap(Some(AdjustPanelSplit::new(kind, rule, tag)))
+            } else { None }
+        }
+        Some(0x65)  => wrap(Some(RotateSectionDown(u64::decode(args.next(), None)))),
+        Some(0x66)  => wrap(Some(RotateSectionUp(u64::decode(args.next(), None)))),
+        Some(0x67)  => wrap(u64::decode(args.next(), None).map(SwitchActiveSection)),
+        Some(0x80)  => wrap(InputSettings::decode(args.next(), Some(Ansi(false))).map(SetInputMode)),
+        _           => None,
     }
-
-    pub fn clear(&mut self) {
-        self.args.clear();
-        self.attachments.clear();
-    }
-
 }
 
 fn image<I: Iterator<Item=Vec<u8>>>(mut attachments: I) -> Option<(Mime, Vec<u8>)> {
@@ -177,14 +272,50 @@ fn image<I: Iterator<Item=Vec<u8>>>(mut attachments: I) -> Option<(Mime, Vec<u8>
         String::from_utf8(data).ok()
     }).and_then(|string| {
         Mime::from_str(&string).ok()
-    }).and_then(|mime| match mime.1 {
-        SubLevel::Gif | SubLevel::Jpeg | SubLevel::Png  => Some(mime),
-        _                                               => None
+    }).and_then(|mime| {
+        let supported = match mime.1 {
+            SubLevel::Gif | SubLevel::Jpeg | SubLevel::Png  => true,
+            SubLevel::Ext(ref ext)                          => ext == "svg+xml" || ext == "apng",
+            _                                               => false,
+        };
+        if supported { Some(mime) } else { None }
     }).and_then(|mime| {
         attachments.next().map(|data| (mime, data))
     })
 }
 
+fn report(opcode: u32, error: AttachmentError) -> Option<Command> {
+    wrap(Some(ReportNottyError(opcode, error.code())))
+}
+
 fn wrap<T: CommandTrait>(cmd: Option<T>) -> Option<Command> {
     cmd.map(|cmd| Command { inner: Box::new(cmd) as Box<CommandTrait> })
 }
+
+#[cfg(test)]
+mod tests {
+
+    use Command;
+    use super::NottyData;
+
+    fn transfer(notty: &mut NottyData, id: u64, data: &str) -> Option<Command> {
+        notty.args.push_str(&format!("10;{:x}", id));
+        notty.start_attachments();
+        for ch in data.chars() { notty.append(ch); }
+        let cmd = notty.parse();
+        notty.clear();
+        cmd
+    }
+
+    #[test]
+    fn transfers_share_limit() {
+        let mut notty = NottyData { limit: 8, ..NottyData::default() };
+        assert!(transfer(&mut notty, 1, "AAAA").is_none());
+        assert!(transfer(&mut notty, 2, "AAAA").is_none());
+        assert!(transfer(&mut notty, 3, "AAAA").is_some());
+        assert!(transfer(&mut notty, 1, "AA==").is_none());
+        assert!(transfer(&mut notty, 2, "AAAA").is_some());
+        assert_eq!(notty.transfers.values().map(|transfer| transfer.len()).sum::<usize>(), 4);
+    }
+
+}
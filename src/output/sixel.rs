@@ -0,0 +1,229 @@
+//  notty is a new kind of terminal emulator.
+//  Copyright (C) 2015 without boats
+//  
+//  This program is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU Affero General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//  
+//  This program is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU Affero General Public License for more details.
+//  
+//  You should have received a copy of the GNU Affero General Public License
+//  along with this program.  If not, see <http://www.gnu.org/licenses/>.
+use std::cmp;
+use std::iter::Peekable;
+use std::str::Chars;
+
+use super::bitmap::Bitmap;
+
+// The largest image a Sixel sequence may draw, to bound the memory it can use.
+const MAX_SIZE: u32 = 4096;
+
+// The colors of the VT340, in percentages of red, green and blue.
+static DEFAULT_COLORS: [(u32, u32, u32); 16] = [
+    (0, 0, 0), (20, 20, 80), (80, 13, 13), (20, 80, 20),
+    (80, 20, 80), (20, 80, 80), (80, 80, 20), (53, 53, 53),
+    (26, 26, 26), (33, 33, 60), (60, 26, 26), (33, 60, 33),
+    (60, 33, 60), (33, 60, 60), (60, 60, 33), (80, 80, 80),
+];
+
+/// Decode the data of a Sixel DCS sequence into a bitmap. The second parameter of the sequence
+/// is 1 if pixels which are not drawn should be transparent.
+pub fn decode(params: &[u32], data: &str) -> Option<Bitmap> {
+    let mut colors = [[0, 0, 0, 0xff]; 256];
+    for (color, &(r, g, b)) in colors.iter_mut().zip(DEFAULT_COLORS.iter()) {
+        *color = rgb(r, g, b);
+    }
+    let background = if params.get(1) == Some(&1) { [0; 4] } else { colors[0] };
+    let mut canvas = Canvas {
+        bitmap: Bitmap::new(0, 0),
+        width: 0,
+        height: 0,
+        background: background,
+    };
+    let mut color = colors[0];
+    let (mut x, mut y) = (0, 0);
+
+    let mut chars = data.chars().peekable();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '"'         => {
+                // Raster attributes: the aspect ratio, which is ignored, and the size.
+                let args = numbers(&mut chars);
+                if let (Some(&w), Some(&h)) = (args.get(2), args.get(3)) {
+                    canvas.reserve(w, h);
+                }
+            }
+            '#'         => {
+                let args = numbers(&mut chars);
+                let n = args.get(0).map_or(0, |&n| n as usize % 256);
+                match (args.get(1), args.get(2), args.get(3), args.get(4)) {
+                    (Some(&1), Some(&h), Some(&l), Some(&s))    => colors[n] = hls(h, l, s),
+                    (Some(&2), Some(&r), Some(&g), Some(&b))    => colors[n] = rgb(r, g, b),
+                    _                                           => (),
+                }
+                color = colors[n];
+            }
+            '!'         => {
+                let count = numbers(&mut chars).get(0).map_or(1, |&n| cmp::max(n, 1));
+                match chars.next() {
+                    Some(ch @ '?'...'~')    => x = canvas.draw(x, y, ch, count, color),
+                    _                       => (),
+                }
+            }
+            '$'         => x = 0,
+            '-'         => {
+                x = 0;
+                y += 6;
+            }
+            '?'...'~'   => x = canvas.draw(x, y, ch, 1, color),
+            _           => (),
+        }
+    }
+
+    canvas.finish()
+}
+
+struct Canvas {
+    bitmap: Bitmap,
+    // The extent of the image which has been drawn; the bitmap may be larger.
+    width: u32,
+    height: u32,
+    background: [u8; 4],
+}
+
+impl Canvas {
+
+    // Draw a sixel `count` times at a position, returning the column after it.
+    fn draw(&mut self, x: u32, y: u32, ch: char, count: u32, color: [u8; 4]) -> u32 {
+        let bits = ch as u32 - '?' as u32;
+        let count = cmp::min(count, MAX_SIZE.saturating_sub(x));
+        let rows = (0..6).filter(|&n| bits & (1 << n) != 0 && y + n < MAX_SIZE).collect::<Vec<_>>();
+        let bottom = rows.last().map_or(0, |&n| y + n + 1);
+        self.width = cmp::max(self.width, x + count);
+        self.height = cmp::max(self.height, bottom);
+        // Grow by doubling, so that images without raster attributes are not copied for every
+        // sixel drawn.
+        if self.width > self.bitmap.width || self.height > self.bitmap.height {
+            let w = cmp::max(self.width, cmp::min(self.bitmap.width * 2, MAX_SIZE));
+            let h = cmp::max(self.height, cmp::min(self.bitmap.height * 2, MAX_SIZE));
+            self.bitmap.grow(w, h, self.background);
+        }
+        for n in rows {
+            for dx in 0..count {
+                self.bitmap.set_pixel(x + dx, y + n, color);
+            }
+        }
+        x + count
+    }
+
+    fn reserve(&mut self, width: u32, height: u32) {
+        let (width, height) = (cmp::min(width, MAX_SIZE), cmp::min(height, MAX_SIZE));
+        self.bitmap.grow(width, height, self.background);
+        self.width = cmp::max(self.width, width);
+        self.height = cmp::max(self.height, height);
+    }
+
+    fn finish(self) -> Option<Bitmap> {
+        if self.width == 0 || self.height == 0 { return None }
+        let Canvas { bitmap, width, height, .. } = self;
+        if bitmap.width == width && bitmap.height == height { return Some(bitmap) }
+        let mut data = Vec::with_capacity((width * height * 4) as usize);
+        for row in bitmap.data.chunks((bitmap.width * 4) as usize).take(height as usize) {
+            data.extend_from_slice(&row[..(width * 4) as usize]);
+        }
+        Some(Bitmap { width: width, height: height, data: data })
+    }
+
+}
+
+// Parse numeric parameters separated by semicolons, leaving the char after them.
+fn numbers(chars: &mut Peekable<Chars>) -> Vec<u32> {
+    let mut args = vec![0];
+    loop {
+        match chars.peek().cloned() {
+            Some(ch @ '0'...'9')    => {
+                let arg = args.last_mut().unwrap();
+                *arg = arg.saturating_mul(10).saturating_add(ch as u32 - '0' as u32);
+            }
+            Some(';')               => args.push(0),
+            _                       => return args,
+        }
+        chars.next();
+    }
+}
+
+fn rgb(r: u32, g: u32, b: u32) -> [u8; 4] {
+    let scale = |n: u32| (cmp::min(n, 100) * 255 / 100) as u8;
+    [scale(r), scale(g), scale(b), 0xff]
+}
+
+// Sixel HLS colors put blue at a hue of 0 degrees, rather than red.
+fn hls(h: u32, l: u32, s: u32) -> [u8; 4] {
+    let (h, l, s) = (((h % 360 + 240) % 360) as f64, cmp::min(l, 100) as f64 / 100.0,
+                     cmp::min(s, 100) as f64 / 100.0);
+    let c = (1.0 - (2.0 * l - 1.0).abs()) * s;
+    let x = c * (1.0 - ((h / 60.0) % 2.0 - 1.0).abs());
+    let m = l - c / 2.0;
+    let (r, g, b) = match (h / 60.0) as u32 {
+        0   => (c, x, 0.0),
+        1   => (x, c, 0.0),
+        2   => (0.0, c, x),
+        3   => (0.0, x, c),
+        4   => (x, 0.0, c),
+        _   => (c, 0.0, x),
+    };
+    let scale = |n: f64| ((n + m) * 255.0).round() as u8;
+    [scale(r), scale(g), scale(b), 0xff]
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::decode;
+
+    #[test]
+    fn pixels() {
+        // Two columns in red: the first with only the top pixel, the second fully drawn.
+        let bitmap = decode(&[0, 1], "#1;2;100;0;0@~").unwrap();
+        assert_eq!((bitmap.width, bitmap.height), (2, 6));
+        assert_eq!(&bitmap.data[..8], &[0xff, 0, 0, 0xff, 0xff, 0, 0, 0xff]);
+        assert_eq!(&bitmap.data[8..16], &[0, 0, 0, 0, 0xff, 0, 0, 0xff]);
+    }
+
+    #[test]
+    fn repeat_and_newline() {
+        let bitmap = decode(&[0, 1], "#2;2;0;100;0!3~-@").unwrap();
+        assert_eq!((bitmap.width, bitmap.height), (3, 7));
+        assert_eq!(&bitmap.data[6 * 12..6 * 12 + 8], &[0, 0xff, 0, 0xff, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn carriage_return() {
+        let bitmap = decode(&[0, 1], "#1;2;100;0;0@$#2;2;0;0;100A").unwrap();
+        assert_eq!((bitmap.width, bitmap.height), (1, 2));
+        assert_eq!(&bitmap.data, &[0xff, 0, 0, 0xff, 0, 0, 0xff, 0xff]);
+    }
+
+    #[test]
+    fn raster_attributes() {
+        let bitmap = decode(&[0, 0], "\"1;1;4;3").unwrap();
+        assert_eq!((bitmap.width, bitmap.height), (4, 3));
+        assert_eq!(&bitmap.data[..4], &[0, 0, 0, 0xff]);
+    }
+
+    #[test]
+    fn large_hue() {
+        let bitmap = decode(&[0, 1], "#1;1;4294967295;50;100~").unwrap();
+        assert_eq!((bitmap.width, bitmap.height), (1, 6));
+    }
+
+    #[test]
+    fn empty() {
+        assert_eq!(decode(&[], ""), None);
+    }
+
+}
@@ -0,0 +1,93 @@
+//  notty is a new kind of terminal emulator.
+//  Copyright (C) 2015 without boats
+//  
+//  This program is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU Affero General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//  
+//  This program is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU Affero General Public License for more details.
+//  
+//  You should have received a copy of the GNU Affero General Public License
+//  along with this program.  If not, see <http://www.gnu.org/licenses/>.
+use std::time::{Duration, Instant};
+
+/// How long the screen flashes when the visual bell rings.
+const FLASH_MILLIS: u64 = 150;
+
+/// What the terminal does when a program rings the bell.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct BellSettings {
+    /// Pass the bell on to the embedding application with `TerminalEvents::bell`.
+    pub audible: bool,
+    /// Flash the screen.
+    pub visual: bool,
+    /// Mark the terminal as wanting the user's attention.
+    pub urgent: bool,
+}
+
+impl Default for BellSettings {
+    fn default() -> BellSettings {
+        BellSettings { audible: true, visual: false, urgent: true }
+    }
+}
+
+/// The transient state left behind by the bell.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct BellState {
+    flash: Option<Instant>,
+    urgent: bool,
+}
+
+impl BellState {
+
+    /// Ring the bell, returning true if the embedding application should be told about it.
+    pub fn ring(&mut self, settings: BellSettings) -> bool {
+        if settings.visual { self.flash = Some(Instant::now()); }
+        if settings.urgent { self.urgent = true; }
+        settings.audible
+    }
+
+    /// True while the screen should be drawn flashed.
+    pub fn is_flashing(&self) -> bool {
+        self.flash.map_or(false, |start| start.elapsed() < Duration::from_millis(FLASH_MILLIS))
+    }
+
+    pub fn is_urgent(&self) -> bool {
+        self.urgent
+    }
+
+    pub fn clear_urgent(&mut self) {
+        self.urgent = false;
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn ring() {
+        let mut state = BellState::default();
+        assert!(state.ring(BellSettings::default()));
+        assert!(!state.is_flashing());
+        assert!(state.is_urgent());
+        state.clear_urgent();
+        assert!(!state.is_urgent());
+    }
+
+    #[test]
+    fn visual_bell() {
+        let mut state = BellState::default();
+        let settings = BellSettings { audible: false, visual: true, urgent: false };
+        assert!(!state.ring(settings));
+        assert!(state.is_flashing());
+        assert!(!state.is_urgent());
+    }
+
+}
@@ -0,0 +1,439 @@
+//  notty is a new kind of terminal emulator.
+//  Copyright (C) 2015 without boats
+//  
+//  This program is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU Affero General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//  
+//  This program is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU Affero General Public License for more details.
+//  
+//  You should have received a copy of the GNU Affero General Public License
+//  along with this program.  If not, see <http://www.gnu.org/licenses/>.
+use std::time::Duration;
+
+// Browsers show frames with delays this short for 100 milliseconds, and so many GIFs depend on
+// it that the terminal does the same.
+const MIN_DELAY: u32 = 20;
+const SHORT_DELAY: u32 = 100;
+
+const PNG_SIGNATURE: &'static [u8] = b"\x89PNG\r\n\x1a\n";
+
+/// The frames of an animated GIF or PNG. The frames are not decoded; each can be copied out of
+/// the data of the image as an image of its own, which is drawn over the frames before it.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct Animation {
+    format: Format,
+    /// The size of the whole animation in pixels.
+    pub width: u32,
+    pub height: u32,
+    /// How many times the animation plays, or 0 if it repeats forever.
+    pub loops: u32,
+    pub frames: Vec<Frame>,
+    // The parts of the data which are copied into the image of every frame.
+    header: Vec<(usize, usize)>,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+enum Format {
+    Gif,
+    Png,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct Frame {
+    /// The rectangle of the animation this frame is drawn over.
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+    /// How long the frame is shown, in milliseconds.
+    pub delay: u32,
+    pub dispose: Dispose,
+    pub blend: Blend,
+    // The parts of the data which hold this frame.
+    parts: Vec<(usize, usize)>,
+}
+
+/// What happens to the rectangle of a frame before the next frame is drawn.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum Dispose {
+    /// The frame is left in place.
+    Keep,
+    /// The rectangle is cleared to transparency.
+    Background,
+    /// The rectangle is restored to what it was before the frame was drawn.
+    Previous,
+}
+
+/// How a frame is drawn over the frames before it.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum Blend {
+    /// The frame replaces its rectangle.
+    Source,
+    /// The frame is composited over its rectangle.
+    Over,
+}
+
+impl Animation {
+    /// Read the frames of an animated GIF or PNG. Images which are not animated, or which cannot
+    /// be read, have no animation.
+    pub fn parse(data: &[u8]) -> Option<Animation> {
+        let animation = if data.starts_with(b"GIF8") {
+            parse_gif(data)
+        } else if data.starts_with(PNG_SIGNATURE) {
+            parse_png(data)
+        } else { None };
+        animation.and_then(|animation| {
+            if animation.frames.len() > 1 { Some(animation) } else { None }
+        })
+    }
+
+    /// A complete image of a single frame, which the data of the animation was parsed from.
+    pub fn frame_image(&self, data: &[u8], n: usize) -> Option<Vec<u8>> {
+        let frame = match self.frames.get(n) { Some(frame) => frame, None => return None };
+        let mut image = vec![];
+        match self.format {
+            Format::Gif => {
+                // The logical screen is shrunk to the frame and the frame moved to its corner.
+                let (start, end) = self.header[0];
+                image.extend_from_slice(&data[start..start + 6]);
+                push_u16_le(&mut image, frame.width);
+                push_u16_le(&mut image, frame.height);
+                image.extend_from_slice(&data[start + 10..end]);
+                let (start, end) = frame.parts[0];
+                let descriptor = frame.parts[1].0;
+                image.extend_from_slice(&data[start..descriptor + 1]);
+                image.extend_from_slice(&[0, 0, 0, 0]);
+                image.extend_from_slice(&data[descriptor + 5..end]);
+                image.push(0x3b);
+            }
+            Format::Png => {
+                // The header is rewritten to the size of the frame, and the frame's data chunks
+                // are renamed to the IDAT chunks of an ordinary PNG.
+                image.extend_from_slice(PNG_SIGNATURE);
+                let (start, end) = self.header[0];
+                let mut ihdr = vec![];
+                push_u32_be(&mut ihdr, frame.width);
+                push_u32_be(&mut ihdr, frame.height);
+                ihdr.extend_from_slice(&data[start + 8..end]);
+                push_chunk(&mut image, b"IHDR", &ihdr);
+                for &(start, end) in &self.header[1..] {
+                    image.extend_from_slice(&data[start..end]);
+                }
+                for &(start, end) in &frame.parts {
+                    push_chunk(&mut image, b"IDAT", &data[start..end]);
+                }
+                push_chunk(&mut image, b"IEND", &[]);
+            }
+        }
+        Some(image)
+    }
+
+    /// The frame which is showing after the animation has played for this long.
+    pub fn frame_at(&self, elapsed: Duration) -> usize {
+        self.position(elapsed).0
+    }
+
+    /// How long until the frame showing after the animation has played for this long changes,
+    /// or `None` if the animation has finished.
+    pub fn next_change(&self, elapsed: Duration) -> Option<Duration> {
+        match self.position(elapsed) {
+            (_, Some(remaining))    => Some(Duration::from_millis(remaining as u64)),
+            (_, None)               => None,
+        }
+    }
+
+    // The frame showing at this time, and how many milliseconds remain until it changes.
+    fn position(&self, elapsed: Duration) -> (usize, Option<u32>) {
+        let total = self.frames.iter().map(Frame::duration).sum::<u32>() as u64;
+        let elapsed = elapsed.as_secs() * 1000 + elapsed.subsec_nanos() as u64 / 1000000;
+        if total == 0 || (self.loops != 0 && elapsed >= total * self.loops as u64) {
+            return (self.frames.len() - 1, None)
+        }
+        let mut time = (elapsed % total) as u32;
+        for (n, frame) in self.frames.iter().enumerate() {
+            if time < frame.duration() { return (n, Some(frame.duration() - time)) }
+            time -= frame.duration();
+        }
+        (self.frames.len() - 1, None)
+    }
+}
+
+impl Frame {
+    fn duration(&self) -> u32 {
+        if self.delay < MIN_DELAY { SHORT_DELAY } else { self.delay }
+    }
+}
+
+fn parse_gif(data: &[u8]) -> Option<Animation> {
+    if data.len() < 13 { return None }
+    let mut i = 13;
+    if data[10] & 0x80 != 0 { i += 3 << ((data[10] & 0x07) + 1); }
+    let mut animation = Animation {
+        format: Format::Gif,
+        width: read_u16_le(&data[6..8]),
+        height: read_u16_le(&data[8..10]),
+        loops: 1,
+        frames: vec![],
+        header: vec![(0, i)],
+    };
+    // The graphic control extension which applies to the next frame.
+    let mut control: Option<(usize, u32, Dispose)> = None;
+    loop {
+        match (data.get(i).cloned(), data.get(i + 1).cloned()) {
+            (Some(0x21), Some(0xf9)) if data.len() > i + 8 => {
+                let dispose = match (data[i + 3] >> 2) & 0x07 {
+                    2 => Dispose::Background,
+                    3 => Dispose::Previous,
+                    _ => Dispose::Keep,
+                };
+                control = Some((i, read_u16_le(&data[i + 4..i + 6]) * 10, dispose));
+                i = match skip_sub_blocks(data, i + 2) { Some(i) => i, None => return None };
+            }
+            (Some(0x21), Some(0xff)) if data.len() > i + 18 => {
+                if &data[i + 3..i + 14] == b"NETSCAPE2.0" && data[i + 15] == 1 {
+                    animation.loops = read_u16_le(&data[i + 16..i + 18]);
+                }
+                i = match skip_sub_blocks(data, i + 2) { Some(i) => i, None => return None };
+            }
+            (Some(0x21), Some(_)) => {
+                i = match skip_sub_blocks(data, i + 2) { Some(i) => i, None => return None };
+            }
+            (Some(0x2c), _) if data.len() > i + 10 => {
+                let descriptor = i;
+                i += 10;
+                if data[descriptor + 9] & 0x80 != 0 {
+                    i += 3 << ((data[descriptor + 9] & 0x07) + 1);
+                }
+                i = match skip_sub_blocks(data, i + 1) { Some(i) => i, None => return None };
+                let (start, delay, dispose) = control.take()
+                                                     .unwrap_or((descriptor, 0, Dispose::Keep));
+                animation.frames.push(Frame {
+                    x: read_u16_le(&data[descriptor + 1..descriptor + 3]),
+                    y: read_u16_le(&data[descriptor + 3..descriptor + 5]),
+                    width: read_u16_le(&data[descriptor + 5..descriptor + 7]),
+                    height: read_u16_le(&data[descriptor + 7..descriptor + 9]),
+                    delay: delay,
+                    dispose: dispose,
+                    blend: Blend::Over,
+                    parts: vec![(start, i), (descriptor, descriptor + 10)],
+                });
+            }
+            (Some(0x3b), _) if !animation.frames.is_empty() => return Some(animation),
+            _ => return None,
+        }
+    }
+}
+
+fn parse_png(data: &[u8]) -> Option<Animation> {
+    let mut animation = Animation {
+        format: Format::Png,
+        width: 0,
+        height: 0,
+        loops: 0,
+        frames: vec![],
+        header: vec![],
+    };
+    let mut animated = false;
+    let mut i = PNG_SIGNATURE.len();
+    while data.len() >= i + 12 {
+        let len = read_u32_be(&data[i..i + 4]) as usize;
+        let (start, end) = (i + 8, i + 8 + len);
+        if data.len() < end + 4 { return None }
+        let chunk = &data[start..end];
+        match &data[i + 4..i + 8] {
+            b"IHDR" if len == 13 => {
+                animation.width = read_u32_be(&chunk[0..4]);
+                animation.height = read_u32_be(&chunk[4..8]);
+                animation.header.push((start, end));
+            }
+            b"acTL" if len == 8 => {
+                animated = true;
+                animation.loops = read_u32_be(&chunk[4..8]);
+            }
+            b"fcTL" if len == 26 => {
+                let den = match read_u16_be(&chunk[22..24]) { 0 => 100, den => den };
+                animation.frames.push(Frame {
+                    width: read_u32_be(&chunk[4..8]),
+                    height: read_u32_be(&chunk[8..12]),
+                    x: read_u32_be(&chunk[12..16]),
+                    y: read_u32_be(&chunk[16..20]),
+                    delay: read_u16_be(&chunk[20..22]) * 1000 / den,
+                    dispose: match chunk[24] {
+                        1 => Dispose::Background,
+                        2 => Dispose::Previous,
+                        _ => Dispose::Keep,
+                    },
+                    blend: if chunk[25] == 0 { Blend::Source } else { Blend::Over },
+                    parts: vec![],
+                });
+            }
+            // The default image is only part of the animation if a frame control chunk came
+            // before it.
+            b"IDAT" => if let Some(frame) = animation.frames.last_mut() {
+                frame.parts.push((start, end));
+            },
+            b"fdAT" if len >= 4 => if let Some(frame) = animation.frames.last_mut() {
+                frame.parts.push((start + 4, end));
+            },
+            b"IEND" => break,
+            b"IHDR" | b"acTL" | b"fcTL" | b"fdAT" => return None,
+            _ => if animation.frames.is_empty() && !animation.header.is_empty() {
+                animation.header.push((i, end + 4));
+            },
+        }
+        i = end + 4;
+    }
+    if !animated || animation.header.is_empty() { return None }
+    animation.frames.retain(|frame| !frame.parts.is_empty());
+    Some(animation)
+}
+
+// The index after the sub-blocks of a GIF beginning at this index.
+fn skip_sub_blocks(data: &[u8], mut i: usize) -> Option<usize> {
+    loop {
+        match data.get(i) {
+            Some(&0)    => return Some(i + 1),
+            Some(&len)  => i += len as usize + 1,
+            None        => return None,
+        }
+    }
+}
+
+fn read_u16_le(data: &[u8]) -> u32 {
+    data[0] as u32 | (data[1] as u32) << 8
+}
+
+fn read_u16_be(data: &[u8]) -> u32 {
+    (data[0] as u32) << 8 | data[1] as u32
+}
+
+fn read_u32_be(data: &[u8]) -> u32 {
+    data.iter().take(4).fold(0, |n, &byte| n << 8 | byte as u32)
+}
+
+fn push_u16_le(data: &mut Vec<u8>, n: u32) {
+    data.extend_from_slice(&[n as u8, (n >> 8) as u8]);
+}
+
+fn push_u32_be(data: &mut Vec<u8>, n: u32) {
+    data.extend_from_slice(&[(n >> 24) as u8, (n >> 16) as u8, (n >> 8) as u8, n as u8]);
+}
+
+fn push_chunk(data: &mut Vec<u8>, kind: &[u8], chunk: &[u8]) {
+    push_u32_be(data, chunk.len() as u32);
+    let start = data.len();
+    data.extend_from_slice(kind);
+    data.extend_from_slice(chunk);
+    let crc = crc32(&data[start..]);
+    push_u32_be(data, crc);
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 { 0xedb88320 ^ (crc >> 1) } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::time::Duration;
+
+    use super::*;
+    use super::{PNG_SIGNATURE, push_chunk};
+
+    fn gif() -> Vec<u8> {
+        let mut gif = b"GIF89a\x02\x00\x02\x00\x00\x00\x00".to_vec();
+        gif.extend_from_slice(b"\x21\xff\x0bNETSCAPE2.0\x03\x01\x00\x00\x00");
+        gif.extend_from_slice(b"\x21\xf9\x04\x08\x05\x00\x00\x00");
+        gif.extend_from_slice(b"\x2c\x00\x00\x00\x00\x02\x00\x02\x00\x00\x02\x01\x00\x00");
+        gif.extend_from_slice(b"\x21\xf9\x04\x00\x01\x00\x00\x00");
+        gif.extend_from_slice(b"\x2c\x01\x00\x01\x00\x01\x00\x01\x00\x00\x02\x01\x07\x00");
+        gif.push(0x3b);
+        gif
+    }
+
+    fn png() -> Vec<u8> {
+        let mut png = PNG_SIGNATURE.to_vec();
+        push_chunk(&mut png, b"IHDR", b"\0\0\0\x02\0\0\0\x02\x08\x06\0\0\0");
+        push_chunk(&mut png, b"acTL", b"\0\0\0\x02\0\0\0\x03");
+        push_chunk(&mut png, b"fcTL", b"\0\0\0\0\0\0\0\x02\0\0\0\x02\0\0\0\0\0\0\0\0\
+                                        \0\x01\0\x0a\0\0");
+        push_chunk(&mut png, b"IDAT", b"\x01\x02\x03");
+        push_chunk(&mut png, b"fcTL", b"\0\0\0\x01\0\0\0\x01\0\0\0\x01\0\0\0\x01\0\0\0\x01\
+                                        \0\0\0\0\x01\x01");
+        push_chunk(&mut png, b"fdAT", b"\0\0\0\x02\x04\x05");
+        push_chunk(&mut png, b"IEND", b"");
+        png
+    }
+
+    #[test]
+    fn gif_frames() {
+        let data = gif();
+        let animation = Animation::parse(&data).unwrap();
+        assert_eq!((animation.width, animation.height, animation.loops), (2, 2, 0));
+        assert_eq!(animation.frames.len(), 2);
+        assert_eq!(animation.frames[0].delay, 50);
+        assert_eq!(animation.frames[0].dispose, Dispose::Background);
+        assert_eq!((animation.frames[1].x, animation.frames[1].y), (1, 1));
+        let frame = animation.frame_image(&data, 1).unwrap();
+        let mut expected = b"GIF89a\x01\x00\x01\x00\x00\x00\x00".to_vec();
+        expected.extend_from_slice(b"\x21\xf9\x04\x00\x01\x00\x00\x00");
+        expected.extend_from_slice(b"\x2c\x00\x00\x00\x00\x01\x00\x01\x00\x00\x02\x01\x07\x00");
+        expected.push(0x3b);
+        assert_eq!(frame, expected);
+        assert!(Animation::parse(&frame).is_none());
+    }
+
+    #[test]
+    fn png_frames() {
+        let data = png();
+        let animation = Animation::parse(&data).unwrap();
+        assert_eq!((animation.width, animation.height, animation.loops), (2, 2, 3));
+        assert_eq!(animation.frames.len(), 2);
+        assert_eq!(animation.frames[0].delay, 100);
+        assert_eq!(animation.frames[0].blend, Blend::Source);
+        assert_eq!(animation.frames[1].dispose, Dispose::Background);
+        assert_eq!(animation.frames[1].blend, Blend::Over);
+        let mut expected = PNG_SIGNATURE.to_vec();
+        push_chunk(&mut expected, b"IHDR", b"\0\0\0\x01\0\0\0\x01\x08\x06\0\0\0");
+        push_chunk(&mut expected, b"IDAT", b"\x04\x05");
+        push_chunk(&mut expected, b"IEND", b"");
+        assert_eq!(animation.frame_image(&data, 1).unwrap(), expected);
+    }
+
+    #[test]
+    fn still_images() {
+        let mut png = PNG_SIGNATURE.to_vec();
+        push_chunk(&mut png, b"IHDR", b"\0\0\0\x02\0\0\0\x02\x08\x06\0\0\0");
+        push_chunk(&mut png, b"IDAT", b"\x01\x02\x03");
+        push_chunk(&mut png, b"IEND", b"");
+        assert!(Animation::parse(&png).is_none());
+        assert!(Animation::parse(b"\xff\xd8\xff").is_none());
+    }
+
+    #[test]
+    fn timing() {
+        let animation = Animation::parse(&gif()).unwrap();
+        let ms = Duration::from_millis;
+        assert_eq!(animation.frame_at(ms(20)), 0);
+        assert_eq!(animation.next_change(ms(20)), Some(ms(30)));
+        assert_eq!(animation.frame_at(ms(60)), 1);
+        assert_eq!(animation.frame_at(ms(160)), 0);
+        let animation = Animation::parse(&png()).unwrap();
+        assert_eq!(animation.frame_at(ms(550)), 1);
+        assert_eq!(animation.frame_at(ms(600)), 1);
+        assert_eq!(animation.next_change(ms(600)), None);
+    }
+
+}
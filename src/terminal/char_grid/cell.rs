@@ -13,19 +13,26 @@
 //  
 //  You should have received a copy of the GNU Affero General Public License
 //  along with this program.  If not, see <http://www.gnu.org/licenses/>.
+use std::cmp;
 use std::sync::Arc;
+use std::time::Instant;
 
 use mime::Mime;
+use unicode_width::*;
 
 use datatypes::{Coords, MediaPosition};
+use grapheme_tables::{grapheme_category, GC_Extended_Pictographic};
 use terminal::{UseStyles, Styles};
 
+use super::{Animation, ImageTile, PLACEHOLDER};
+
 use self::CharData::*;
 
 #[derive(Clone, PartialEq, Debug)]
 pub struct CharCell {
     pub styles: UseStyles,
     pub content: CharData,
+    pub link: Option<Arc<Hyperlink>>,
 }
 
 #[derive(Clone, PartialEq, Debug)]
@@ -45,8 +52,76 @@ pub enum CharData {
 
 #[derive(Eq, PartialEq, Hash, Debug)]
 pub struct ImageData {
-    pub data: Vec<u8>,
+    pub data: Arc<Vec<u8>>,
     coords: Coords,
+    animation: Option<Animation>,
+    started: Instant,
+}
+
+impl ImageData {
+    /// Image data placed at a position in a grid. The data itself may be shared with other
+    /// placements of the same image.
+    pub fn new(data: Arc<Vec<u8>>, coords: Coords) -> ImageData {
+        ImageData {
+            animation: Animation::parse(&data),
+            data: data,
+            coords: coords,
+            started: Instant::now(),
+        }
+    }
+
+    /// A placeholder to stand in for this image once its data has been evicted to keep images
+    /// within the memory budget.
+    pub fn placeholder(&self) -> ImageData {
+        ImageData {
+            data: Arc::new(Vec::new()),
+            coords: self.coords,
+            animation: None,
+            started: self.started,
+        }
+    }
+
+    /// Whether this image shares this data, rather than holding an equal copy of it.
+    pub fn has_data(&self, data: &Arc<Vec<u8>>) -> bool {
+        &*self.data as *const Vec<u8> == &**data as *const Vec<u8>
+    }
+
+    pub fn is_placeholder(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// The frames of an animated GIF or PNG.
+    pub fn animation(&self) -> Option<&Animation> {
+        self.animation.as_ref()
+    }
+
+    /// The frame of an animated image which should be drawn now. Animations start playing when
+    /// they are written to the grid.
+    pub fn frame(&self) -> usize {
+        self.animation.as_ref().map_or(0, |animation| animation.frame_at(self.started.elapsed()))
+    }
+
+    /// True if this is an animation which has not finished playing.
+    pub fn is_animating(&self) -> bool {
+        self.animation.as_ref().map_or(false, |animation| {
+            animation.next_change(self.started.elapsed()).is_some()
+        })
+    }
+
+    /// True if this is an SVG, which is rendered at the size of its cells rather than scaled.
+    pub fn is_vector(&self) -> bool {
+        let head = String::from_utf8_lossy(&self.data[..cmp::min(self.data.len(), 1024)]);
+        let head = head.trim_left_matches('\u{feff}').trim_left();
+        head.starts_with("<") && head.contains("<svg")
+    }
+}
+
+/// A hyperlink set with OSC 8. Cells which are part of the same link share the same `Arc`, so
+/// renderers can compare links by pointer to highlight the whole link on hover.
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub struct Hyperlink {
+    pub uri: String,
+    pub id: Option<String>,
 }
 
 impl CharCell {
@@ -55,20 +130,23 @@ impl CharCell {
         CharCell {
             styles: styles,
             content: Empty,
+            link: None,
         }
     }
 
     pub fn character(ch: char, styles: UseStyles) -> CharCell {
         CharCell {
             styles: styles,
-            content: Char(ch)
+            content: Char(ch),
+            link: None,
         }
     }
 
     pub fn grapheme(grapheme: String, styles: UseStyles) -> CharCell {
         CharCell {
             styles: styles,
-            content: Grapheme(grapheme)
+            content: Grapheme(grapheme),
+            link: None,
         }
     }
 
@@ -82,15 +160,13 @@ impl CharCell {
         CharCell {
             styles: styles,
             content: Image {
-                data: Arc::new(ImageData {
-                    data: data,
-                    coords: coords,
-                }),
+                data: Arc::new(ImageData::new(Arc::new(data), coords)),
                 mime: mime,
                 pos: pos,
                 width: width,
                 height: height
-            }
+            },
+            link: None,
         }
     }
 
@@ -98,9 +174,14 @@ impl CharCell {
         CharCell {
             styles: styles,
             content: Extension(coords),
+            link: None,
         }
     }
 
+    pub fn with_link(self, link: Option<Arc<Hyperlink>>) -> CharCell {
+        CharCell { link: link, ..self }
+    }
+
     pub fn extend_by(&mut self, ext: char) -> bool {
         match self.content {
             Char(c)             => {
@@ -117,6 +198,15 @@ impl CharCell {
         }
     }
 
+    /// The number of columns the text in this cell occupies.
+    pub fn text_width(&self) -> u32 {
+        match self.content {
+            Char(c)         => c.width().unwrap_or(1) as u32,
+            Grapheme(ref s) => grapheme_width(s),
+            _               => 1,
+        }
+    }
+
     pub fn repr(&self) -> String {
         match self.content {
             Char(c)         => c.to_string(),
@@ -131,12 +221,43 @@ impl CharCell {
         self.content == Empty
     }
 
+    /// The tile of an image this cell shows, if it was written with a unicode placeholder. The
+    /// tile shown by the cell before it in the row is needed to resolve placeholders which
+    /// leave out their row or column.
+    pub fn image_tile(&self, previous: Option<ImageTile>) -> Option<ImageTile> {
+        match self.content {
+            Char(ch) if ch == PLACEHOLDER   => {
+                ImageTile::parse(&ch.to_string(), self.styles, previous)
+            }
+            Grapheme(ref s)                 => ImageTile::parse(s, self.styles, previous),
+            _                               => None,
+        }
+    }
+
     pub fn is_char_extension(&self) -> bool {
         if let Extension(..) = self.content { true } else { false }
     }
 
 }
 
+// A grapheme is as wide as its widest character, unless it is an emoji: emoji shown as pictures
+// (flags, skin tones, ZWJ sequences and anything followed by VS16) are two columns wide, while
+// VS15 asks for an emoji to be shown as a single column of text.
+fn grapheme_width(grapheme: &str) -> u32 {
+    let is_regional_indicator = |c: char| '\u{1f1e6}' <= c && c <= '\u{1f1ff}';
+    let is_modifier = |c: char| '\u{1f3fb}' <= c && c <= '\u{1f3ff}';
+    let pictographic = grapheme.chars().next().map_or(false, |c| {
+        grapheme_category(c) == GC_Extended_Pictographic
+    });
+    if grapheme.contains('\u{fe0e}') { return 1; }
+    if grapheme.contains('\u{fe0f}')
+        || (pictographic && (grapheme.contains('\u{200d}') || grapheme.chars().any(is_modifier)))
+        || grapheme.chars().filter(|&c| is_regional_indicator(c)).count() > 1 {
+        return 2;
+    }
+    cmp::max(grapheme.chars().filter_map(|c| c.width()).max().unwrap_or(1), 1) as u32
+}
+
 impl Default for CharCell {
     fn default() -> CharCell {
         CharCell::new(UseStyles::Custom(Styles::new()))
@@ -13,16 +13,19 @@
 //
 //  You should have received a copy of the GNU Affero General Public License
 //  along with this program.  If not, see <http://www.gnu.org/licenses/>.
+use std::sync::Arc;
+
 use datatypes::{Coords, Movement, move_within};
 use datatypes::Direction::*;
 use datatypes::Movement::*;
-use terminal::{CharCell, CharData, Grid, Styles, UseStyles};
+use terminal::{CharCell, CharData, Grid, Hyperlink, RowInfo, Styles, UseStyles};
 
 #[derive(Clone)]
 pub struct Cursor {
     pub coords: Coords,
     pub style: Styles,
     pub text_style: UseStyles,
+    pub link: Option<Arc<Hyperlink>>,
 }
 
 impl Cursor {
@@ -32,10 +35,11 @@ impl Cursor {
             coords: Coords::default(),
             style: Styles::new(),
             text_style: UseStyles::default(),
+            link: None,
         }
     }
 
-    pub fn navigate(&mut self, grid: &mut Grid<CharCell>, movement: Movement) {
+    pub fn navigate(&mut self, grid: &mut Grid<CharCell, RowInfo>, movement: Movement) {
         match movement {
             IndexTo(Up, n) | PreviousLine(n) if n > self.coords.y => {
                 let n = n - self.coords.y;
@@ -0,0 +1,99 @@
+//  notty is a new kind of terminal emulator.
+//  Copyright (C) 2015 without boats
+//  
+//  This program is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU Affero General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//  
+//  This program is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU Affero General Public License for more details.
+//  
+//  You should have received a copy of the GNU Affero General Public License
+//  along with this program.  If not, see <http://www.gnu.org/licenses/>.
+use std::path::{Path, PathBuf};
+
+/// The working directory of the program in a grid, as reported with OSC 7.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct WorkingDir {
+    /// The host the directory is on, which is empty if the program did not name one. Programs
+    /// running over ssh will report the remote host.
+    pub host: String,
+    pub path: PathBuf,
+}
+
+impl WorkingDir {
+
+    /// Parse a `file://host/path` url, decoding any percent-encoded bytes in the path.
+    pub fn from_url(url: &str) -> Option<WorkingDir> {
+        if !url.starts_with("file://") { return None }
+        let url = &url[7..];
+        let (host, path) = match url.find('/') {
+            Some(n) => (&url[..n], &url[n..]),
+            None    => return None,
+        };
+        percent_decode(path).map(|path| WorkingDir {
+            host: host.to_owned(),
+            path: PathBuf::from(path),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+}
+
+fn percent_decode(s: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(s.len());
+    let mut iter = s.bytes();
+    while let Some(byte) = iter.next() {
+        if byte == b'%' {
+            let hex = match (iter.next(), iter.next()) {
+                (Some(hi), Some(lo))    => [hi, lo],
+                _                       => return None,
+            };
+            let hex = match ::std::str::from_utf8(&hex) {
+                Ok(hex) => hex,
+                Err(_)  => return None,
+            };
+            match u8::from_str_radix(hex, 16) {
+                Ok(byte)    => bytes.push(byte),
+                Err(_)      => return None,
+            }
+        } else { bytes.push(byte) }
+    }
+    String::from_utf8(bytes).ok()
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::path::Path;
+
+    use super::*;
+
+    #[test]
+    fn from_url() {
+        let dir = WorkingDir::from_url("file://host/home/user/my%20files").unwrap();
+        assert_eq!(dir.host, "host");
+        assert_eq!(dir.path(), Path::new("/home/user/my files"));
+    }
+
+    #[test]
+    fn from_url_without_host() {
+        let dir = WorkingDir::from_url("file:///tmp").unwrap();
+        assert_eq!(dir.host, "");
+        assert_eq!(dir.path(), Path::new("/tmp"));
+    }
+
+    #[test]
+    fn invalid_urls() {
+        assert_eq!(WorkingDir::from_url("http://host/tmp"), None);
+        assert_eq!(WorkingDir::from_url("file://host"), None);
+        assert_eq!(WorkingDir::from_url("file://host/tmp%2"), None);
+    }
+
+}
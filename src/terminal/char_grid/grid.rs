@@ -21,42 +21,47 @@ use std::ops::{Index, IndexMut};
 
 use datatypes::{Coords, Direction, Region};
 
-pub struct Grid<T> {
+/// A grid of cells, with an additional item of data for each row which moves along with the
+/// row as the grid scrolls and grows.
+pub struct Grid<T, R = ()> {
     pub width: usize,
     pub height: usize,
     data: VecDeque<T>,
+    rows: VecDeque<R>,
     rem_x: Option<usize>,
     rem_y: Option<usize>,
 }
 
-impl<T: Clone + Default> Grid<T> {
+impl<T: Clone + Default, R: Clone + Default> Grid<T, R> {
 
-    pub fn new(width: usize, height: usize) -> Grid<T> {
+    pub fn new(width: usize, height: usize) -> Grid<T, R> {
         Grid::constructor(width, height, Some(0), Some(0))
     }
 
-    pub fn with_x_cap(width: usize, height: usize, max_x: usize) -> Grid<T> {
+    pub fn with_x_cap(width: usize, height: usize, max_x: usize) -> Grid<T, R> {
         Grid::constructor(width, height, Some(max_x), Some(0))
     }
 
-    pub fn with_y_cap(width: usize, height: usize, max_y: usize) -> Grid<T> {
+    pub fn with_y_cap(width: usize, height: usize, max_y: usize) -> Grid<T, R> {
         Grid::constructor(width, height, Some(0), Some(max_y))
     }
 
-    pub fn with_x_y_caps(width: usize, height: usize, max_x: usize, max_y: usize) -> Grid<T> {
+    pub fn with_x_y_caps(width: usize, height: usize, max_x: usize, max_y: usize)
+            -> Grid<T, R> {
         Grid::constructor(width, height, Some(max_x), Some(max_y))
     }
 
-    pub fn with_infinite_scroll(width: usize, height: usize) -> Grid<T> {
+    pub fn with_infinite_scroll(width: usize, height: usize) -> Grid<T, R> {
         Grid::constructor(width, height, None, None)
     }
 
     fn constructor(w: usize, h: usize, max_x: Option<usize>, max_y: Option<usize>)
-            -> Grid<T> {
+            -> Grid<T, R> {
         Grid {
             width: w,
             height: h,
             data: iter::repeat(T::default()).take(w * h).collect(),
+            rows: iter::repeat(R::default()).take(h).collect(),
             rem_x: max_x.map(|x| x.saturating_sub(w)),
             rem_y: max_y.map(|y| y.saturating_sub(h)),
         }
@@ -66,6 +71,24 @@ impl<T: Clone + Default> Grid<T> {
         Region::new(0, 0, self.width as u32, self.height as u32)
     }
 
+    pub fn row(&self, y: usize) -> &R {
+        assert!(self.height > y, "{} index outside of y bounds", y);
+        &self.rows[y]
+    }
+
+    pub fn row_mut(&mut self, y: usize) -> &mut R {
+        assert!(self.height > y, "{} index outside of y bounds", y);
+        &mut self.rows[y]
+    }
+
+    pub fn rows(&self) -> <&VecDeque<R> as IntoIterator>::IntoIter {
+        self.rows.iter()
+    }
+
+    pub fn rows_mut(&mut self) -> <&mut VecDeque<R> as IntoIterator>::IntoIter {
+        self.rows.iter_mut()
+    }
+
     pub fn range_inclusive(&self, start: Coords, end: Coords)
             -> iter::Take<iter::Skip<<&VecDeque<T> as IntoIterator>::IntoIter>> {
         assert!(self.width > start.x as usize, "{} outside of x bounds", start.x);
@@ -80,7 +103,9 @@ impl<T: Clone + Default> Grid<T> {
 
     pub fn add_to_top(&mut self, data: Vec<T>) {
         assert!(data.len() % self.width == 0);
-        self.height += data.len() / self.width;
+        let n = data.len() / self.width;
+        self.height += n;
+        for _ in 0..n { self.rows.push_front(R::default()); }
         for item in data {
             self.data.push_front(item);
         }
@@ -88,7 +113,9 @@ impl<T: Clone + Default> Grid<T> {
 
     pub fn add_to_bottom(&mut self, data: Vec<T>) {
         assert!(data.len() % self.width == 0);
-        self.height += data.len() / self.width;
+        let n = data.len() / self.width;
+        self.height += n;
+        for _ in 0..n { self.rows.push_back(R::default()); }
         for item in data {
             self.data.push_back(item);
         }
@@ -97,6 +124,7 @@ impl<T: Clone + Default> Grid<T> {
     pub fn remove_from_top(&mut self, n: usize) -> Vec<T> {
         assert!(n < self.height);
         self.height -= n;
+        self.rows.drain(..n);
         let n = n * self.width;
         self.data.drain(..n).collect()
     }
@@ -104,6 +132,8 @@ impl<T: Clone + Default> Grid<T> {
     pub fn remove_from_bottom(&mut self, n: usize) -> Vec<T> {
         assert!(n < self.height);
         self.height -= n;
+        let height = self.height;
+        self.rows.drain(height..);
         let n = self.data.len() - (n * self.width);
         self.data.drain(n..).collect()
     }
@@ -158,10 +188,10 @@ impl<T: Clone + Default> Grid<T> {
         use datatypes::Direction::*;
         match direction {
             Up if self.rem_y != Some(0)     => self.extend_up(n),
-            Up if n >= self.height          => self.data.clear(),
+            Up if n >= self.height          => { self.data.clear(); self.rows.clear() }
             Up                              => self.shift_up(n),
             Down if self.rem_y != Some(0)   => self.extend_down(n),
-            Down if n >= self.height        => self.data.clear(),
+            Down if n >= self.height        => { self.data.clear(); self.rows.clear() }
             Down                            => self.shift_down(n),
             Left if self.rem_x != Some(0)   => self.extend_left(n),
             Left if n >= self.width         => self.data.clear(),
@@ -181,6 +211,7 @@ impl<T: Clone + Default> Grid<T> {
         for _ in 0..(rem_or_n * self.width) {
             self.data.push_front(T::default());
         }
+        for _ in 0..rem_or_n { self.rows.push_front(R::default()); }
         self.height += rem_or_n;
         if self.rem_y.map_or(false, |y| n > y) {
             let rem = n - self.rem_y.unwrap();
@@ -194,6 +225,7 @@ impl<T: Clone + Default> Grid<T> {
         for _ in 0..(rem_or_n * self.width) {
             self.data.push_back(T::default());
         }
+        for _ in 0..rem_or_n { self.rows.push_back(R::default()); }
         self.height += rem_or_n;
         if self.rem_y.map_or(false, |y| n > y) {
             let rem = n - self.rem_y.unwrap();
@@ -239,6 +271,10 @@ impl<T: Clone + Default> Grid<T> {
             self.data.pop_back();
             self.data.push_front(T::default());
         }
+        for _ in 0..n {
+            self.rows.pop_back();
+            self.rows.push_front(R::default());
+        }
     }
 
     fn shift_down(&mut self, n: usize) {
@@ -246,6 +282,10 @@ impl<T: Clone + Default> Grid<T> {
             self.data.pop_front();
             self.data.push_back(T::default());
         }
+        for _ in 0..n {
+            self.rows.pop_front();
+            self.rows.push_back(R::default());
+        }
     }
 
     fn shift_left(&mut self, n: usize) {
@@ -270,7 +310,7 @@ impl<T: Clone + Default> Grid<T> {
 
 }
 
-impl<T> Index<Coords> for Grid<T> {
+impl<T, R> Index<Coords> for Grid<T, R> {
     type Output = T;
     fn index(&self, idx: Coords) -> &T {
         assert!(self.width > idx.x as usize, "{} index outside of x bounds", idx.x);
@@ -279,7 +319,7 @@ impl<T> Index<Coords> for Grid<T> {
     }
 }
 
-impl<T> IndexMut<Coords> for Grid<T> {
+impl<T, R> IndexMut<Coords> for Grid<T, R> {
     fn index_mut(&mut self, idx: Coords) -> &mut T {
         assert!(self.width > idx.x as usize, "{} index outside of x bounds", idx.x);
         assert!(self.height > idx.y as usize, "{} index outside of y bounds", idx.y);
@@ -287,7 +327,7 @@ impl<T> IndexMut<Coords> for Grid<T> {
     }
 }
 
-impl<'a, T> IntoIterator for &'a Grid<T> {
+impl<'a, T, R> IntoIterator for &'a Grid<T, R> {
     type IntoIter = <&'a VecDeque<T> as IntoIterator>::IntoIter;
     type Item = <&'a VecDeque<T> as IntoIterator>::Item;
     fn into_iter(self) -> Self::IntoIter {
@@ -295,7 +335,7 @@ impl<'a, T> IntoIterator for &'a Grid<T> {
     }
 }
 
-impl<'a, T> IntoIterator for &'a mut Grid<T> {
+impl<'a, T, R> IntoIterator for &'a mut Grid<T, R> {
     type IntoIter = <&'a mut VecDeque<T> as IntoIterator>::IntoIter;
     type Item = <&'a mut VecDeque<T> as IntoIterator>::Item;
     fn into_iter(self) -> Self::IntoIter {
@@ -376,6 +416,18 @@ mod tests {
         }, 9, 8);
     }
 
+    #[test]
+    fn rows_move_with_data() {
+        let mut grid: Grid<i32, i32> = Grid::with_y_cap(8, 8, 10);
+        *grid.row_mut(7) = 1;
+        grid.scroll(4, Down);
+        assert_eq!(grid.rows().count(), 10);
+        assert_eq!(*grid.row(5), 1);
+        grid.remove_from_top(2);
+        assert_eq!(grid.rows().count(), 8);
+        assert_eq!(*grid.row(3), 1);
+    }
+
     #[test]
     fn remove_from_top() {
         run_test(|mut grid, width, _| {
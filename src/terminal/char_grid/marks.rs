@@ -0,0 +1,34 @@
+//  notty is a new kind of terminal emulator.
+//  Copyright (C) 2015 without boats
+//  
+//  This program is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU Affero General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//  
+//  This program is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU Affero General Public License for more details.
+//  
+//  You should have received a copy of the GNU Affero General Public License
+//  along with this program.  If not, see <http://www.gnu.org/licenses/>.
+/// A semantic mark set by shell integration with OSC 133, recording where the parts of a
+/// command begin.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Mark {
+    /// The beginning of the prompt (OSC 133;A).
+    PromptStart,
+    /// The end of the prompt and beginning of the command the user types (OSC 133;B).
+    CommandStart,
+    /// The beginning of the command's output (OSC 133;C).
+    OutputStart,
+    /// The command has finished, with its exit status if the shell reported it (OSC 133;D).
+    CommandEnd(Option<i32>),
+}
+
+impl Mark {
+    pub fn is_command_end(&self) -> bool {
+        if let Mark::CommandEnd(_) = *self { true } else { false }
+    }
+}
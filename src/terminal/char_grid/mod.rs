@@ -13,10 +13,13 @@
 //  
 //  You should have received a copy of the GNU Affero General Public License
 //  along with this program.  If not, see <http://www.gnu.org/licenses/>.
+use std::cmp;
 use std::collections::HashMap;
 use std::ops::Index;
+use std::sync::Arc;
 use std::sync::atomic::Ordering::Relaxed;
 
+use mime::Mime;
 use unicode_width::*;
 
 use cfg::SCROLLBACK;
@@ -25,25 +28,42 @@ use datatypes::Area::*;
 use datatypes::Movement::*;
 use datatypes::Direction::*;
 
+mod animation;
 mod cell;
 mod cursor;
+mod cwd;
 mod grid;
+mod marks;
+mod placeholder;
+mod placement;
+mod row;
 mod styles;
 mod tooltip;
 
-pub use self::cell::{CharCell, CharData, ImageData};
+pub use self::animation::{Animation, Blend, Dispose, Frame};
+pub use self::cell::{CharCell, CharData, Hyperlink, ImageData};
 pub use self::cursor::Cursor;
+pub use self::cwd::WorkingDir;
 pub use self::grid::Grid;
+pub use self::marks::Mark;
+pub use self::placeholder::{ImageTile, PLACEHOLDER};
+pub use self::placement::Placement;
+pub use self::row::RowInfo;
 pub use self::styles::{Styles, UseStyles};
 pub use self::tooltip::Tooltip;
 
 pub struct CharGrid {
-    grid: Grid<CharCell>,
+    grid: Grid<CharCell, RowInfo>,
     cursor: Cursor,
     tooltips: HashMap<Coords, Tooltip>,
     window: Region,
+    cwd: Option<WorkingDir>,
+    keyboard_flags: Vec<u8>,
 }
 
+// The depth of the kitty keyboard protocol's flag stack; the oldest entries are discarded.
+const MAX_KEYBOARD_FLAGS: usize = 16;
+
 impl CharGrid {
     pub fn new(width: u32, height: u32, retain_offscreen_state: bool) -> CharGrid {
         let grid = match (retain_offscreen_state, SCROLLBACK.load(Relaxed)) {
@@ -56,10 +76,13 @@ impl CharGrid {
             cursor: Cursor::new(),
             tooltips: HashMap::new(),
             window: Region::new(0, 0, width, height),
+            cwd: None,
+            keyboard_flags: Vec::new(),
         }
     }
 
     pub fn resize_window(&mut self, region: Region) {
+        let bounds = self.grid.bounds();
         if self.grid_width() < region.width() {
             let n = (region.width() - self.grid_width()) * self.grid_height();
             self.grid.add_to_right(vec![CharCell::default(); n as usize]);
@@ -68,6 +91,7 @@ impl CharGrid {
             let n = (region.height() - self.grid_height()) * self.grid_width();
             self.grid.add_to_bottom(vec![CharCell::default(); n as usize]);
         }
+        if self.grid.bounds() != bounds { self.extend_images(bounds); }
         self.window = Region {
             right: self.window.left + region.width(),
             bottom: self.window.top + region.height(),
@@ -79,22 +103,29 @@ impl CharGrid {
         match data {
             CellData::Char(c)       => {
                 let width = c.width().unwrap() as u32;
-                self.grid[self.cursor.coords] = CharCell::character(c, self.cursor.text_style);
+                self.grid[self.cursor.coords] = CharCell::character(c, self.cursor.text_style)
+                                                    .with_link(self.cursor.link.clone());
                 let bounds = self.grid.bounds();
                 let mut coords = self.cursor.coords;
                 for _ in 1..width {
                     let next_coords = move_within(coords, To(Right, 1, false), bounds);
                     if next_coords == coords { break; } else { coords = next_coords; }
                     self.grid[coords] = CharCell::extension(self.cursor.coords,
-                                                            self.cursor.text_style);
+                                                            self.cursor.text_style)
+                                            .with_link(self.cursor.link.clone());
                 }
                 self.cursor.navigate(&mut self.grid, To(Right, 1, true));
             }
             CellData::ExtensionChar(c)  => {
                 self.cursor.navigate(&mut self.grid, To(Left, 1, true));
-                if !self.grid[self.cursor.coords].extend_by(c) {
+                if self.grid[self.cursor.coords].extend_by(c) {
+                    let origin = self.cursor.coords;
+                    self.fit_grapheme(origin);
+                    self.cursor.navigate(&mut self.grid, To(Right, 1, true));
+                } else {
                     self.cursor.navigate(&mut self.grid, To(Right, 1, true));
-                    self.grid[self.cursor.coords] = CharCell::character(c, self.cursor.text_style);
+                    self.grid[self.cursor.coords] = CharCell::character(c, self.cursor.text_style)
+                                                        .with_link(self.cursor.link.clone());
                     self.cursor.navigate(&mut self.grid, To(Right, 1, true));
                 }
             }
@@ -188,6 +219,10 @@ impl CharGrid {
         self.cursor.text_style = UseStyles::default();
     }
 
+    pub fn set_link(&mut self, link: Option<Arc<Hyperlink>>) {
+        self.cursor.link = link;
+    }
+
     pub fn set_cursor_style(&mut self, style: Style) {
         self.cursor.style.update(style);
     }
@@ -224,6 +259,305 @@ impl CharGrid {
         })
     }
 
+    /// The working directory last reported by the program in this grid.
+    pub fn cwd(&self) -> Option<&WorkingDir> {
+        self.cwd.as_ref()
+    }
+
+    pub fn set_cwd(&mut self, cwd: Option<WorkingDir>) {
+        self.cwd = cwd;
+    }
+
+    /// The current flags of the kitty keyboard protocol in this grid, or 0 if it is not enabled.
+    /// Each grid keeps a stack of its own, so the alternate screen does not disturb the flags
+    /// of the main screen.
+    pub fn keyboard_flags(&self) -> u8 {
+        self.keyboard_flags.last().cloned().unwrap_or(0)
+    }
+
+    /// Push a new set of kitty keyboard protocol flags onto the stack.
+    pub fn push_keyboard_flags(&mut self, flags: u8) {
+        if self.keyboard_flags.len() == MAX_KEYBOARD_FLAGS { self.keyboard_flags.remove(0); }
+        self.keyboard_flags.push(flags);
+    }
+
+    /// Pop `n` sets of kitty keyboard protocol flags from the stack.
+    pub fn pop_keyboard_flags(&mut self, n: u32) {
+        for _ in 0..n { self.keyboard_flags.pop(); }
+    }
+
+    /// Replace the current kitty keyboard protocol flags. Mode 1 sets the flags exactly, mode
+    /// 2 sets the given bits and mode 3 clears them.
+    pub fn set_keyboard_flags(&mut self, flags: u8, mode: u8) {
+        let current = self.keyboard_flags();
+        let flags = match mode {
+            1   => flags,
+            2   => current | flags,
+            3   => current & !flags,
+            _   => return,
+        };
+        match self.keyboard_flags.last_mut() {
+            Some(last)  => *last = flags,
+            None        => self.keyboard_flags.push(flags),
+        }
+    }
+
+    /// Set a shell integration mark at the cursor.
+    pub fn add_mark(&mut self, mark: Mark) {
+        let Coords { x, y } = self.cursor.coords;
+        self.grid.row_mut(y as usize).marks.push((x, mark));
+    }
+
+    /// All of the shell integration marks in the grid, including its scrollback, in order.
+    pub fn marks(&self) -> Vec<(Coords, Mark)> {
+        self.grid.rows().enumerate().flat_map(|(y, row)| {
+            row.marks.iter().map(move |&(x, mark)| (Coords { x: x, y: y as u32 }, mark))
+        }).collect()
+    }
+
+    /// The row of the nearest prompt above the given row.
+    pub fn previous_prompt(&self, row: u32) -> Option<u32> {
+        self.marks().into_iter().rev().find(|&(coords, mark)| {
+            coords.y < row && mark == Mark::PromptStart
+        }).map(|(coords, _)| coords.y)
+    }
+
+    /// The row of the nearest prompt below the given row.
+    pub fn next_prompt(&self, row: u32) -> Option<u32> {
+        self.marks().into_iter().find(|&(coords, mark)| {
+            coords.y > row && mark == Mark::PromptStart
+        }).map(|(coords, _)| coords.y)
+    }
+
+    /// The first and last cells of the output of the most recent command which has finished,
+    /// if it produced any output.
+    pub fn last_command_output(&self) -> Option<(Coords, Coords)> {
+        let marks = self.marks();
+        let end = match marks.iter().rposition(|&(_, mark)| mark.is_command_end()) {
+            Some(idx)   => idx,
+            None        => return None,
+        };
+        let start = match marks[..end].iter().rposition(|&(_, mark)| mark == Mark::OutputStart) {
+            Some(idx)   => marks[idx].0,
+            None        => return None,
+        };
+        let end = match marks[end].0 {
+            Coords { x: 0, y: 0 }   => return None,
+            Coords { x: 0, y }      => Coords { x: self.grid_width() - 1, y: y - 1 },
+            Coords { x, y }         => Coords { x: x - 1, y: y },
+        };
+        if (end.y, end.x) < (start.y, start.x) { None } else { Some((start, end)) }
+    }
+
+    /// Place an image over the grid with its top left corner at the cursor. A placement with
+    /// the same image and placement id as an earlier one replaces it.
+    pub fn place_image(&mut self, placement: Placement) {
+        if placement.id != 0 {
+            let (image, id) = (placement.image, placement.id);
+            self.delete_images(|_, p| p.image == image && p.id == id);
+        }
+        let Coords { x, y } = self.cursor.coords;
+        self.grid.row_mut(y as usize).images.push((x, placement));
+    }
+
+    /// The images placed with their top left corner in the visible part of the grid, with the
+    /// position of that corner relative to the window.
+    pub fn placements(&self) -> Vec<(Coords, &Placement)> {
+        let window = self.window;
+        self.grid.rows().enumerate().flat_map(|(y, row)| {
+            row.images.iter().map(move |&(x, ref placement)| {
+                (Coords { x: x, y: y as u32 }, placement)
+            })
+        }).filter(|&(coords, _)| window.contains(coords)).map(|(coords, placement)| {
+            (Coords { x: coords.x - window.left, y: coords.y - window.top }, placement)
+        }).collect()
+    }
+
+    /// Remove every placement for which the predicate returns true, given the grid position of
+    /// its top left corner. Returns the ids of the images whose placements were removed.
+    pub fn delete_images<F>(&mut self, f: F) -> Vec<u32> where F: Fn(Coords, &Placement) -> bool {
+        let mut deleted = vec![];
+        for (y, row) in self.grid.rows_mut().enumerate() {
+            row.images.retain(|&(x, ref placement)| {
+                if f(Coords { x: x, y: y as u32 }, placement) {
+                    deleted.push(placement.image);
+                    false
+                } else { true }
+            });
+        }
+        deleted
+    }
+
+    /// The data of the image written in the cell at these coordinates of the grid, if any.
+    pub fn image_payload(&self, coords: Coords) -> Option<Arc<Vec<u8>>> {
+        match self.grid[coords].content {
+            CharData::Image { ref data, .. }    => Some(data.data.clone()),
+            _                                   => None,
+        }
+    }
+
+    /// The data of every image which is at least partly inside the window.
+    pub fn visible_images(&self) -> Vec<Arc<Vec<u8>>> {
+        self.visible_image_data().into_iter().map(|data| data.data.clone()).collect()
+    }
+
+    /// True if any image inside the window is an animation which has not finished playing.
+    pub fn is_animating(&self) -> bool {
+        self.visible_image_data().into_iter().any(|data| data.is_animating())
+    }
+
+    /// Replace the data of the image written in the cell at these coordinates of the grid, or
+    /// in the cell the image extends from. Animations restart from their first frame. Returns
+    /// the new data, or `None` if there is no image there or the coordinates are outside the
+    /// grid.
+    pub fn update_image(&mut self, coords: Coords, data: Vec<u8>, mime: Mime)
+            -> Option<Arc<Vec<u8>>> {
+        if !self.grid.bounds().contains(coords) { return None }
+        let coords = match self.grid[coords].content {
+            CharData::Extension(origin) => origin,
+            _                           => coords,
+        };
+        match self.grid[coords].content {
+            CharData::Image { data: ref mut image, mime: ref mut image_mime, .. } => {
+                let payload = Arc::new(data);
+                *image = Arc::new(ImageData::new(payload.clone(), coords));
+                *image_mime = mime;
+                Some(payload)
+            }
+            _ => None,
+        }
+    }
+
+    /// Images written in cells outside the window which cover cells inside it, such as images
+    /// whose first rows have scrolled into the scrollback. Each is given with the coordinates in
+    /// the window of the top left cell of its visible part, and the offset of that cell from
+    /// the cell the image was written in.
+    pub fn partial_images(&self) -> Vec<(Coords, Coords, &CharCell)> {
+        let mut origins: Vec<Coords> = vec![];
+        for y in self.window.top..self.window.bottom {
+            for x in self.window.left..self.window.right {
+                if let CharData::Extension(origin) = self.grid[Coords { x: x, y: y }].content {
+                    if !self.window.contains(origin) && !origins.contains(&origin) {
+                        origins.push(origin);
+                    }
+                }
+            }
+        }
+        // If the cell an image was written in has been removed from the grid, the extension
+        // cells may point at a cell which is not the image, and nothing is drawn.
+        let (window, bounds) = (self.window, self.grid.bounds());
+        origins.into_iter().filter(|&origin| bounds.contains(origin)).filter_map(|origin| {
+            let cell = &self.grid[origin];
+            match cell.content {
+                CharData::Image { width, height, .. } => {
+                    let top_left = Coords {
+                        x: cmp::max(origin.x, window.left),
+                        y: cmp::max(origin.y, window.top),
+                    };
+                    let right = cmp::min(origin.x.saturating_add(width), bounds.right);
+                    let bottom = cmp::min(origin.y.saturating_add(height), bounds.bottom);
+                    if top_left.x >= right || top_left.y >= bottom { return None }
+                    Some((Coords { x: top_left.x - window.left, y: top_left.y - window.top },
+                          Coords { x: top_left.x - origin.x, y: top_left.y - origin.y },
+                          cell))
+                }
+                _ => None,
+            }
+        }).collect()
+    }
+
+    // When the grid grows, images which were clipped by the edge of the grid when they were
+    // written cover the rest of the cells they were written to cover.
+    fn extend_images(&mut self, old_bounds: Region) {
+        let bounds = self.grid.bounds();
+        for y in old_bounds.top..old_bounds.bottom {
+            for x in old_bounds.left..old_bounds.right {
+                let origin = Coords { x: x, y: y };
+                let (width, height, styles) = match self.grid[origin] {
+                    CharCell { content: CharData::Image { width, height, .. }, styles, .. } => {
+                        (width, height, styles)
+                    }
+                    _ => continue,
+                };
+                let right = cmp::min(x.saturating_add(width), bounds.right);
+                let bottom = cmp::min(y.saturating_add(height), bounds.bottom);
+                for coords in CoordsIter::from_region(Region::new(x, y, right, bottom)) {
+                    if !old_bounds.contains(coords) && self.grid[coords].is_empty() {
+                        self.grid[coords] = CharCell::extension(origin, styles);
+                    }
+                }
+            }
+        }
+    }
+
+    /// The ids of the images which placeholder cells inside the window show tiles of.
+    pub fn visible_image_tiles(&self) -> Vec<u32> {
+        let mut images = vec![];
+        for y in self.window.top..self.window.bottom {
+            let mut previous = None;
+            for x in self.window.left..self.window.right {
+                previous = self.grid[Coords { x: x, y: y }].image_tile(previous);
+                if let Some(tile) = previous {
+                    if !images.contains(&tile.image) { images.push(tile.image); }
+                }
+            }
+        }
+        images
+    }
+
+    fn visible_image_data(&self) -> Vec<&Arc<ImageData>> {
+        let mut images = vec![];
+        for y in self.window.top..self.window.bottom {
+            for x in self.window.left..self.window.right {
+                let coords = match self.grid[Coords { x: x, y: y }].content {
+                    CharData::Extension(origin) => origin,
+                    _                           => Coords { x: x, y: y },
+                };
+                if let CharData::Image { ref data, .. } = self.grid[coords].content {
+                    images.push(data);
+                }
+            }
+        }
+        let window = self.window;
+        for (y, row) in self.grid.rows().enumerate() {
+            images.extend(row.images.iter().filter(|&&(_, ref placement)| {
+                y as u32 < window.bottom && (y as u32).saturating_add(placement.height) > window.top
+            }).map(|&(_, ref placement)| &placement.data));
+        }
+        images
+    }
+
+    /// Replace every image whose data is one of these payloads with a placeholder, so that the
+    /// memory of the data can be freed.
+    pub fn evict_images(&mut self, payloads: &[Arc<Vec<u8>>]) {
+        let evicted = |data: &ImageData| payloads.iter().any(|payload| data.has_data(payload));
+        let bounds = self.grid.bounds();
+        for y in bounds.top..bounds.bottom {
+            for x in bounds.left..bounds.right {
+                let coords = Coords { x: x, y: y };
+                if let CharData::Image { ref mut data, .. } = self.grid[coords].content {
+                    if evicted(data) {
+                        let placeholder = data.placeholder();
+                        *data = Arc::new(placeholder);
+                    }
+                }
+            }
+        }
+        for row in self.grid.rows_mut() {
+            for &mut (_, ref mut placement) in &mut row.images {
+                if evicted(&placement.data) {
+                    let placeholder = placement.data.placeholder();
+                    placement.data = Arc::new(placeholder);
+                }
+            }
+        }
+    }
+
+    /// The part of the grid which is visible on screen.
+    pub fn window(&self) -> Region {
+        self.window
+    }
+
     pub fn grid_width(&self) -> u32 {
         self.grid.width as u32
     }
@@ -240,16 +574,39 @@ impl CharGrid {
         self.tooltips.get_mut(&coords)
     }
 
-    fn in_area<F>(&mut self, area: Area, f: F) where F: Fn(&mut Grid<CharCell>, Coords) {
+    fn in_area<F>(&mut self, area: Area, f: F) where F: Fn(&mut Grid<CharCell, RowInfo>, Coords) {
         for coords in CoordsIter::from_area(area, self.cursor.coords, self.grid.bounds()) {
             f(&mut self.grid, coords);
         }
     }
 
+    // Cover the cells after a grapheme which has just been extended with extensions if it has
+    // grown wider, or clear them if it has become narrower.
+    fn fit_grapheme(&mut self, origin: Coords) {
+        let width = self.grid[origin].text_width();
+        let styles = self.grid[origin].styles;
+        let link = self.grid[origin].link.clone();
+        let bounds = self.grid.bounds();
+        let mut coords = origin;
+        for n in 1.. {
+            let next_coords = move_within(coords, To(Right, 1, false), bounds);
+            if next_coords == coords { break; } else { coords = next_coords; }
+            let is_extension = self.grid[coords].content == CharData::Extension(origin);
+            if n < width {
+                if !is_extension {
+                    self.grid[coords] = CharCell::extension(origin, styles)
+                                            .with_link(link.clone());
+                }
+            } else if is_extension {
+                self.grid[coords] = CharCell::default();
+            } else { break; }
+        }
+    }
+
 }
 
 impl<'a> IntoIterator for &'a CharGrid {
-    type IntoIter = <&'a Grid<CharCell> as IntoIterator>::IntoIter;
+    type IntoIter = <&'a Grid<CharCell, RowInfo> as IntoIterator>::IntoIter;
     type Item = &'a CharCell;
     fn into_iter(self) -> Self::IntoIter {
         self.grid.into_iter()
@@ -268,10 +625,14 @@ impl Index<Coords> for CharGrid {
 #[cfg(test)]
 mod tests {
 
+    use std::sync::Arc;
     use std::sync::atomic::Ordering::Relaxed;
 
+    use mime::Mime;
+
     use super::*;
-    use datatypes::{CellData, Coords, Direction, Movement, Region};
+    use datatypes::{Area, CellData, Color, Coords, Direction, MediaPosition, Movement, Region,
+                    Style};
 
     fn run_test<F: Fn(CharGrid, u32)>(test: F) {
         ::cfg::TAB_STOP.store(4, Relaxed);
@@ -301,6 +662,27 @@ mod tests {
         });
     }
 
+    #[test]
+    fn write_wide_graphemes() {
+        run_test(|mut grid, _| {
+            for c in vec![
+                CellData::Char('\u{1f1f3}'),
+                CellData::ExtensionChar('\u{1f1f4}'),
+                CellData::Char('\u{2764}'),
+                CellData::ExtensionChar('\u{fe0f}'),
+                CellData::Char('A'),
+            ].into_iter() { grid.write(c); }
+            let flag = Coords { x: 0, y: 0 };
+            let heart = Coords { x: 2, y: 0 };
+            assert_eq!(grid.grid[flag].repr(), "\u{1f1f3}\u{1f1f4}");
+            assert_eq!(grid.grid[Coords {x:1, y:0}].content, CharData::Extension(flag));
+            assert_eq!(grid.grid[heart].repr(), "\u{2764}\u{fe0f}");
+            assert_eq!(grid.grid[Coords {x:3, y:0}].content, CharData::Extension(heart));
+            assert_eq!(grid.grid[Coords {x:4, y:0}].repr(), "A");
+            assert_eq!(grid.cursor_position(), Coords { x: 5, y: 0 });
+        });
+    }
+
     fn setup(grid: &mut CharGrid) {
         let mut chars = vec![
             CellData::Char('A'),
@@ -326,6 +708,168 @@ mod tests {
         grid.move_cursor(Movement::ToBeginning);
     }
 
+    #[test]
+    fn hyperlinks() {
+        run_test(|mut grid, _| {
+            let link = Arc::new(Hyperlink { uri: String::from("http://x.org"), id: None });
+            grid.write(CellData::Char('A'));
+            grid.set_link(Some(link.clone()));
+            grid.write(CellData::Char('B'));
+            grid.write(CellData::ExtensionChar('\u{301}'));
+            grid.set_link(None);
+            grid.write(CellData::Char('C'));
+            assert_eq!(grid.grid[Coords {x:0, y:0}].link, None);
+            assert_eq!(grid.grid[Coords {x:1, y:0}].link, Some(link.clone()));
+            assert_eq!(grid.grid[Coords {x:2, y:0}].link, None);
+            grid.move_cursor(Movement::ToBeginning);
+            grid.insert_blank_at(1);
+            assert_eq!(grid.grid[Coords {x:2, y:0}].link, Some(link));
+            grid.erase(Area::CursorRow);
+            assert_eq!(grid.grid[Coords {x:2, y:0}].link, None);
+        });
+    }
+
+    #[test]
+    fn image_tiles() {
+        run_test(|mut grid, _| {
+            grid.set_style(Style::FgColor(Color::True(0, 0, 5)));
+            grid.write(CellData::Char(PLACEHOLDER));
+            grid.write(CellData::ExtensionChar('\u{305}'));
+            grid.write(CellData::Char(PLACEHOLDER));
+            assert_eq!(grid.grid[Coords { x: 1, y: 0 }].image_tile(None),
+                       Some(ImageTile { image: 5, row: 0, column: 0 }));
+            assert_eq!(grid.visible_image_tiles(), vec![5]);
+            grid.move_cursor(Movement::ToBeginning);
+            grid.insert_blank_at(2);
+            assert_eq!(grid.visible_image_tiles(), vec![5]);
+            grid.erase(Area::CursorRow);
+            assert!(grid.visible_image_tiles().is_empty());
+        });
+    }
+
+    #[test]
+    fn keyboard_flags() {
+        run_test(|mut grid, _| {
+            assert_eq!(grid.keyboard_flags(), 0);
+            grid.push_keyboard_flags(1);
+            grid.set_keyboard_flags(8, 2);
+            assert_eq!(grid.keyboard_flags(), 9);
+            grid.push_keyboard_flags(2);
+            grid.set_keyboard_flags(2, 3);
+            assert_eq!(grid.keyboard_flags(), 0);
+            grid.pop_keyboard_flags(1);
+            assert_eq!(grid.keyboard_flags(), 9);
+            for _ in 0..20 { grid.push_keyboard_flags(4); }
+            grid.pop_keyboard_flags(15);
+            assert_eq!(grid.keyboard_flags(), 4);
+            grid.pop_keyboard_flags(1);
+            assert_eq!(grid.keyboard_flags(), 0);
+            assert_eq!(CharGrid::new(10, 10, false).keyboard_flags(), 0);
+        });
+    }
+
+    #[test]
+    fn marks() {
+        run_test(|mut grid, _| {
+            grid.add_mark(Mark::PromptStart);
+            grid.write(CellData::Char('$'));
+            grid.add_mark(Mark::CommandStart);
+            grid.move_cursor(Movement::NextLine(1));
+            grid.add_mark(Mark::OutputStart);
+            for c in "ABC".chars() { grid.write(CellData::Char(c)); }
+            grid.move_cursor(Movement::NextLine(1));
+            grid.add_mark(Mark::CommandEnd(Some(0)));
+            grid.add_mark(Mark::PromptStart);
+            assert_eq!(grid.marks().len(), 5);
+            assert_eq!(grid.previous_prompt(2), Some(0));
+            assert_eq!(grid.next_prompt(0), Some(2));
+            let (start, end) = grid.last_command_output().unwrap();
+            assert_eq!(start, Coords { x: 0, y: 1 });
+            assert_eq!(end, Coords { x: 9, y: 1 });
+            assert_eq!(grid.chars_in_range(start, end), "ABC");
+        });
+    }
+
+    #[test]
+    fn placements() {
+        run_test(|mut grid, h| {
+            let data = Arc::new(ImageData::new(Arc::new(vec![0; 4]), Coords { x: 1, y: 1 }));
+            let placement = |id| Placement {
+                image: 1, id: id, data: data.clone(), pos: MediaPosition::Fit,
+                width: 2, height: 2, z: -1,
+            };
+            grid.move_cursor(Movement::To(Direction::Right, 3, false));
+            grid.place_image(placement(1));
+            grid.place_image(placement(1));
+            grid.place_image(placement(2));
+            assert_eq!(grid.placements().len(), 2);
+            assert_eq!(grid.placements()[0].0, Coords { x: 3, y: 0 });
+            grid.move_cursor(Movement::NextLine(10));
+            assert_eq!(grid.placements().len(), if h == 10 { 2 } else { 0 });
+            assert_eq!(grid.delete_images(|_, p| p.id == 2), vec![1]);
+            assert_eq!(grid.delete_images(|_, _| true).len(), 1);
+        });
+    }
+
+    #[test]
+    fn update_image() {
+        run_test(|mut grid, _| {
+            let mime: Mime = "image/png".parse().unwrap();
+            grid.write(image(2, 2));
+            let data = grid.update_image(Coords { x: 1, y: 1 }, vec![1; 2], mime.clone()).unwrap();
+            assert_eq!(*data, vec![1; 2]);
+            assert_eq!(grid.image_payload(Coords { x: 0, y: 0 }), Some(data));
+            assert!(grid.update_image(Coords { x: 5, y: 5 }, vec![], mime.clone()).is_none());
+            assert!(grid.update_image(Coords { x: 10, y: 0 }, vec![], mime.clone()).is_none());
+            assert!(grid.update_image(Coords { x: !0, y: !0 }, vec![], mime).is_none());
+        });
+    }
+
+    fn image(width: u32, height: u32) -> CellData {
+        CellData::Image {
+            pos: MediaPosition::Fit,
+            width: width,
+            height: height,
+            data: vec![0; 4],
+            mime: "image/png".parse().unwrap(),
+        }
+    }
+
+    #[test]
+    fn partial_images() {
+        run_test(|mut grid, h| {
+            grid.move_cursor(Movement::To(Direction::Right, 2, false));
+            grid.write(image(2, 3));
+            assert!(grid.partial_images().is_empty());
+            grid.move_cursor(Movement::NextLine(10));
+            let partial = grid.partial_images();
+            if h == 11 {
+                assert_eq!(partial.len(), 1);
+                assert_eq!((partial[0].0, partial[0].1), (Coords { x: 2, y: 0 },
+                                                          Coords { x: 0, y: 1 }));
+            } else {
+                // Without scrollback the cell the image was written in is gone.
+                assert!(partial.is_empty());
+            }
+        });
+    }
+
+    #[test]
+    fn images_survive_resize() {
+        run_test(|mut grid, _| {
+            grid.move_cursor(Movement::To(Direction::Right, 8, false));
+            grid.write(image(4, 2));
+            assert_eq!(grid.grid_width(), 10);
+            grid.resize_window(Region::new(0, 0, 6, 10));
+            grid.resize_window(Region::new(0, 0, 12, 10));
+            let origin = Coords { x: 8, y: 0 };
+            for &coords in &[Coords { x: 11, y: 0 }, Coords { x: 10, y: 1 }] {
+                assert_eq!(grid.grid[coords].content, CharData::Extension(origin));
+            }
+            assert_eq!(grid.image_payload(origin), Some(Arc::new(vec![0; 4])));
+        });
+    }
+
     #[test]
     fn move_cursor() {
         run_test(|mut grid, h| {
@@ -0,0 +1,150 @@
+//  notty is a new kind of terminal emulator.
+//  Copyright (C) 2015 without boats
+//  
+//  This program is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU Affero General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//  
+//  This program is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU Affero General Public License for more details.
+//  
+//  You should have received a copy of the GNU Affero General Public License
+//  along with this program.  If not, see <http://www.gnu.org/licenses/>.
+use datatypes::Color;
+use terminal::{Styles, UseStyles};
+
+/// The character written into cells to place tiles of an image with the kitty graphics
+/// protocol's unicode placeholders.
+pub const PLACEHOLDER: char = '\u{10eeee}';
+
+// The combining characters which encode the row, column and high byte of the image id of a
+// placeholder, in the order of the numbers they encode.
+static DIACRITICS: [char; 297] = [
+    '\u{0305}', '\u{030D}', '\u{030E}', '\u{0310}', '\u{0312}', '\u{033D}', '\u{033E}', '\u{033F}',
+    '\u{0346}', '\u{034A}', '\u{034B}', '\u{034C}', '\u{0350}', '\u{0351}', '\u{0352}', '\u{0357}',
+    '\u{035B}', '\u{0363}', '\u{0364}', '\u{0365}', '\u{0366}', '\u{0367}', '\u{0368}', '\u{0369}',
+    '\u{036A}', '\u{036B}', '\u{036C}', '\u{036D}', '\u{036E}', '\u{036F}', '\u{0483}', '\u{0484}',
+    '\u{0485}', '\u{0486}', '\u{0487}', '\u{0592}', '\u{0593}', '\u{0594}', '\u{0595}', '\u{0597}',
+    '\u{0598}', '\u{0599}', '\u{059C}', '\u{059D}', '\u{059E}', '\u{059F}', '\u{05A0}', '\u{05A1}',
+    '\u{05A8}', '\u{05A9}', '\u{05AB}', '\u{05AC}', '\u{05AF}', '\u{05C4}', '\u{0610}', '\u{0611}',
+    '\u{0612}', '\u{0613}', '\u{0614}', '\u{0615}', '\u{0616}', '\u{0617}', '\u{0657}', '\u{0658}',
+    '\u{0659}', '\u{065A}', '\u{065B}', '\u{065D}', '\u{065E}', '\u{06D6}', '\u{06D7}', '\u{06D8}',
+    '\u{06D9}', '\u{06DA}', '\u{06DB}', '\u{06DC}', '\u{06DF}', '\u{06E0}', '\u{06E1}', '\u{06E2}',
+    '\u{06E4}', '\u{06E7}', '\u{06E8}', '\u{06EB}', '\u{06EC}', '\u{0730}', '\u{0732}', '\u{0733}',
+    '\u{0735}', '\u{0736}', '\u{073A}', '\u{073D}', '\u{073F}', '\u{0740}', '\u{0741}', '\u{0743}',
+    '\u{0745}', '\u{0747}', '\u{0749}', '\u{074A}', '\u{07EB}', '\u{07EC}', '\u{07ED}', '\u{07EE}',
+    '\u{07EF}', '\u{07F0}', '\u{07F1}', '\u{07F3}', '\u{0816}', '\u{0817}', '\u{0818}', '\u{0819}',
+    '\u{081B}', '\u{081C}', '\u{081D}', '\u{081E}', '\u{081F}', '\u{0820}', '\u{0821}', '\u{0822}',
+    '\u{0823}', '\u{0825}', '\u{0826}', '\u{0827}', '\u{0829}', '\u{082A}', '\u{082B}', '\u{082C}',
+    '\u{082D}', '\u{0951}', '\u{0953}', '\u{0954}', '\u{0F82}', '\u{0F83}', '\u{0F86}', '\u{0F87}',
+    '\u{135D}', '\u{135E}', '\u{135F}', '\u{17DD}', '\u{193A}', '\u{1A17}', '\u{1A75}', '\u{1A76}',
+    '\u{1A77}', '\u{1A78}', '\u{1A79}', '\u{1A7A}', '\u{1A7B}', '\u{1A7C}', '\u{1B6B}', '\u{1B6D}',
+    '\u{1B6E}', '\u{1B6F}', '\u{1B70}', '\u{1B71}', '\u{1B72}', '\u{1B73}', '\u{1CD0}', '\u{1CD1}',
+    '\u{1CD2}', '\u{1CDA}', '\u{1CDB}', '\u{1CE0}', '\u{1DC0}', '\u{1DC1}', '\u{1DC3}', '\u{1DC4}',
+    '\u{1DC5}', '\u{1DC6}', '\u{1DC7}', '\u{1DC8}', '\u{1DC9}', '\u{1DCB}', '\u{1DCC}', '\u{1DD1}',
+    '\u{1DD2}', '\u{1DD3}', '\u{1DD4}', '\u{1DD5}', '\u{1DD6}', '\u{1DD7}', '\u{1DD8}', '\u{1DD9}',
+    '\u{1DDA}', '\u{1DDB}', '\u{1DDC}', '\u{1DDD}', '\u{1DDE}', '\u{1DDF}', '\u{1DE0}', '\u{1DE1}',
+    '\u{1DE2}', '\u{1DE3}', '\u{1DE4}', '\u{1DE5}', '\u{1DE6}', '\u{1DFE}', '\u{20D0}', '\u{20D1}',
+    '\u{20D4}', '\u{20D5}', '\u{20D6}', '\u{20D7}', '\u{20DB}', '\u{20DC}', '\u{20E1}', '\u{20E7}',
+    '\u{20E9}', '\u{20F0}', '\u{2CEF}', '\u{2CF0}', '\u{2CF1}', '\u{2DE0}', '\u{2DE1}', '\u{2DE2}',
+    '\u{2DE3}', '\u{2DE4}', '\u{2DE5}', '\u{2DE6}', '\u{2DE7}', '\u{2DE8}', '\u{2DE9}', '\u{2DEA}',
+    '\u{2DEB}', '\u{2DEC}', '\u{2DED}', '\u{2DEE}', '\u{2DEF}', '\u{2DF0}', '\u{2DF1}', '\u{2DF2}',
+    '\u{2DF3}', '\u{2DF4}', '\u{2DF5}', '\u{2DF6}', '\u{2DF7}', '\u{2DF8}', '\u{2DF9}', '\u{2DFA}',
+    '\u{2DFB}', '\u{2DFC}', '\u{2DFD}', '\u{2DFE}', '\u{2DFF}', '\u{A66F}', '\u{A67C}', '\u{A67D}',
+    '\u{A6F0}', '\u{A6F1}', '\u{A8E0}', '\u{A8E1}', '\u{A8E2}', '\u{A8E3}', '\u{A8E4}', '\u{A8E5}',
+    '\u{A8E6}', '\u{A8E7}', '\u{A8E8}', '\u{A8E9}', '\u{A8EA}', '\u{A8EB}', '\u{A8EC}', '\u{A8ED}',
+    '\u{A8EE}', '\u{A8EF}', '\u{A8F0}', '\u{A8F1}', '\u{AAB0}', '\u{AAB2}', '\u{AAB3}', '\u{AAB7}',
+    '\u{AAB8}', '\u{AABE}', '\u{AABF}', '\u{AAC1}', '\u{FE20}', '\u{FE21}', '\u{FE22}', '\u{FE23}',
+    '\u{FE24}', '\u{FE25}', '\u{FE26}', '\u{10A0F}', '\u{10A38}', '\u{1D185}', '\u{1D186}',
+    '\u{1D187}', '\u{1D188}', '\u{1D189}', '\u{1D1AA}', '\u{1D1AB}', '\u{1D1AC}', '\u{1D1AD}',
+    '\u{1D242}', '\u{1D243}', '\u{1D244}',
+];
+
+/// The tile of an image a placeholder cell shows. The image is one with a virtual placement,
+/// which gives the number of rows and columns the image is divided into.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct ImageTile {
+    /// The id of the image, encoded by the foreground color of the cell.
+    pub image: u32,
+    pub row: u32,
+    pub column: u32,
+}
+
+impl ImageTile {
+    /// Resolve a cell written with a placeholder. The row and column may be left out, in which
+    /// case the tile continues the row of the placeholder in the cell before it.
+    pub fn parse(grapheme: &str, styles: UseStyles, previous: Option<ImageTile>)
+            -> Option<ImageTile> {
+        let mut chars = grapheme.chars();
+        if chars.next() != Some(PLACEHOLDER) { return None }
+        let image = match styles {
+            UseStyles::Custom(Styles { fg_color: Color::True(r, g, b), .. })   => {
+                (r as u32) << 16 | (g as u32) << 8 | b as u32
+            }
+            UseStyles::Custom(Styles { fg_color: Color::Palette(n), .. })      => n as u32,
+            _                                                                   => return None,
+        };
+        let mut numbers = chars.map(|ch| DIACRITICS.binary_search(&ch).ok().map(|n| n as u32));
+        let row = numbers.next().and_then(|n| n);
+        let column = numbers.next().and_then(|n| n);
+        let image = match numbers.next().and_then(|n| n) {
+            Some(high)  => image | high << 24,
+            None        => image,
+        };
+        let continued = previous.and_then(|previous| {
+            if previous.image == image && row.map_or(true, |row| row == previous.row) {
+                Some((previous.row, previous.column + 1))
+            } else { None }
+        });
+        let (row, column) = match (row, column, continued) {
+            (Some(row), Some(column), _)    => (row, column),
+            (_, _, Some(continued))         => continued,
+            (row, _, None)                  => (row.unwrap_or(0), 0),
+        };
+        Some(ImageTile { image: image, row: row, column: column })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use datatypes::Color;
+    use terminal::{Styles, UseStyles};
+
+    use super::*;
+
+    fn styles(color: Color) -> UseStyles {
+        UseStyles::Custom(Styles { fg_color: color, ..Styles::default() })
+    }
+
+    #[test]
+    fn parse() {
+        let blue = styles(Color::True(0, 0, 42));
+        assert_eq!(ImageTile::parse("\u{10eeee}\u{30d}\u{310}", blue, None),
+                   Some(ImageTile { image: 42, row: 1, column: 3 }));
+        let palette = styles(Color::Palette(7));
+        assert_eq!(ImageTile::parse("\u{10eeee}\u{305}\u{305}\u{30e}", palette, None),
+                   Some(ImageTile { image: 2 << 24 | 7, row: 0, column: 0 }));
+        assert_eq!(ImageTile::parse("x", blue, None), None);
+        assert_eq!(ImageTile::parse("\u{10eeee}", UseStyles::default(), None), None);
+    }
+
+    #[test]
+    fn continued() {
+        let blue = styles(Color::True(0, 0, 42));
+        let first = ImageTile::parse("\u{10eeee}\u{30d}\u{310}", blue, None);
+        let second = ImageTile::parse("\u{10eeee}", blue, first);
+        assert_eq!(second, Some(ImageTile { image: 42, row: 1, column: 4 }));
+        assert_eq!(ImageTile::parse("\u{10eeee}\u{30d}", blue, second),
+                   Some(ImageTile { image: 42, row: 1, column: 5 }));
+        assert_eq!(ImageTile::parse("\u{10eeee}\u{305}", blue, second),
+                   Some(ImageTile { image: 42, row: 0, column: 0 }));
+        let red = styles(Color::True(1, 0, 0));
+        assert_eq!(ImageTile::parse("\u{10eeee}", red, second),
+                   Some(ImageTile { image: 1 << 16, row: 0, column: 0 }));
+    }
+
+}
@@ -0,0 +1,38 @@
+//  notty is a new kind of terminal emulator.
+//  Copyright (C) 2015 without boats
+//  
+//  This program is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU Affero General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//  
+//  This program is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU Affero General Public License for more details.
+//  
+//  You should have received a copy of the GNU Affero General Public License
+//  along with this program.  If not, see <http://www.gnu.org/licenses/>.
+use std::sync::Arc;
+
+use datatypes::MediaPosition;
+
+use super::ImageData;
+
+/// An image placed over the cells of a grid with the kitty graphics protocol. Unlike images
+/// written into cells, placements do not replace the text beneath them, and the same image
+/// data can be placed many times.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Placement {
+    /// The id of the image which was placed.
+    pub image: u32,
+    /// The id of this placement, or 0 if it has none.
+    pub id: u32,
+    pub data: Arc<ImageData>,
+    pub pos: MediaPosition,
+    /// The size of the placement in cells.
+    pub width: u32,
+    pub height: u32,
+    /// Placements with a negative z-index are drawn beneath text, others above it.
+    pub z: i32,
+}
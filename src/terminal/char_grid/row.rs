@@ -0,0 +1,25 @@
+//  notty is a new kind of terminal emulator.
+//  Copyright (C) 2015 without boats
+//  
+//  This program is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU Affero General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//  
+//  This program is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU Affero General Public License for more details.
+//  
+//  You should have received a copy of the GNU Affero General Public License
+//  along with this program.  If not, see <http://www.gnu.org/licenses/>.
+use super::{Mark, Placement};
+
+/// The data held for each row of a `CharGrid`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RowInfo {
+    /// Shell integration marks in this row, with the column at which they were set.
+    pub marks: Vec<(u32, Mark)>,
+    /// Images placed with their top left corner in this row, with the column of that corner.
+    pub images: Vec<(u32, Placement)>,
+}
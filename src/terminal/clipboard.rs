@@ -0,0 +1,75 @@
+//  notty is a new kind of terminal emulator.
+//  Copyright (C) 2015 without boats
+//  
+//  This program is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU Affero General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//  
+//  This program is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU Affero General Public License for more details.
+//  
+//  You should have received a copy of the GNU Affero General Public License
+//  along with this program.  If not, see <http://www.gnu.org/licenses/>.
+/// Which clipboard operations programs running in the terminal may perform with OSC 52.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum ClipboardPolicy {
+    /// OSC 52 is ignored.
+    Deny,
+    /// Programs may set the clipboard, but not read it.
+    WriteOnly,
+    /// Programs may set and read the clipboard.
+    ReadWrite,
+}
+
+impl ClipboardPolicy {
+    pub fn can_write(&self) -> bool {
+        *self != ClipboardPolicy::Deny
+    }
+
+    pub fn can_read(&self) -> bool {
+        *self == ClipboardPolicy::ReadWrite
+    }
+}
+
+impl Default for ClipboardPolicy {
+    fn default() -> ClipboardPolicy {
+        ClipboardPolicy::WriteOnly
+    }
+}
+
+/// The selections which OSC 52 can address.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum Selection {
+    Clipboard,
+    Primary,
+    Secondary,
+    Select,
+    /// One of the X11 cut buffers, numbered from 0 to 7.
+    CutBuffer(u8),
+}
+
+impl Selection {
+    pub fn from_char(ch: char) -> Option<Selection> {
+        match ch {
+            'c'             => Some(Selection::Clipboard),
+            'p'             => Some(Selection::Primary),
+            'q'             => Some(Selection::Secondary),
+            's'             => Some(Selection::Select),
+            n @ '0'...'7'   => Some(Selection::CutBuffer(n as u8 - b'0')),
+            _               => None,
+        }
+    }
+
+    pub fn as_char(&self) -> char {
+        match *self {
+            Selection::Clipboard    => 'c',
+            Selection::Primary      => 'p',
+            Selection::Secondary    => 'q',
+            Selection::Select       => 's',
+            Selection::CutBuffer(n) => (b'0' + n) as char,
+        }
+    }
+}
@@ -0,0 +1,65 @@
+//  notty is a new kind of terminal emulator.
+//  Copyright (C) 2015 without boats
+//  
+//  This program is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU Affero General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//  
+//  This program is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU Affero General Public License for more details.
+//  
+//  You should have received a copy of the GNU Affero General Public License
+//  along with this program.  If not, see <http://www.gnu.org/licenses/>.
+use super::{Notification, Selection, WindowOp, WorkingDir};
+
+/// Side effects of the terminal which the embedding application handles, such as ringing the
+/// bell or setting the window title.
+///
+/// Every method has a default implementation which does nothing, so implementors need only
+/// handle the events they care about; `NoEvents` ignores all of them.
+pub trait TerminalEvents {
+    /// A program rang the bell.
+    fn bell(&mut self) { }
+
+    /// The title of the terminal changed.
+    fn title_changed(&mut self, _title: &str) { }
+
+    /// The icon name of the terminal changed.
+    fn icon_changed(&mut self, _icon: &str) { }
+
+    /// Store text in a selection. This is only called if the clipboard policy allows writing.
+    fn store_clipboard(&mut self, _selection: Selection, _data: &str) { }
+
+    /// Ask for the contents of a selection, to be sent back to the program which asked for
+    /// them. They should be given to the terminal later with `Terminal::report_clipboard` or
+    /// `Command::report_clipboard`, rather than waited for here. This is only called if the
+    /// clipboard policy allows reading.
+    fn load_clipboard(&mut self, _selection: Selection) { }
+
+    /// A program asked for a desktop notification.
+    fn notify(&mut self, _notification: Notification) { }
+
+    /// The working directory of the active grid changed.
+    fn cwd_changed(&mut self, _cwd: &WorkingDir) { }
+
+    /// Change the window. This is only called if the window policy allows it.
+    fn window_op(&mut self, _op: WindowOp) { }
+
+    /// The position of the top left corner of the window in pixels, if it is known.
+    fn window_position(&mut self) -> Option<(u32, u32)> { None }
+
+    /// Whether the window is iconified.
+    fn is_iconified(&mut self) -> bool { false }
+
+    /// The program on the tty exited, with its exit status if it is known.
+    fn child_exited(&mut self, _status: Option<i32>) { }
+}
+
+/// An implementation of `TerminalEvents` which ignores every event.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct NoEvents;
+
+impl TerminalEvents for NoEvents { }
@@ -0,0 +1,242 @@
+//  notty is a new kind of terminal emulator.
+//  Copyright (C) 2015 without boats
+//  
+//  This program is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU Affero General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//  
+//  This program is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU Affero General Public License for more details.
+//  
+//  You should have received a copy of the GNU Affero General Public License
+//  along with this program.  If not, see <http://www.gnu.org/licenses/>.
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Weak};
+
+use datatypes::Coords;
+use terminal::Placement;
+
+/// The size of a cell in pixels assumed when the renderer has not given it.
+pub const DEFAULT_CELL_SIZE: (u32, u32) = (8, 16);
+
+/// An image transmitted with the kitty graphics protocol, which can be placed any number of
+/// times without being sent again.
+#[derive(Clone, Debug)]
+pub struct StoredImage {
+    pub data: Arc<Vec<u8>>,
+    /// The size of the image in pixels.
+    pub width: u32,
+    pub height: u32,
+}
+
+/// How a stored image should be placed at the cursor.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct PlaceParams {
+    /// The number of columns and rows to scale the image to; 0 means it is sized by its pixels.
+    pub cols: u32,
+    pub rows: u32,
+    pub z: i32,
+    /// Whether the cursor is moved past the image after placing it.
+    pub move_cursor: bool,
+    /// Whether the placement is virtual, so that the image is shown only by unicode placeholder
+    /// cells written by the program rather than placed at the cursor.
+    pub virtual_placement: bool,
+}
+
+/// Which placements to delete. Positions are in cells relative to the visible screen.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum DeleteSpec {
+    All,
+    /// The placements of an image, or only the placement with the given id if it is nonzero.
+    Image(u32, u32),
+    /// The placements of the image most recently transmitted with this number.
+    Number(u32),
+    Cursor,
+    Cell(Coords),
+    Column(u32),
+    Row(u32),
+    ZIndex(i32),
+}
+
+/// The images which have been transmitted to the terminal, by id.
+#[derive(Default)]
+pub struct ImageStore {
+    images: HashMap<u32, StoredImage>,
+    numbers: HashMap<u32, u32>,
+    virtual_placements: Vec<Placement>,
+    next_id: u32,
+}
+
+impl ImageStore {
+
+    /// Store an image under the given id, or under a newly allocated id if it is 0. If the
+    /// image has a nonzero number, it can be looked up by that number until another image is
+    /// stored with the same one.
+    pub fn insert(&mut self, id: u32, number: u32, image: StoredImage) -> u32 {
+        let id = if id != 0 { id } else { self.allocate() };
+        self.images.insert(id, image);
+        if number != 0 { self.numbers.insert(number, id); }
+        id
+    }
+
+    pub fn get(&self, id: u32) -> Option<&StoredImage> {
+        self.images.get(&id)
+    }
+
+    /// The id of the image most recently stored with this number.
+    pub fn by_number(&self, number: u32) -> Option<u32> {
+        self.numbers.get(&number).cloned()
+    }
+
+    /// Remove the images with any of this data.
+    pub fn evict(&mut self, evicted: &[Arc<Vec<u8>>]) {
+        let ids: Vec<u32> = self.images.iter().filter(|&(_, image)| {
+            evicted.iter().any(|data| same(data, &image.data))
+        }).map(|(&id, _)| id).collect();
+        for id in ids { self.remove(id); }
+    }
+
+    /// Add a virtual placement, replacing any with the same image and placement id.
+    pub fn place_virtual(&mut self, placement: Placement) {
+        self.delete_virtual(|p| p.image == placement.image && p.id == placement.id);
+        self.virtual_placements.push(placement);
+    }
+
+    /// The virtual placement of an image which placeholder cells showing the image use. If the
+    /// image has several, the most recent is used.
+    pub fn virtual_placement(&self, image: u32) -> Option<&Placement> {
+        self.virtual_placements.iter().rev().find(|placement| placement.image == image)
+    }
+
+    pub fn delete_virtual<F: Fn(&Placement) -> bool>(&mut self, f: F) {
+        self.virtual_placements.retain(|placement| !f(placement));
+    }
+
+    pub fn remove(&mut self, id: u32) {
+        self.images.remove(&id);
+        self.delete_virtual(|placement| placement.image == id);
+        let numbers: Vec<u32> = self.numbers.iter().filter(|&(_, &n)| n == id)
+                                    .map(|(&number, _)| number).collect();
+        for number in numbers { self.numbers.remove(&number); }
+    }
+
+    // Ids chosen by the terminal count down from the top of the range, so they are unlikely
+    // to collide with the ids programs choose for themselves.
+    fn allocate(&mut self) -> u32 {
+        loop {
+            self.next_id = self.next_id.wrapping_sub(1);
+            if self.next_id != 0 && !self.images.contains_key(&self.next_id) {
+                return self.next_id
+            }
+        }
+    }
+
+}
+
+/// The data of the images written to the terminal, oldest first, so that the oldest can be
+/// evicted once images use more memory than the budget allows.
+#[derive(Default)]
+pub struct ImageBudget {
+    images: VecDeque<Weak<Vec<u8>>>,
+}
+
+impl ImageBudget {
+
+    pub fn track(&mut self, data: &Arc<Vec<u8>>) {
+        self.images.push_back(Arc::downgrade(data));
+    }
+
+    /// The memory used by the images which are still held, forgetting those which are not.
+    pub fn used(&mut self) -> usize {
+        self.images.retain(|data| data.upgrade().is_some());
+        self.images.iter().filter_map(|data| data.upgrade()).map(|data| data.len()).sum()
+    }
+
+    /// Choose the oldest images to evict to bring the memory used by images within the limit,
+    /// skipping those which are visible. Images which have since been freed are forgotten.
+    pub fn evict(&mut self, limit: usize, visible: &[Arc<Vec<u8>>]) -> Vec<Arc<Vec<u8>>> {
+        let live: Vec<Arc<Vec<u8>>> = self.images.iter().filter_map(|data| data.upgrade())
+                                                       .collect();
+        let mut used = live.iter().map(|data| data.len()).sum::<usize>();
+        let mut evicted = vec![];
+        self.images.clear();
+        for data in live {
+            if used > limit && !visible.iter().any(|visible| same(visible, &data)) {
+                used -= data.len();
+                evicted.push(data);
+            } else {
+                self.images.push_back(Arc::downgrade(&data));
+            }
+        }
+        evicted
+    }
+
+}
+
+fn same(a: &Arc<Vec<u8>>, b: &Arc<Vec<u8>>) -> bool {
+    &**a as *const Vec<u8> == &**b as *const Vec<u8>
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::sync::Arc;
+
+    use datatypes::{Coords, MediaPosition};
+    use terminal::{ImageData, Placement};
+
+    use super::{ImageBudget, ImageStore, StoredImage};
+
+    fn image() -> StoredImage {
+        StoredImage { data: Arc::new(vec![]), width: 1, height: 1 }
+    }
+
+    #[test]
+    fn ids_and_numbers() {
+        let mut store = ImageStore::default();
+        assert_eq!(store.insert(7, 0, image()), 7);
+        let id = store.insert(0, 3, image());
+        assert!(id != 0 && id != 7);
+        assert_eq!(store.by_number(3), Some(id));
+        assert!(store.get(7).is_some());
+        store.remove(id);
+        assert_eq!(store.by_number(3), None);
+        assert!(store.get(id).is_none());
+    }
+
+    #[test]
+    fn virtual_placements() {
+        let mut store = ImageStore::default();
+        let id = store.insert(0, 0, image());
+        let data = Arc::new(ImageData::new(Arc::new(vec![]), Coords::default()));
+        let placement = |pid, width| Placement {
+            image: id, id: pid, data: data.clone(), pos: MediaPosition::Fit,
+            width: width, height: 1, z: 0,
+        };
+        store.place_virtual(placement(1, 2));
+        store.place_virtual(placement(2, 3));
+        store.place_virtual(placement(1, 4));
+        assert_eq!(store.virtual_placement(id).unwrap().width, 4);
+        store.delete_virtual(|p| p.id == 1);
+        assert_eq!(store.virtual_placement(id).unwrap().width, 3);
+        store.remove(id);
+        assert!(store.virtual_placement(id).is_none());
+    }
+
+    #[test]
+    fn budget() {
+        let mut budget = ImageBudget::default();
+        let images = (0..3).map(|_| Arc::new(vec![0; 10])).collect::<Vec<_>>();
+        for image in &images { budget.track(image); }
+        budget.track(&Arc::new(vec![0; 10]));
+        assert_eq!(budget.used(), 30);
+        let evicted = budget.evict(25, &images[..1]);
+        assert_eq!(evicted.len(), 1);
+        assert!(super::same(&evicted[0], &images[1]));
+        assert_eq!(budget.evict(15, &[]).len(), 1);
+    }
+
+}
@@ -14,74 +14,252 @@
 //  You should have received a copy of the GNU Affero General Public License
 //  along with this program.  If not, see <http://www.gnu.org/licenses/>.
 use std::borrow::Cow;
+use std::sync::atomic::Ordering::Relaxed;
 
-use datatypes::Key;
+use cfg::ALT_SETS_EIGHTH_BIT;
+use datatypes::{Key, KeypadKey};
 use datatypes::Key::*;
 
-use super::modifiers::Modifiers;
-
-macro_rules! term_key {
-    ($term:expr, $app_mode:expr, $mods:expr) => (match $mods.triplet() {
-        (false, false, false) if $app_mode  => Some(Cow::Borrowed(concat!("\x1bO", $term))),
-        (false, false, false)               => Some(Cow::Borrowed(concat!("\x1b[", $term))),
-        (true,  false, false)               => Some(Cow::Borrowed(concat!("\x1b[1;2", $term))),
-        (false, false, true)                => Some(Cow::Borrowed(concat!("\x1b[1;3", $term))),
-        (true,  false, true)                => Some(Cow::Borrowed(concat!("\x1b[1;4", $term))),
-        (false, true,  false)               => Some(Cow::Borrowed(concat!("\x1b[1;5", $term))),
-        (true,  true,  false)               => Some(Cow::Borrowed(concat!("\x1b[1;6", $term))),
-        (false, true,  true)                => Some(Cow::Borrowed(concat!("\x1b[1;7", $term))),
-        (true,  true,  true)                => Some(Cow::Borrowed(concat!("\x1b[1;8", $term))),
-    });
-}
+use super::modifiers::{Modifiers, SHIFT, ALT, CTRL, META};
 
-macro_rules! tilde_key {
-    ($code:expr, $mods:expr) => (match $mods.triplet() {
-        (false, false, false)           => Some(Cow::Borrowed(concat!("\x1b[", $code, "~"))),
-        (true,  false, false)           => Some(Cow::Borrowed(concat!("\x1b[", $code, ";2~"))),
-        (false, false, true)            => Some(Cow::Borrowed(concat!("\x1b[", $code, ";3~"))),
-        (true,  false, true)            => Some(Cow::Borrowed(concat!("\x1b[", $code, ";4~"))),
-        (false, true,  false)           => Some(Cow::Borrowed(concat!("\x1b[", $code, ";5~"))),
-        (true,  true,  false)           => Some(Cow::Borrowed(concat!("\x1b[", $code, ";6~"))),
-        (false, true,  true)            => Some(Cow::Borrowed(concat!("\x1b[", $code, ";7~"))),
-        (true,  true,  true)            => Some(Cow::Borrowed(concat!("\x1b[", $code, ";8~"))),
-    });
+/// Options which change the way keys are encoded in ANSI mode. These are set by the controlling
+/// process independently of the input mode.
+#[derive(Copy, Clone)]
+pub struct AnsiOptions {
+    /// Whether the numeric keypad is in application mode (DECKPAM) or numeric mode (DECKPNM).
+    pub keypad_mode: bool,
+    /// Whether the Alt key prefixes characters with the escape character, or sets their
+    /// eighth bit.
+    pub alt_sends_esc: bool,
+    /// The xterm modifyOtherKeys level, from 0 (disabled) to 2.
+    pub modify_other_keys: u8,
 }
 
+impl Default for AnsiOptions {
+    fn default() -> AnsiOptions {
+        AnsiOptions {
+            keypad_mode: false,
+            alt_sends_esc: !ALT_SETS_EIGHTH_BIT.load(Relaxed),
+            modify_other_keys: 0,
+        }
+    }
+}
 
-pub fn encode(key: &Key, app_mode: bool, mods: Modifiers) -> Option<Cow<'static, str>> {
+pub fn encode(key: &Key, app_mode: bool, opts: AnsiOptions, mods: Modifiers)
+        -> Option<Cow<'static, str>> {
     match *key {
-        Char(c) if mods.alt()   => Some(Cow::Owned(format!("\x1b{}", c))),
-        Char(c)                 => Some(Cow::Owned(c.to_string())),
-        UpArrow                 => term_key!('A', app_mode, mods),
-        DownArrow               => term_key!('B', app_mode, mods),
-        LeftArrow               => term_key!('D', app_mode, mods),
-        RightArrow              => term_key!('C', app_mode, mods),
+        Char('\t') if mods.bits() == SHIFT  => Some(Cow::Borrowed("\x1b[Z")),
+        Char(c)                 => char_key(c, opts, mods),
+        UpArrow                 => term_key('A', app_mode, mods),
+        DownArrow               => term_key('B', app_mode, mods),
+        LeftArrow               => term_key('D', app_mode, mods),
+        RightArrow              => term_key('C', app_mode, mods),
+        Enter if modify_other(13, opts, mods)   => Some(other_key(13, mods)),
         Enter                   => Some(Cow::Borrowed("\r")),
+        Backspace if modify_other(127, opts, mods)  => Some(other_key(127, mods)),
         Backspace               => Some(Cow::Borrowed("\x7f")),
-        Meta | Menu             => None,
-        PageUp                  => tilde_key!('5', mods),
-        PageDown                => tilde_key!('6', mods),
-        Home                    => term_key!('H', false, mods),
-        End                     => term_key!('F', false, mods),
-        Insert                  => tilde_key!('2', mods),
-        Delete                  => tilde_key!('3', mods),
-        NumLock                 => unimplemented!(),
-        ScrollLock              => unimplemented!(),
-        Function(n)             => match n {
-            0   => term_key!('P', true, mods), 
-            1   => term_key!('Q', true, mods),
-            2   => term_key!('R', true, mods),
-            4   => term_key!('S', true, mods),
-            _   => unimplemented!(),
-        },
+        Menu                    => None,
+        PageUp                  => tilde_key(5, mods),
+        PageDown                => tilde_key(6, mods),
+        Home                    => term_key('H', false, mods),
+        End                     => term_key('F', false, mods),
+        Insert                  => tilde_key(2, mods),
+        Delete                  => tilde_key(3, mods),
+        NumLock | ScrollLock    => None,
+        Function(n @ 13...24)   => function_key(n - 12, mods.shifted()),
+        Function(n)             => function_key(n, mods),
+        Keypad(k)               => keypad_key(k, opts, mods),
+        Media(_)                => None,
         Cmd(ref cmd)            => Some(cmd.clone()),
-        MenuSelection(_)        => unimplemented!(),
+        MenuSelection(n)        => Some(Cow::Owned(format!("\x1b{{51;{:x}}}", n))),
         ShiftLeft
             | ShiftRight
             | CtrlLeft
             | CtrlRight
             | AltLeft
             | AltGr
+            | Meta
+            | SuperLeft
+            | SuperRight
+            | Hyper
             | CapsLock          => unreachable!(),
     }
 }
+
+fn char_key(c: char, opts: AnsiOptions, mods: Modifiers) -> Option<Cow<'static, str>> {
+    if modify_other(c as u32, opts, mods) {
+        return Some(other_key(c as u32, mods));
+    }
+    let c = if mods.ctrl() { Char(c).ctrl_modify() } else { Char(c) };
+    match c {
+        Char(c) if mods.bits() & (ALT | META) == 0  => Some(Cow::Owned(c.to_string())),
+        Char(c) if opts.alt_sends_esc || c > '\x7f' => Some(Cow::Owned(format!("\x1b{}", c))),
+        Char(c)     => ::std::char::from_u32(c as u32 | 0x80).map(|c| Cow::Owned(c.to_string())),
+        _           => unreachable!(),
+    }
+}
+
+// True if this key should be reported using the xterm `CSI 27 ; mod ; key ~` form under the
+// current modifyOtherKeys level. Level 1 only reports keys which would otherwise be ambiguous,
+// level 2 reports every key modified by something other than shift.
+fn modify_other(code: u32, opts: AnsiOptions, mods: Modifiers) -> bool {
+    let bits = mods.bits();
+    match opts.modify_other_keys {
+        0   => false,
+        1   => {
+            let ctrl_ambiguous = bits & CTRL != 0 && (bits & SHIFT != 0 || match code {
+                0x20 | 0x3f...0x7f  => false,
+                _                   => true,
+            });
+            ctrl_ambiguous || bits & !(SHIFT | ALT | CTRL) != 0 ||
+                (bits & !SHIFT != 0 && (code == 9 || code == 13 || code == 27 || code == 127))
+        }
+        _   => bits & !SHIFT != 0,
+    }
+}
+
+fn other_key(code: u32, mods: Modifiers) -> Cow<'static, str> {
+    Cow::Owned(format!("\x1b[27;{};{}~", mods.param(), code))
+}
+
+fn term_key(term: char, app_mode: bool, mods: Modifiers) -> Option<Cow<'static, str>> {
+    match mods.param() {
+        1 if app_mode   => Some(Cow::Owned(format!("\x1bO{}", term))),
+        1               => Some(Cow::Owned(format!("\x1b[{}", term))),
+        n               => Some(Cow::Owned(format!("\x1b[1;{}{}", n, term))),
+    }
+}
+
+fn tilde_key(code: u32, mods: Modifiers) -> Option<Cow<'static, str>> {
+    match mods.param() {
+        1               => Some(Cow::Owned(format!("\x1b[{}~", code))),
+        n               => Some(Cow::Owned(format!("\x1b[{};{}~", code, n))),
+    }
+}
+
+fn function_key(n: u8, mods: Modifiers) -> Option<Cow<'static, str>> {
+    match n {
+        1   => term_key('P', true, mods),
+        2   => term_key('Q', true, mods),
+        3   => term_key('R', true, mods),
+        4   => term_key('S', true, mods),
+        5   => tilde_key(15, mods),
+        6   => tilde_key(17, mods),
+        7   => tilde_key(18, mods),
+        8   => tilde_key(19, mods),
+        9   => tilde_key(20, mods),
+        10  => tilde_key(21, mods),
+        11  => tilde_key(23, mods),
+        12  => tilde_key(24, mods),
+        _   => None,
+    }
+}
+
+fn keypad_key(key: KeypadKey, opts: AnsiOptions, mods: Modifiers) -> Option<Cow<'static, str>> {
+    let term = match key {
+        KeypadKey::Begin            => return term_key('E', false, mods),
+        _ if !opts.keypad_mode      => return match key {
+            KeypadKey::Enter    => encode(&Enter, false, opts, mods),
+            _                   => key.as_char().and_then(|c| char_key(c, opts, mods)),
+        },
+        KeypadKey::Digit(n @ 0...9) => (b'p' + n) as char,
+        KeypadKey::Digit(_)         => return None,
+        KeypadKey::Decimal          => 'n',
+        KeypadKey::Separator        => 'l',
+        KeypadKey::Divide           => 'o',
+        KeypadKey::Multiply         => 'j',
+        KeypadKey::Subtract         => 'm',
+        KeypadKey::Add              => 'k',
+        KeypadKey::Equal            => 'X',
+        KeypadKey::Enter            => 'M',
+    };
+    match mods.param() {
+        1   => Some(Cow::Owned(format!("\x1bO{}", term))),
+        n   => Some(Cow::Owned(format!("\x1bO{}{}", n, term))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use datatypes::{Key, KeypadKey};
+    use datatypes::Key::*;
+
+    use super::*;
+    use super::super::modifiers::Modifiers;
+
+    fn mods(keys: &[Key]) -> Modifiers {
+        let mut mods = Modifiers::new();
+        for key in keys { mods.apply(key, true); }
+        mods
+    }
+
+    fn opts(keypad_mode: bool, modify_other_keys: u8) -> AnsiOptions {
+        AnsiOptions {
+            keypad_mode: keypad_mode,
+            alt_sends_esc: true,
+            modify_other_keys: modify_other_keys,
+        }
+    }
+
+    #[test]
+    fn function_keys() {
+        let none = mods(&[]);
+        let shift = mods(&[ShiftLeft]);
+        assert_eq!(encode(&Function(1), false, opts(false, 0), none).unwrap(), "\x1bOP");
+        assert_eq!(encode(&Function(1), false, opts(false, 0), shift).unwrap(), "\x1b[1;2P");
+        assert_eq!(encode(&Function(5), false, opts(false, 0), none).unwrap(), "\x1b[15~");
+        assert_eq!(encode(&Function(12), false, opts(false, 0), shift).unwrap(), "\x1b[24;2~");
+        assert_eq!(encode(&Function(13), false, opts(false, 0), none).unwrap(), "\x1b[1;2P");
+        assert_eq!(encode(&Function(24), false, opts(false, 0), none).unwrap(), "\x1b[24;2~");
+        assert!(encode(&Function(25), false, opts(false, 0), none).is_none());
+    }
+
+    #[test]
+    fn keypad_keys() {
+        let none = mods(&[]);
+        let digit = Keypad(KeypadKey::Digit(7));
+        assert_eq!(encode(&digit, false, opts(false, 0), none).unwrap(), "7");
+        assert_eq!(encode(&digit, false, opts(true, 0), none).unwrap(), "\x1bOw");
+        assert_eq!(encode(&Keypad(KeypadKey::Enter), false, opts(false, 0), none).unwrap(), "\r");
+        assert_eq!(encode(&Keypad(KeypadKey::Enter), false, opts(true, 0), none).unwrap(), "\x1bOM");
+        assert_eq!(encode(&Keypad(KeypadKey::Begin), false, opts(true, 0), none).unwrap(), "\x1b[E");
+        let ctrl = mods(&[CtrlLeft]);
+        assert_eq!(encode(&Keypad(KeypadKey::Add), false, opts(true, 0), ctrl).unwrap(), "\x1bO5k");
+    }
+
+    #[test]
+    fn alt_keys() {
+        let alt = mods(&[AltLeft]);
+        assert_eq!(encode(&Char('a'), false, opts(false, 0), alt).unwrap(), "\x1ba");
+        let eight_bit = AnsiOptions { alt_sends_esc: false, ..opts(false, 0) };
+        assert_eq!(encode(&Char('a'), false, eight_bit, alt).unwrap(), "\u{e1}");
+        let ctrl_alt = mods(&[CtrlLeft, AltLeft]);
+        assert_eq!(encode(&Char('a'), false, opts(false, 0), ctrl_alt).unwrap(), "\x1b\x01");
+    }
+
+    #[test]
+    fn modified_special_keys() {
+        let super_key = mods(&[SuperLeft]);
+        assert_eq!(encode(&UpArrow, false, opts(false, 0), super_key).unwrap(), "\x1b[1;9A");
+        let hyper_ctrl = mods(&[Hyper, CtrlLeft]);
+        assert_eq!(encode(&Delete, false, opts(false, 0), hyper_ctrl).unwrap(), "\x1b[3;21~");
+        let shift = mods(&[ShiftLeft]);
+        assert_eq!(encode(&Char('\t'), false, opts(false, 0), shift).unwrap(), "\x1b[Z");
+    }
+
+    #[test]
+    fn modify_other_keys() {
+        let ctrl = mods(&[CtrlLeft]);
+        let ctrl_shift = mods(&[CtrlLeft, ShiftLeft]);
+        let shift = mods(&[ShiftLeft]);
+        assert_eq!(encode(&Char('a'), false, opts(false, 0), ctrl).unwrap(), "\x01");
+        assert_eq!(encode(&Char('a'), false, opts(false, 1), ctrl).unwrap(), "\x01");
+        assert_eq!(encode(&Char('a'), false, opts(false, 2), ctrl).unwrap(), "\x1b[27;5;97~");
+        assert_eq!(encode(&Char('A'), false, opts(false, 1), ctrl_shift).unwrap(), "\x1b[27;6;65~");
+        assert_eq!(encode(&Char('1'), false, opts(false, 1), ctrl).unwrap(), "\x1b[27;5;49~");
+        assert_eq!(encode(&Enter, false, opts(false, 1), ctrl).unwrap(), "\x1b[27;5;13~");
+        assert_eq!(encode(&Char('A'), false, opts(false, 2), shift).unwrap(), "A");
+    }
+
+}
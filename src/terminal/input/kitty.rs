@@ -0,0 +1,272 @@
+//  notty is a new kind of terminal emulator.
+//  Copyright (C) 2015 without boats
+//  
+//  This program is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU Affero General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//  
+//  This program is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU Affero General Public License for more details.
+//  
+//  You should have received a copy of the GNU Affero General Public License
+//  along with this program.  If not, see <http://www.gnu.org/licenses/>.
+use std::borrow::Cow;
+
+use datatypes::{Key, KeyAction, KeypadKey, MediaKey};
+use datatypes::Key::*;
+
+use super::ansi::{self, AnsiOptions};
+use super::modifiers::{Modifiers, CAPS_LOCK, SHIFT};
+
+pub const DISAMBIGUATE:     u8 = 0x01;
+pub const EVENT_TYPES:      u8 = 0x02;
+pub const ALTERNATE_KEYS:   u8 = 0x04;
+pub const ALL_KEYS:         u8 = 0x08;
+pub const ASSOCIATED_TEXT:  u8 = 0x10;
+
+/// The kitty progressive enhancement keyboard protocol. The flags are the enhancements which
+/// the controlling process has requested; keys which are not affected by those enhancements
+/// are encoded the same as in ANSI mode.
+#[derive(Copy, Clone)]
+pub struct Kitty {
+    pub flags: u8,
+    pub app_mode: bool,
+}
+
+// How the number identifying a key is placed in the escape code.
+#[derive(Copy, Clone)]
+enum Code {
+    /// `CSI code ; modifiers u`
+    Unicode(u32),
+    /// `CSI code ; modifiers ~`
+    Tilde(u32),
+    /// `CSI 1 ; modifiers term`
+    Letter(char),
+}
+
+impl Kitty {
+
+    pub fn new(flags: u8, app_mode: bool) -> Kitty {
+        Kitty { flags: flags, app_mode: app_mode }
+    }
+
+    pub fn encode(&self, key: &Key, action: KeyAction, opts: AnsiOptions, mods: Modifiers)
+            -> Option<Cow<'static, str>> {
+        let all_keys = self.flags & ALL_KEYS != 0;
+        let event_types = self.flags & EVENT_TYPES != 0;
+        let press = action.is_press();
+        // Caps lock is only reported when every key is sent as an escape code, so that text
+        // keys keep their legacy encoding while it is on.
+        let bits = if all_keys { mods.kitty_bits() } else { mods.kitty_bits() & !CAPS_LOCK };
+        let event = match action {
+            KeyAction::Repeat if event_types        => 2,
+            KeyAction::Press | KeyAction::Repeat    => 1,
+            KeyAction::Release if event_types       => 3,
+            KeyAction::Release                      => return None,
+        };
+        let code = match *key {
+            Cmd(ref cmd)                => return if press { Some(cmd.clone()) } else { None },
+            MenuSelection(_)            => return ansi::encode(key, self.app_mode, opts, mods),
+            Char('\x1b')                => Code::Unicode(27),
+            Char(c @ '\t') if !all_keys && bits == 0       => return legacy(c, press),
+            Char(c) if !all_keys && bits & !SHIFT == 0 && c != '\t' => {
+                return legacy(c, press)
+            }
+            Char(c)                     => Code::Unicode(c as u32),
+            Enter if !all_keys && bits == 0                => return legacy('\r', press),
+            Enter                       => Code::Unicode(13),
+            Backspace if !all_keys && bits == 0            => return legacy('\x7f', press),
+            Backspace                   => Code::Unicode(127),
+            Keypad(k) if !all_keys && bits & !SHIFT == 0 && k.as_char().is_some()
+                                        => return legacy(k.as_char().unwrap(), press),
+            UpArrow                     => Code::Letter('A'),
+            DownArrow                   => Code::Letter('B'),
+            RightArrow                  => Code::Letter('C'),
+            LeftArrow                   => Code::Letter('D'),
+            Home                        => Code::Letter('H'),
+            End                         => Code::Letter('F'),
+            Insert                      => Code::Tilde(2),
+            Delete                      => Code::Tilde(3),
+            PageUp                      => Code::Tilde(5),
+            PageDown                    => Code::Tilde(6),
+            Function(1)                 => Code::Letter('P'),
+            Function(2)                 => Code::Letter('Q'),
+            Function(3)                 => Code::Tilde(13),
+            Function(4)                 => Code::Letter('S'),
+            Function(5)                 => Code::Tilde(15),
+            Function(n @ 6...10)        => Code::Tilde(11 + n as u32),
+            Function(n @ 11...12)       => Code::Tilde(12 + n as u32),
+            Function(n @ 13...24)       => Code::Unicode(57363 + n as u32),
+            Function(_)                 => return None,
+            Keypad(k)                   => Code::Unicode(keypad_code(k)),
+            Media(k)                    => Code::Unicode(media_code(k)),
+            _ if !all_keys              => return None,
+            CapsLock                    => Code::Unicode(57358),
+            ScrollLock                  => Code::Unicode(57359),
+            NumLock                     => Code::Unicode(57360),
+            Menu                        => Code::Unicode(57363),
+            ShiftLeft                   => Code::Unicode(57441),
+            CtrlLeft                    => Code::Unicode(57442),
+            AltLeft                     => Code::Unicode(57443),
+            SuperLeft                   => Code::Unicode(57444),
+            Hyper                       => Code::Unicode(57445),
+            Meta                        => Code::Unicode(57446),
+            ShiftRight                  => Code::Unicode(57447),
+            CtrlRight                   => Code::Unicode(57448),
+            SuperRight                  => Code::Unicode(57450),
+            AltGr                       => Code::Unicode(57453),
+        };
+        let mods_field = match (1 + bits, event) {
+            (m, 1) if m == 1    => String::new(),
+            (m, 1)              => m.to_string(),
+            (m, e)              => format!("{}:{}", m, e),
+        };
+        // Unmodified functional keys without event reporting keep their legacy encoding.
+        if mods_field.is_empty() && !all_keys {
+            match code {
+                Code::Letter(_) | Code::Tilde(_) => {
+                    return ansi::encode(key, self.app_mode, opts, mods);
+                }
+                _   => (),
+            }
+        }
+        Some(Cow::Owned(match code {
+            Code::Unicode(n)    => {
+                let mut code = n.to_string();
+                let mut text = String::new();
+                if let Char(c) = *key {
+                    let base = c.to_lowercase().next().unwrap_or(c);
+                    if base != c {
+                        code = (base as u32).to_string();
+                        if self.flags & ALTERNATE_KEYS != 0 && bits & SHIFT != 0 {
+                            code = format!("{}:{}", code, c as u32);
+                        }
+                    }
+                    if all_keys && self.flags & ASSOCIATED_TEXT != 0 && press &&
+                            bits & !(SHIFT | CAPS_LOCK) == 0 && !c.is_control() {
+                        text = (c as u32).to_string();
+                    }
+                }
+                match (mods_field.is_empty(), text.is_empty()) {
+                    (true, true)    => format!("\x1b[{}u", code),
+                    (false, true)   => format!("\x1b[{};{}u", code, mods_field),
+                    (true, false)   => format!("\x1b[{};1;{}u", code, text),
+                    (false, false)  => format!("\x1b[{};{};{}u", code, mods_field, text),
+                }
+            }
+            Code::Tilde(n) if mods_field.is_empty()     => format!("\x1b[{}~", n),
+            Code::Tilde(n)                              => format!("\x1b[{};{}~", n, mods_field),
+            Code::Letter(c) if mods_field.is_empty()    => format!("\x1b[{}", c),
+            Code::Letter(c)                             => format!("\x1b[1;{}{}", mods_field, c),
+        }))
+    }
+
+}
+
+fn legacy(c: char, press: bool) -> Option<Cow<'static, str>> {
+    if press { Some(Cow::Owned(c.to_string())) } else { None }
+}
+
+fn keypad_code(key: KeypadKey) -> u32 {
+    match key {
+        KeypadKey::Digit(n)     => 57399 + n as u32,
+        KeypadKey::Decimal      => 57409,
+        KeypadKey::Divide       => 57410,
+        KeypadKey::Multiply     => 57411,
+        KeypadKey::Subtract     => 57412,
+        KeypadKey::Add          => 57413,
+        KeypadKey::Enter        => 57414,
+        KeypadKey::Equal        => 57415,
+        KeypadKey::Separator    => 57416,
+        KeypadKey::Begin        => 57427,
+    }
+}
+
+fn media_code(key: MediaKey) -> u32 {
+    match key {
+        MediaKey::Play          => 57428,
+        MediaKey::Pause         => 57429,
+        MediaKey::PlayPause     => 57430,
+        MediaKey::Stop          => 57432,
+        MediaKey::Next          => 57435,
+        MediaKey::Previous      => 57436,
+        MediaKey::VolumeDown    => 57438,
+        MediaKey::VolumeUp      => 57439,
+        MediaKey::Mute          => 57440,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use datatypes::{Key, KeyAction};
+    use datatypes::Key::*;
+    use datatypes::KeyAction::*;
+
+    use super::*;
+    use super::super::ansi::AnsiOptions;
+    use super::super::modifiers::Modifiers;
+
+    fn mods(keys: &[Key]) -> Modifiers {
+        let mut mods = Modifiers::new();
+        for key in keys { mods.apply(key, true); }
+        mods
+    }
+
+    fn encode(flags: u8, key: Key, action: KeyAction, held: &[Key]) -> Option<String> {
+        Kitty::new(flags, false).encode(&key, action, AnsiOptions::default(), mods(held))
+                                .map(|s| s.into_owned())
+    }
+
+    #[test]
+    fn disambiguate() {
+        assert_eq!(encode(DISAMBIGUATE, Char('a'), Press, &[]).unwrap(), "a");
+        assert_eq!(encode(DISAMBIGUATE, Char('A'), Press, &[ShiftLeft]).unwrap(), "A");
+        assert_eq!(encode(DISAMBIGUATE, Char('\x1b'), Press, &[]).unwrap(), "\x1b[27u");
+        assert_eq!(encode(DISAMBIGUATE, Char('a'), Press, &[CtrlLeft]).unwrap(), "\x1b[97;5u");
+        assert_eq!(encode(DISAMBIGUATE, Char('a'), Press, &[AltLeft]).unwrap(), "\x1b[97;3u");
+        assert_eq!(encode(DISAMBIGUATE, Enter, Press, &[]).unwrap(), "\r");
+        assert_eq!(encode(DISAMBIGUATE, Enter, Press, &[CtrlLeft]).unwrap(), "\x1b[13;5u");
+        assert_eq!(encode(DISAMBIGUATE, UpArrow, Press, &[]).unwrap(), "\x1b[A");
+        assert_eq!(encode(DISAMBIGUATE, UpArrow, Press, &[CtrlLeft]).unwrap(), "\x1b[1;5A");
+        assert_eq!(encode(DISAMBIGUATE, Function(3), Press, &[ShiftLeft]).unwrap(), "\x1b[13;2~");
+        assert!(encode(DISAMBIGUATE, Char('a'), Release, &[]).is_none());
+        assert!(encode(DISAMBIGUATE, ShiftLeft, Press, &[]).is_none());
+        assert_eq!(encode(DISAMBIGUATE, Char('a'), Repeat, &[CtrlLeft]).unwrap(), "\x1b[97;5u");
+    }
+
+    #[test]
+    fn event_types() {
+        let flags = DISAMBIGUATE | EVENT_TYPES;
+        assert_eq!(encode(flags, Char('a'), Press, &[CtrlLeft]).unwrap(), "\x1b[97;5u");
+        assert_eq!(encode(flags, Char('a'), Release, &[CtrlLeft]).unwrap(), "\x1b[97;5:3u");
+        assert_eq!(encode(flags, UpArrow, Release, &[]).unwrap(), "\x1b[1;1:3A");
+        assert_eq!(encode(flags, Char('a'), Repeat, &[CtrlLeft]).unwrap(), "\x1b[97;5:2u");
+        assert_eq!(encode(flags, Char('a'), Repeat, &[]).unwrap(), "a");
+        assert!(encode(flags, Char('a'), Release, &[]).is_none());
+    }
+
+    #[test]
+    fn all_keys() {
+        let flags = DISAMBIGUATE | ALL_KEYS | ASSOCIATED_TEXT;
+        assert_eq!(encode(flags, Char('a'), Press, &[]).unwrap(), "\x1b[97;1;97u");
+        assert_eq!(encode(flags, Enter, Press, &[]).unwrap(), "\x1b[13u");
+        assert_eq!(encode(flags, ShiftLeft, Press, &[ShiftLeft]).unwrap(), "\x1b[57441;2u");
+        let flags = flags | ALTERNATE_KEYS;
+        assert_eq!(encode(flags, Char('A'), Press, &[ShiftLeft]).unwrap(), "\x1b[97:65;2;65u");
+        assert_eq!(encode(flags, Char('A'), Press, &[CapsLock]).unwrap(), "\x1b[97;65;65u");
+    }
+
+    #[test]
+    fn caps_lock() {
+        assert_eq!(encode(DISAMBIGUATE, Char('A'), Press, &[CapsLock]).unwrap(), "A");
+        assert_eq!(encode(DISAMBIGUATE, Char('a'), Press, &[CapsLock, CtrlLeft]).unwrap(),
+                   "\x1b[97;5u");
+        assert_eq!(encode(DISAMBIGUATE | ALL_KEYS, Enter, Press, &[CapsLock]).unwrap(),
+                   "\x1b[13;65u");
+    }
+
+}
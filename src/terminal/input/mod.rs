@@ -13,19 +13,23 @@
 //  
 //  You should have received a copy of the GNU Affero General Public License
 //  along with this program.  If not, see <http://www.gnu.org/licenses/>.
+use std::cmp;
 use std::io::{self, Write};
 
 use Command;
-use datatypes::{InputSettings, Key};
+use datatypes::{InputSettings, Key, KeyAction};
 
 mod buffer;
 mod ansi;
+mod kitty;
 mod line_echo;
 mod modifiers;
 mod notty;
 mod screen_echo;
 
+use self::ansi::AnsiOptions;
 use self::buffer::InputBuffer;
+use self::kitty::Kitty;
 use self::line_echo::LineEcho;
 use self::modifiers::Modifiers;
 use self::notty::Extended;
@@ -33,13 +37,16 @@ use self::screen_echo::ScreenEcho;
 use self::InputMode::*;
 
 pub trait Tty: Write {
-    fn set_winsize(&mut self, u16, u16) -> io::Result<()>;
+    /// Set the size of the tty in columns and rows, and the size of its text area in pixels.
+    fn set_winsize(&mut self, u16, u16, u16, u16) -> io::Result<()>;
 }
 
 pub struct Input {
     tty: Box<Tty + Send>,
     mode: InputMode,
     paste_mode: PasteMode,
+    ansi: AnsiOptions,
+    keyboard_flags: u8,
     modifiers: Modifiers,
 }
 
@@ -50,12 +57,16 @@ impl Input {
             tty: Box::new(tty),
             mode: Ansi(false),
             paste_mode: PasteMode::Silent,
+            ansi: AnsiOptions::default(),
+            keyboard_flags: 0,
             modifiers: Modifiers::new(),
         }
     }
 
     pub fn set_mode(&mut self, mode: InputSettings) {
         match mode {
+            InputSettings::Ansi(flag) if self.keyboard_flags != 0   =>
+                self.mode = Kitty(Kitty::new(self.keyboard_flags, flag)),
             InputSettings::Ansi(flag)                   =>
                 self.mode = Ansi(flag),
             InputSettings::Notty(_)                     =>
@@ -74,14 +85,53 @@ impl Input {
         };
     }
 
-    pub fn set_winsize(&mut self, width: u32, height: u32) -> io::Result<()> {
-        self.tty.set_winsize(width as u16, height as u16)
+    /// Set whether the numeric keypad is in application mode (DECKPAM) or numeric mode
+    /// (DECKPNM).
+    pub fn set_keypad_mode(&mut self, app_mode: bool) {
+        self.ansi.keypad_mode = app_mode;
     }
 
-    pub fn write(&mut self, key: Key, press: bool) -> io::Result<Option<Command>> {
-        if key.is_modifier() { self.modifiers.apply(&key, press); }
-        let key = if self.modifiers.ctrl() { key.ctrl_modify() } else { key };
-        self.mode.write(key, press, &mut self.tty, self.modifiers)
+    /// Set whether Alt prefixes characters with an escape or sets their eighth bit (the xterm
+    /// altSendsEscape mode).
+    pub fn set_alt_sends_esc(&mut self, flag: bool) {
+        self.ansi.alt_sends_esc = flag;
+    }
+
+    /// Set the xterm modifyOtherKeys level; levels above 2 are treated as 2.
+    pub fn set_modify_other_keys(&mut self, level: u8) {
+        self.ansi.modify_other_keys = level;
+    }
+
+    /// Encode keys with the kitty keyboard protocol using these flags, or turn the protocol
+    /// off if they are 0.
+    pub fn use_keyboard_flags(&mut self, flags: u8) {
+        if flags == self.keyboard_flags { return }
+        self.keyboard_flags = flags;
+        self.update_keyboard_mode();
+    }
+
+    fn update_keyboard_mode(&mut self) {
+        let flags = self.keyboard_flags;
+        self.mode = match self.mode {
+            Ansi(app_mode) if flags != 0    => Kitty(Kitty::new(flags, app_mode)),
+            Kitty(kitty) if flags != 0      => Kitty(Kitty::new(flags, kitty.app_mode)),
+            Kitty(kitty)                    => Ansi(kitty.app_mode),
+            _                               => return,
+        };
+    }
+
+    pub fn set_winsize(&mut self, width: u32, height: u32, x_pixels: u32, y_pixels: u32)
+            -> io::Result<()> {
+        // Sizes too large for the tty are given as the largest it can hold, not truncated.
+        let clamp = |n: u32| cmp::min(n, !0u16 as u32) as u16;
+        self.tty.set_winsize(clamp(width), clamp(height), clamp(x_pixels), clamp(y_pixels))
+    }
+
+    pub fn write(&mut self, key: Key, action: KeyAction) -> io::Result<Option<Command>> {
+        if key.is_modifier() && action != KeyAction::Repeat {
+            self.modifiers.apply(&key, action == KeyAction::Press);
+        }
+        self.mode.write(key, action, &mut self.tty, self.modifiers, self.ansi)
     }
 
     pub fn paste(&mut self, data: &str) -> io::Result<Option<Command>> {
@@ -92,6 +142,7 @@ impl Input {
 
 enum InputMode {
     Ansi(bool),
+    Kitty(Kitty),
     ExtendedRaw(Extended),
     ExtendedLineBuffer(LineEcho, InputBuffer),
     ExtendedScreen(ScreenEcho, Extended),
@@ -105,28 +156,39 @@ enum PasteMode {
 
 impl InputMode {
 
-    fn write(&mut self, key: Key, press: bool, tty: &mut Write, modifiers: Modifiers)
-            -> io::Result<Option<Command>> {
+    fn write(&mut self, key: Key, action: KeyAction, tty: &mut Write, modifiers: Modifiers,
+             opts: AnsiOptions) -> io::Result<Option<Command>> {
+        // ANSI encoding applies the control modifier itself, because of modifyOtherKeys.
+        let key = match *self {
+            Ansi(_) | Kitty(_)          => key,
+            _ if modifiers.ctrl()       => key.ctrl_modify(),
+            _                           => key,
+        };
         match *self {
-            Ansi(app_mode) if press && !key.is_modifier() => {
-                if let Some(data) = ansi::encode(&key, app_mode, modifiers) {
+            Ansi(app_mode) if action.is_press() && !key.is_modifier() => {
+                if let Some(data) = ansi::encode(&key, app_mode, opts, modifiers) {
+                    tty.write_all(data.as_bytes()).and(Ok(None))
+                } else { Ok(None) }
+            }
+            Kitty(kitty) if !key.is_modifier() || kitty.flags & kitty::ALL_KEYS != 0 => {
+                if let Some(data) = kitty.encode(&key, action, opts, modifiers) {
                     tty.write_all(data.as_bytes()).and(Ok(None))
                 } else { Ok(None) }
             }
             ExtendedRaw(notty)                  => {
-                let data = notty.encode(&key, press, modifiers);
+                let data = notty.encode(&key, action, modifiers);
                 tty.write_all(data.as_bytes()).and(Ok(None))
             }
-            ExtendedLineBuffer(ref mut echo, ref mut buffer) => {
+            ExtendedLineBuffer(ref mut echo, ref mut buffer) if action.is_press() => {
                 if let Some(data) = buffer.write(&key, echo.settings) {
                     try!(tty.write_all(data.as_bytes()))
                 }
-                if press { Ok(echo.echo(key)) } else { Ok(None) }
+                Ok(echo.echo(key))
             }
             ExtendedScreen(ref mut echo, notty) => {
-                let data = notty.encode(&key, press, modifiers);
+                let data = notty.encode(&key, action, modifiers);
                 try!(tty.write_all(data.as_bytes()));
-                if press { Ok(echo.echo(key)) } else { Ok(None) }
+                if action.is_press() { Ok(echo.echo(key)) } else { Ok(None) }
             }
             _                                   => Ok(None)
         }
@@ -135,9 +197,9 @@ impl InputMode {
     fn paste(&self, data: &str, tty: &mut Write, paste_mode: PasteMode)
             -> io::Result<Option<Command>> {
         match (self, paste_mode) {
-            (&Ansi(_), PasteMode::Bracketed)    =>
+            (&Ansi(_), PasteMode::Bracketed) | (&Kitty(_), PasteMode::Bracketed)   =>
                 write!(tty, "\x1b[200~{}\x1b[201~", data).and(Ok(None)),
-            (&Ansi(_), PasteMode::Silent)       =>
+            (&Ansi(_), PasteMode::Silent) | (&Kitty(_), PasteMode::Silent)         =>
                 write!(tty, "{}", data).and(Ok(None)),
             _               => unimplemented!(),
         }
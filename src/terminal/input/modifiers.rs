@@ -16,6 +16,14 @@
 use datatypes::Key;
 use datatypes::Key::*;
 
+pub const SHIFT: u8 = 0x01;
+pub const ALT:   u8 = 0x02;
+pub const CTRL:  u8 = 0x04;
+pub const SUPER: u8 = 0x08;
+pub const HYPER: u8 = 0x10;
+pub const META:  u8 = 0x20;
+pub const CAPS_LOCK: u8 = 0x40;
+
 #[derive(Copy, Clone)]
 pub struct Modifiers {
     lshift: bool,
@@ -25,6 +33,10 @@ pub struct Modifiers {
     rctrl: bool,
     lalt: bool,
     ralt: bool,
+    lsuper: bool,
+    rsuper: bool,
+    hyper: bool,
+    meta: bool,
 }
 
 impl Modifiers {
@@ -37,6 +49,10 @@ impl Modifiers {
             rctrl: false,
             lalt: false,
             ralt: false,
+            lsuper: false,
+            rsuper: false,
+            hyper: false,
+            meta: false,
         }
     }
 
@@ -52,8 +68,49 @@ impl Modifiers {
         self.lalt || self.ralt
     }
 
-    pub fn triplet(&self) -> (bool, bool, bool) {
-        (self.shift(), self.ctrl(), self.alt())
+    pub fn super_key(&self) -> bool {
+        self.lsuper || self.rsuper
+    }
+
+    pub fn hyper(&self) -> bool {
+        self.hyper
+    }
+
+    pub fn meta(&self) -> bool {
+        self.meta
+    }
+
+    /// The modifiers held as a bit field, using the `SHIFT`, `ALT`, `CTRL`, `SUPER`, `HYPER`
+    /// and `META` flags.
+    pub fn bits(&self) -> u8 {
+        let mut bits = 0;
+        if self.shift()     { bits |= SHIFT }
+        if self.alt()       { bits |= ALT }
+        if self.ctrl()      { bits |= CTRL }
+        if self.super_key() { bits |= SUPER }
+        if self.hyper()     { bits |= HYPER }
+        if self.meta()      { bits |= META }
+        bits
+    }
+
+    /// The modifiers as the kitty keyboard protocol reports them: shift is only set by the
+    /// shift keys themselves, and caps lock has a bit of its own.
+    pub fn kitty_bits(&self) -> u8 {
+        let mut bits = self.bits() & !SHIFT;
+        if self.lshift || self.rshift   { bits |= SHIFT }
+        if self.caps                    { bits |= CAPS_LOCK }
+        bits
+    }
+
+    /// The modifier parameter xterm uses in escape codes for modified keys, where 1 means no
+    /// modifiers are held.
+    pub fn param(&self) -> u8 {
+        1 + self.bits()
+    }
+
+    /// These modifiers with shift held down.
+    pub fn shifted(self) -> Modifiers {
+        if self.shift() { self } else { Modifiers { caps: !self.caps, ..self } }
     }
 
     pub fn apply(&mut self, key: &Key, press: bool) {
@@ -64,7 +121,12 @@ impl Modifiers {
             CtrlRight           => self.rctrl = press,
             AltLeft             => self.lalt = press,
             AltGr               => self.ralt = press,
+            SuperLeft           => self.lsuper = press,
+            SuperRight          => self.rsuper = press,
+            Hyper               => self.hyper = press,
+            Meta                => self.meta = press,
             CapsLock if press   => self.caps = !self.caps,
+            CapsLock            => (),
             _                   => unreachable!(),
         }
     }
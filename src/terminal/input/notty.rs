@@ -15,84 +15,106 @@
 //  along with this program.  If not, see <http://www.gnu.org/licenses/>.
 use std::borrow::Cow;
 
-use datatypes::Key;
+use datatypes::{Key, KeyAction, KeypadKey, MediaKey};
 use datatypes::Key::*;
-use super::modifiers::Modifiers;
-
-macro_rules! key {
-    ($k:expr, $press:expr, $mods:expr) =>(match ($mods.triplet(), $press) {
-        ((false, false, false), false)  => Cow::Borrowed(concat!("\x1b{0;",$k,"}")),
-        ((false, false, false), true)   => Cow::Borrowed(concat!("\x1b{1;",$k,"}")),
-        ((false, false, true),  false)  => Cow::Borrowed(concat!("\x1b{2;",$k,"}")),
-        ((false, false, true),  true)   => Cow::Borrowed(concat!("\x1b{3;",$k,"}")),
-        ((false, true,  false), false)  => Cow::Borrowed(concat!("\x1b{4;",$k,"}")),
-        ((false, true,  false), true)   => Cow::Borrowed(concat!("\x1b{5;",$k,"}")),
-        ((false, true,  true),  false)  => Cow::Borrowed(concat!("\x1b{6;",$k,"}")),
-        ((false, true,  true),  true)   => Cow::Borrowed(concat!("\x1b{7;",$k,"}")),
-        ((true,  false, false), false)  => Cow::Borrowed(concat!("\x1b{8;",$k,"}")),
-        ((true,  false, false), true)   => Cow::Borrowed(concat!("\x1b{9;",$k,"}")),
-        ((true,  false, true),  false)  => Cow::Borrowed(concat!("\x1b{a;",$k,"}")),
-        ((true,  false, true),  true)   => Cow::Borrowed(concat!("\x1b{b;",$k,"}")),
-        ((true,  true,  false), false)  => Cow::Borrowed(concat!("\x1b{c;",$k,"}")),
-        ((true,  true,  false), true)   => Cow::Borrowed(concat!("\x1b{d;",$k,"}")),
-        ((true,  true,  true),  false)  => Cow::Borrowed(concat!("\x1b{e;",$k,"}")),
-        ((true,  true,  true),  true)   => Cow::Borrowed(concat!("\x1b{f;",$k,"}")),
-    });
-}
+use super::modifiers::{Modifiers, SHIFT, ALT, CTRL};
 
 #[derive(Copy, Clone)]
 pub struct Extended;
 
 impl Extended {
 
-    pub fn encode(&self, key: &Key, press: bool, mods: Modifiers) -> Cow<'static, str> {
+    pub fn encode(&self, key: &Key, action: KeyAction, mods: Modifiers) -> Cow<'static, str> {
         match *key {
-            Char(c)             => char_key(c, press, mods),
-            Enter               => char_key('\n', press, mods),
-            Backspace           => char_key('\x08', press, mods),
-            Delete              => char_key('\x7f', press, mods),
-            UpArrow             => key!('1', press, mods),
-            DownArrow           => key!('2', press, mods),
-            LeftArrow           => key!('3', press, mods),
-            RightArrow          => key!('4', press, mods),
-            PageUp              => key!('5', press, mods),
-            PageDown            => key!('6', press, mods),
-            Home                => key!('7', press, mods),
-            End                 => key!('8', press, mods),
-            Insert              => key!('9', press, mods),
-            ShiftLeft           => key!("a", press, mods),
-            ShiftRight          => key!("a", press, mods),
-            CtrlLeft            => key!("b", press, mods),
-            CtrlRight           => key!("b", press, mods),
-            AltLeft             => key!("c", press, mods),
-            AltGr               => key!("d", press, mods),
-            Meta                => key!("e", press, mods),
-            Menu                => key!("f", press, mods),
-            NumLock             => unimplemented!(),
-            ScrollLock          => unimplemented!(),
-            CapsLock            => unimplemented!(), 
-            Function(_)         => unimplemented!(),
+            Char(c)             => char_key(c, action, mods),
+            Enter               => char_key('\n', action, mods),
+            Backspace           => char_key('\x08', action, mods),
+            Delete              => char_key('\x7f', action, mods),
+            UpArrow             => coded_key(0x1, action, mods),
+            DownArrow           => coded_key(0x2, action, mods),
+            LeftArrow           => coded_key(0x3, action, mods),
+            RightArrow          => coded_key(0x4, action, mods),
+            PageUp              => coded_key(0x5, action, mods),
+            PageDown            => coded_key(0x6, action, mods),
+            Home                => coded_key(0x7, action, mods),
+            End                 => coded_key(0x8, action, mods),
+            Insert              => coded_key(0x9, action, mods),
+            ShiftLeft           => coded_key(0xa, action, mods),
+            ShiftRight          => coded_key(0xa, action, mods),
+            CtrlLeft            => coded_key(0xb, action, mods),
+            CtrlRight           => coded_key(0xb, action, mods),
+            AltLeft             => coded_key(0xc, action, mods),
+            AltGr               => coded_key(0xd, action, mods),
+            Meta                => coded_key(0xe, action, mods),
+            Menu                => coded_key(0xf, action, mods),
+            CapsLock            => coded_key(0x10, action, mods),
+            NumLock             => coded_key(0x11, action, mods),
+            ScrollLock          => coded_key(0x12, action, mods),
+            SuperLeft           => coded_key(0x13, action, mods),
+            SuperRight          => coded_key(0x13, action, mods),
+            Hyper               => coded_key(0x14, action, mods),
+            Function(n)         => coded_key(0x20 + n as u32, action, mods),
+            Keypad(k)           => coded_key(keypad_code(k), action, mods),
+            Media(k)            => coded_key(media_code(k), action, mods),
             Cmd(ref s)          => s.clone(),
-            MenuSelection(_)    => unimplemented!(),
+            MenuSelection(n)    => Cow::Owned(format!("\x1b{{51;{:x}}}", n)),
         }
     }
 
 }
 
-fn char_key(c: char, press: bool, mods: Modifiers) -> Cow<'static, str> {
-    match (mods.triplet(), press) {
-        ((_,     _,     false), false)  => Cow::Owned(c.to_string()),
-        ((false, false, false), true)   => Cow::Owned(format!("\x1b{{1{{{}}}", c)),
-        ((false, false, true),  false)  => Cow::Owned(format!("\x1b{{2{{{}}}", c)),
-        ((false, false, true),  true)   => Cow::Owned(format!("\x1b{{3{{{}}}", c)),
-        ((false, true,  false), true)   => Cow::Owned(format!("\x1b{{5{{{}}}", c)),
-        ((false, true,  true),  false)  => Cow::Owned(format!("\x1b{{6{{{}}}", c)),
-        ((false, true,  true),  true)   => Cow::Owned(format!("\x1b{{7{{{}}}", c)),
-        ((true,  false, false), true)   => Cow::Owned(format!("\x1b{{9{{{}}}", c)),
-        ((true,  false, true),  false)  => Cow::Owned(format!("\x1b{{a{{{}}}", c)),
-        ((true,  false, true),  true)   => Cow::Owned(format!("\x1b{{b{{{}}}", c)),
-        ((true,  true,  false), true)   => Cow::Owned(format!("\x1b{{d{{{}}}", c)),
-        ((true,  true,  true),  false)  => Cow::Owned(format!("\x1b{{e{{{}}}", c)),
-        ((true,  true,  true),  true)   => Cow::Owned(format!("\x1b{{f{{{}}}", c)),
+// The first argument of an extended key code. The low bit is set for presses; the next three
+// bits are alt, ctrl and shift, and the next three are super, hyper and meta. Repeats set the
+// press bit as well as 0x80, so that programs which do not care about them see a press.
+fn flags(action: KeyAction, mods: Modifiers) -> u32 {
+    let bits = mods.bits();
+    let mut flags = match action {
+        KeyAction::Press    => 0x1,
+        KeyAction::Repeat   => 0x81,
+        KeyAction::Release  => 0x0,
+    };
+    if bits & ALT != 0      { flags |= 0x2 }
+    if bits & CTRL != 0     { flags |= 0x4 }
+    if bits & SHIFT != 0    { flags |= 0x8 }
+    flags | ((bits & !(SHIFT | ALT | CTRL)) as u32) << 1
+}
+
+fn coded_key(code: u32, action: KeyAction, mods: Modifiers) -> Cow<'static, str> {
+    Cow::Owned(format!("\x1b{{{:x};{:x}}}", flags(action, mods), code))
+}
+
+fn char_key(c: char, action: KeyAction, mods: Modifiers) -> Cow<'static, str> {
+    match flags(action, mods) {
+        0   => Cow::Owned(c.to_string()),
+        n   => Cow::Owned(format!("\x1b{{{:x}{{{}}}", n, c)),
+    }
+}
+
+fn keypad_code(key: KeypadKey) -> u32 {
+    match key {
+        KeypadKey::Digit(n)     => 0x40 + n as u32,
+        KeypadKey::Decimal      => 0x4a,
+        KeypadKey::Separator    => 0x4b,
+        KeypadKey::Divide       => 0x4c,
+        KeypadKey::Multiply     => 0x4d,
+        KeypadKey::Subtract     => 0x4e,
+        KeypadKey::Add          => 0x4f,
+        KeypadKey::Equal        => 0x50,
+        KeypadKey::Enter        => 0x51,
+        KeypadKey::Begin        => 0x52,
+    }
+}
+
+fn media_code(key: MediaKey) -> u32 {
+    match key {
+        MediaKey::Play          => 0x60,
+        MediaKey::Pause         => 0x61,
+        MediaKey::PlayPause     => 0x62,
+        MediaKey::Stop          => 0x63,
+        MediaKey::Next          => 0x64,
+        MediaKey::Previous      => 0x65,
+        MediaKey::VolumeUp      => 0x66,
+        MediaKey::VolumeDown    => 0x67,
+        MediaKey::Mute          => 0x68,
     }
 }
@@ -13,40 +13,100 @@
 //  
 //  You should have received a copy of the GNU Affero General Public License
 //  along with this program.  If not, see <http://www.gnu.org/licenses/>.
+use std::borrow::Cow;
+use std::cmp;
+use std::collections::HashMap;
 use std::io::{self};
 use std::ops::{Deref, DerefMut};
+use std::sync::{Arc, Weak};
 use std::sync::atomic::Ordering::Relaxed;
 
+mod bell;
 mod char_grid;
+mod clipboard;
+mod events;
+mod images;
 mod screen;
 mod input;
+mod notification;
+mod palette;
+mod preedit;
+mod window;
+
+use base64;
+use mime::Mime;
 
 use Command;
-use datatypes::{InputSettings, Key};
+use datatypes::{CellData, Coords, InputSettings, Key, KeyAction, MediaAlignment, MediaPosition};
+use datatypes::Movement::{Column, NextLine};
 
+pub use self::bell::BellSettings;
 pub use self::char_grid::*;
+pub use self::clipboard::{ClipboardPolicy, Selection};
+pub use self::events::{TerminalEvents, NoEvents};
+pub use self::images::{StoredImage, PlaceParams, DeleteSpec, DEFAULT_CELL_SIZE};
 pub use self::input::Tty;
+pub use self::notification::Notification;
+pub use self::palette::{Palette, DynamicColor, Rgb, parse_color, format_color};
+pub use self::preedit::Preedit;
 pub use self::screen::{Screen, Cells, Panels};
+pub use self::window::{TitleKind, WindowOp, WindowPolicy, WindowReport};
 
+use self::bell::BellState;
+use self::images::{ImageBudget, ImageStore};
 use self::input::Input;
-use cfg::{TAB_STOP, SCROLLBACK};
+use cfg::{TAB_STOP, SCROLLBACK, IMAGE_BUDGET};
+
+// The depth of the stack of saved titles; the oldest entries are discarded.
+const MAX_TITLES: usize = 10;
+
+// The memory images may use if no budget has been configured.
+const DEFAULT_IMAGE_BUDGET: usize = 0x10000000;
 
 pub struct Terminal {
     title: String,
+    icon: String,
+    titles: Vec<(Option<String>, Option<String>)>,
     screen: Screen,
     tty: Input,
+    preedit: Option<Preedit>,
+    links: HashMap<Hyperlink, Weak<Hyperlink>>,
+    palette: Palette,
+    default_palette: Palette,
+    clipboard_policy: ClipboardPolicy,
+    events: Box<TerminalEvents>,
+    bell_settings: BellSettings,
+    bell: BellState,
+    window_policy: WindowPolicy,
+    cell_size: (u32, u32),
+    images: ImageStore,
+    image_budget: ImageBudget,
 }
 
 impl Terminal {
 
-    pub fn new<W: Tty + Send + 'static>(width: u32, height: u32, tty: W)
-            -> Terminal {
+    pub fn new<W, E>(width: u32, height: u32, tty: W, events: E) -> Terminal
+            where W: Tty + Send + 'static, E: TerminalEvents + 'static {
         if TAB_STOP.load(Relaxed) == 0 { TAB_STOP.store(4, Relaxed) };
         if SCROLLBACK.load(Relaxed) == 0 { SCROLLBACK.store(-1, Relaxed) };
         Terminal {
             title: String::new(),
+            icon: String::new(),
+            titles: Vec::new(),
             screen: Screen::new(width, height),
             tty: Input::new(tty),
+            preedit: None,
+            links: HashMap::new(),
+            palette: Palette::default(),
+            default_palette: Palette::default(),
+            clipboard_policy: ClipboardPolicy::default(),
+            events: Box::new(events),
+            bell_settings: BellSettings::default(),
+            bell: BellState::default(),
+            window_policy: WindowPolicy::default(),
+            cell_size: (0, 0),
+            images: ImageStore::default(),
+            image_budget: ImageBudget::default(),
         }
     }
 
@@ -60,38 +120,495 @@ impl Terminal {
         } else { Ok(()) }
     }
 
-    pub fn send_input(&mut self, key: Key, press: bool) -> io::Result<()> {
+    pub fn clipboard_policy(&self) -> ClipboardPolicy {
+        self.clipboard_policy
+    }
+
+    pub fn set_clipboard_policy(&mut self, policy: ClipboardPolicy) {
+        self.clipboard_policy = policy;
+    }
+
+    /// Store text in the given selections, if the clipboard policy allows it.
+    pub fn store_clipboard(&mut self, selections: &[Selection], data: &str) {
+        if !self.clipboard_policy.can_write() { return }
+        for &selection in selections {
+            self.events.store_clipboard(selection, data);
+        }
+    }
+
+    /// Ask the embedding application for the contents of a selection in answer to an OSC 52
+    /// query, if the clipboard policy allows it. The answer comes later through
+    /// `report_clipboard`.
+    pub fn load_clipboard(&mut self, selection: Selection) {
+        if self.clipboard_policy.can_read() {
+            self.events.load_clipboard(selection);
+        }
+    }
+
+    /// Send the contents of a selection to the tty in answer to an OSC 52 query, if the
+    /// clipboard policy allows it.
+    pub fn report_clipboard(&mut self, selection: Selection, data: &str) -> io::Result<()> {
+        if !self.clipboard_policy.can_read() { return Ok(()) }
+        let data = match base64::encode(data) {
+            Ok(data)    => data,
+            Err(_)      => return Ok(()),
+        };
+        let cmd = format!("\x1b]52;{};{}\x1b\\", selection.as_char(), data);
+        self.send_input(Key::Cmd(Cow::Owned(cmd)), KeyAction::Press)
+    }
+
+    /// The colors the terminal should be drawn with.
+    pub fn palette(&self) -> &Palette {
+        &self.palette
+    }
+
+    /// Set the palette the terminal starts with and returns to when colors are reset. This
+    /// should be called by the frontend with the colors from its configuration.
+    pub fn set_default_palette(&mut self, palette: Palette) {
+        self.palette = palette.clone();
+        self.default_palette = palette;
+    }
+
+    pub fn set_palette_color(&mut self, n: u8, color: Rgb) {
+        self.palette.set_color(n, color);
+    }
+
+    /// Reset a color of the palette to its default, or every color if `n` is `None`.
+    pub fn reset_palette_color(&mut self, n: Option<u8>) {
+        match n {
+            Some(n) => self.palette.set_color(n, self.default_palette.color(n)),
+            None    => for n in 0..256 {
+                self.palette.set_color(n as u8, self.default_palette.color(n as u8));
+            },
+        }
+    }
+
+    pub fn set_dynamic_color(&mut self, color: DynamicColor, rgb: Rgb) {
+        self.palette.set_dynamic_color(color, rgb);
+    }
+
+    pub fn reset_dynamic_color(&mut self, color: DynamicColor) {
+        self.palette.set_dynamic_color(color, self.default_palette.dynamic_color(color));
+    }
+
+    /// Begin writing cells as part of a hyperlink, or stop if the link is `None`. Links with an
+    /// id are interned, so that a link written in several pieces is still a single link.
+    pub fn set_link(&mut self, link: Option<Hyperlink>) {
+        let link = match link {
+            Some(link @ Hyperlink { id: Some(_), .. })  => Some(self.intern_link(link)),
+            Some(link)                                  => Some(Arc::new(link)),
+            None                                        => None,
+        };
+        self.screen.set_link(link);
+    }
+
+    fn intern_link(&mut self, link: Hyperlink) -> Arc<Hyperlink> {
+        if let Some(link) = self.links.get(&link).and_then(Weak::upgrade) {
+            return link;
+        }
+        let dead = self.links.iter().filter(|&(_, weak)| weak.upgrade().is_none())
+                                    .map(|(key, _)| key.clone()).collect::<Vec<_>>();
+        for key in dead { self.links.remove(&key); }
+        let arc = Arc::new(link.clone());
+        self.links.insert(link, Arc::downgrade(&arc));
+        arc
+    }
+
+    /// The text currently being composed by an input method, if any.
+    pub fn preedit(&self) -> Option<&Preedit> {
+        self.preedit.as_ref()
+    }
+
+    pub fn set_preedit(&mut self, preedit: Preedit) {
+        self.preedit = if preedit.text().is_empty() { None } else { Some(preedit) };
+    }
+
+    pub fn clear_preedit(&mut self) {
+        self.preedit = None;
+    }
+
+    /// Commit text composed by an input method, ending the composition. The text is sent to
+    /// the tty as if each char had been typed.
+    pub fn commit_text(&mut self, text: &str) -> io::Result<()> {
+        self.preedit = None;
+        for c in text.chars() {
+            try!(self.send_input(Key::Char(c), KeyAction::Press));
+            try!(self.send_input(Key::Char(c), KeyAction::Release));
+        }
+        Ok(())
+    }
+
+    pub fn send_input(&mut self, key: Key, action: KeyAction) -> io::Result<()> {
+        self.update_keyboard_flags();
         if let Some(cmd) = try!(match key {
-            Key::DownArrow | Key::UpArrow | Key::Enter if press => {
+            Key::DownArrow | Key::UpArrow | Key::Enter if action.is_press() => {
                 let cursor = self.cursor_position();
                 match match self.tooltip_at_mut(cursor) {
                     Some(tooltip @ &mut Tooltip::Menu { .. })   => tooltip.interact(&key),
                     _                                           => Err(true)
                 } {
-                    Ok(n)       => self.tty.write(Key::MenuSelection(n), true),
-                    Err(true)   => self.tty.write(key, press),
+                    Ok(n)       => self.tty.write(Key::MenuSelection(n), KeyAction::Press),
+                    Err(true)   => self.tty.write(key, action),
                     Err(false)  => Ok(None),
                 }
             }
-            _           => self.tty.write(key, press),
+            _           => self.tty.write(key, action),
         }) {
             cmd.inner.apply(self)
         } else { Ok(()) }
     }
 
+    pub fn title(&self) -> &str {
+        &self.title
+    }
+
     pub fn set_title(&mut self, title: String) {
+        self.events.title_changed(&title);
         self.title = title;
     }
 
+    pub fn icon(&self) -> &str {
+        &self.icon
+    }
+
+    pub fn set_icon(&mut self, icon: String) {
+        self.events.icon_changed(&icon);
+        self.icon = icon;
+    }
+
+    /// The working directory of the program in the active grid, as reported with OSC 7. New
+    /// programs started for a split or pushed panel can be spawned here.
+    pub fn cwd(&self) -> Option<&WorkingDir> {
+        self.screen.cwd_of(None)
+    }
+
+    pub fn set_cwd(&mut self, cwd: WorkingDir) {
+        self.events.cwd_changed(&cwd);
+        self.screen.set_cwd(Some(cwd));
+    }
+
+    /// Save the title, the icon name or both, to be restored with `pop_title`.
+    pub fn push_title(&mut self, kind: TitleKind) {
+        if self.titles.len() == MAX_TITLES { self.titles.remove(0); }
+        let title = if kind.title() { Some(self.title.clone()) } else { None };
+        let icon = if kind.icon() { Some(self.icon.clone()) } else { None };
+        self.titles.push((title, icon));
+    }
+
+    /// Restore the title, the icon name or both from the most recently saved entry, if they
+    /// were saved in it.
+    pub fn pop_title(&mut self, kind: TitleKind) {
+        if let Some((title, icon)) = self.titles.pop() {
+            if let (true, Some(title)) = (kind.title(), title) { self.set_title(title); }
+            if let (true, Some(icon)) = (kind.icon(), icon) { self.set_icon(icon); }
+        }
+    }
+
+    pub fn window_policy(&self) -> WindowPolicy {
+        self.window_policy
+    }
+
+    pub fn set_window_policy(&mut self, policy: WindowPolicy) {
+        self.window_policy = policy;
+    }
+
+    /// Ask the embedding application to change the window, if the window policy allows it.
+    pub fn window_op(&mut self, op: WindowOp) {
+        if self.window_policy.allows() {
+            self.events.window_op(op);
+        }
+    }
+
+    /// The position of the window in pixels, if the embedding application knows it.
+    pub fn window_position(&mut self) -> Option<(u32, u32)> {
+        self.events.window_position()
+    }
+
+    pub fn is_iconified(&mut self) -> bool {
+        self.events.is_iconified()
+    }
+
     pub fn set_input_mode(&mut self, mode: InputSettings) {
         self.tty.set_mode(mode);
     }
 
+    pub fn set_keypad_mode(&mut self, app_mode: bool) {
+        self.tty.set_keypad_mode(app_mode);
+    }
+
+    pub fn set_alt_sends_esc(&mut self, flag: bool) {
+        self.tty.set_alt_sends_esc(flag);
+    }
+
+    pub fn set_modify_other_keys(&mut self, level: u8) {
+        self.tty.set_modify_other_keys(level);
+    }
+
+    pub fn keyboard_flags(&self) -> u8 {
+        self.screen.keyboard_flags()
+    }
+
+    pub fn push_keyboard_flags(&mut self, flags: u8) {
+        self.screen.push_keyboard_flags(flags);
+        self.update_keyboard_flags();
+    }
+
+    pub fn pop_keyboard_flags(&mut self, n: u32) {
+        self.screen.pop_keyboard_flags(n);
+        self.update_keyboard_flags();
+    }
+
+    pub fn set_keyboard_flags(&mut self, flags: u8, mode: u8) {
+        self.screen.set_keyboard_flags(flags, mode);
+        self.update_keyboard_flags();
+    }
+
+    // Keys are encoded with the kitty keyboard flags of the active grid, which changes when
+    // panels are pushed, popped or switched.
+    fn update_keyboard_flags(&mut self) {
+        let flags = self.screen.keyboard_flags();
+        self.tty.use_keyboard_flags(flags);
+    }
+
+    pub fn bell_settings(&self) -> BellSettings {
+        self.bell_settings
+    }
+
+    pub fn set_bell_settings(&mut self, settings: BellSettings) {
+        self.bell_settings = settings;
+    }
+
     pub fn bell(&mut self) {
-        println!("BELL");
+        if self.bell.ring(self.bell_settings) {
+            self.events.bell();
+        }
+    }
+
+    /// True for a short time after the bell rings, if the visual bell is enabled. The screen
+    /// should be drawn flashed while this is true.
+    pub fn is_flashing(&self) -> bool {
+        self.bell.is_flashing()
+    }
+
+    /// True if the bell has rung since the urgency was last cleared, and the urgent bell is
+    /// enabled. The embedding application should clear this when its window is focused.
+    pub fn is_urgent(&self) -> bool {
+        self.bell.is_urgent()
+    }
+
+    pub fn clear_urgent(&mut self) {
+        self.bell.clear_urgent();
+    }
+
+    pub fn notify(&mut self, notification: Notification) {
+        self.events.notify(notification);
+    }
+
+    /// Tell the terminal that the program on the tty has exited.
+    pub fn child_exited(&mut self, status: Option<i32>) {
+        self.events.child_exited(status);
+    }
+
+    /// The size of a cell in pixels, or (0, 0) if the renderer has not given it.
+    pub fn cell_size(&self) -> (u32, u32) {
+        self.cell_size
+    }
+
+    /// The size of the text area in pixels, or (0, 0) if the renderer has not given it.
+    pub fn pixel_size(&self) -> (u32, u32) {
+        let area = self.area();
+        (area.width() * self.cell_size.0, area.height() * self.cell_size.1)
+    }
+
+    /// Write data at the cursor. Images count against the memory budget for images.
+    pub fn write(&mut self, data: CellData) {
+        let image = if let CellData::Image { .. } = data { true } else { false };
+        let coords = self.cursor_position();
+        self.screen.write(data);
+        if image {
+            if let Some(data) = self.image_payload(coords) {
+                self.image_budget.track(&data);
+                self.enforce_image_budget();
+            }
+        }
+    }
+
+    /// Replace the data of the image at these coordinates, so that programs can stream the
+    /// frames of an animation into a cell. Returns false if there is no image there.
+    pub fn update_image(&mut self, coords: Coords, data: Vec<u8>, mime: Mime) -> bool {
+        match self.screen.update_image(coords, data, mime) {
+            Some(data)  => {
+                self.image_budget.track(&data);
+                self.enforce_image_budget();
+                true
+            }
+            None        => false,
+        }
     }
 
-    pub fn set_winsize(&mut self, cols: Option<u32>, rows: Option<u32>) -> io::Result<()> {
+    /// True while any image on the screen is an animation which has not finished playing. The
+    /// screen should be redrawn regularly while this is true.
+    pub fn is_animating(&self) -> bool {
+        self.grids().into_iter().any(CharGrid::is_animating)
+    }
+
+    // Once images use more memory than the budget, the oldest which are not visible in any
+    // grid are replaced with placeholders and removed from the image store.
+    fn enforce_image_budget(&mut self) {
+        let limit = match IMAGE_BUDGET.load(Relaxed) { 0 => DEFAULT_IMAGE_BUDGET, n => n };
+        // Finding the visible images means searching the whole grid, so it is only done once
+        // the images are over budget.
+        if self.image_budget.used() <= limit { return }
+        let mut visible = self.grids().into_iter().flat_map(CharGrid::visible_images)
+                                      .collect::<Vec<_>>();
+        for image in self.grids().into_iter().flat_map(CharGrid::visible_image_tiles) {
+            visible.extend(self.images.virtual_placement(image)
+                               .map(|placement| placement.data.data.clone()));
+        }
+        let evicted = self.image_budget.evict(limit, &visible);
+        if evicted.is_empty() { return }
+        for grid in self.grids_mut() {
+            grid.evict_images(&evicted);
+        }
+        self.images.evict(&evicted);
+    }
+
+    /// Store an image sent with the kitty graphics protocol under the given id, or a new one if
+    /// it is 0, and return the id.
+    pub fn transmit_image(&mut self, id: u32, number: u32, image: StoredImage) -> u32 {
+        self.image_budget.track(&image.data);
+        let id = self.images.insert(id, number, image);
+        self.enforce_image_budget();
+        id
+    }
+
+    /// The id of the image most recently transmitted with this number.
+    pub fn image_by_number(&self, number: u32) -> Option<u32> {
+        self.images.by_number(number)
+    }
+
+    /// Place a stored image with its top left corner at the cursor, or as a virtual placement
+    /// shown by unicode placeholder cells. Returns the error code to report to the program if
+    /// there is no image with this id.
+    pub fn place_image(&mut self, id: u32, placement: u32, params: PlaceParams)
+            -> Result<(), &'static str> {
+        let image = match self.images.get(id) {
+            Some(image) => image.clone(),
+            None        => return Err("ENOENT:no such image"),
+        };
+        let ((cell_w, cell_h), natural) = match self.cell_size {
+            (0, _) | (_, 0) => (DEFAULT_CELL_SIZE, MediaPosition::Fit),
+            size            => {
+                (size, MediaPosition::Display(MediaAlignment::LeftTop, MediaAlignment::LeftTop))
+            }
+        };
+        // A missing dimension is chosen to keep the aspect ratio of the image. The sizes are
+        // given by the program, so they are computed in 64 bits and kept within the grid.
+        let (grid_w, grid_h) = (self.grid_width(), self.grid_height());
+        let (cols, rows) = (cmp::min(params.cols, grid_w), cmp::min(params.rows, grid_h));
+        let (img_w, img_h) = (cmp::max(image.width, 1) as u64, cmp::max(image.height, 1) as u64);
+        let (cell_w, cell_h) = (cell_w as u64, cell_h as u64);
+        let (width, height, pos) = match (cols, rows) {
+            (0, 0)          => (ceil_div(img_w, cell_w), ceil_div(img_h, cell_h), natural),
+            (0, rows)       => {
+                let width = ceil_div((rows as u64 * cell_h).saturating_mul(img_w), img_h * cell_w);
+                (width, rows, MediaPosition::Fit)
+            }
+            (cols, 0)       => {
+                let height = ceil_div((cols as u64 * cell_w).saturating_mul(img_h), img_w * cell_h);
+                (cols, height, MediaPosition::Fit)
+            }
+            (cols, rows)    => (cols, rows, MediaPosition::Stretch),
+        };
+        let (width, height) = (cmp::min(width, grid_w), cmp::min(height, grid_h));
+        let placement = Placement {
+            image: id,
+            id: placement,
+            data: Arc::new(ImageData::new(image.data, Coords { x: 0, y: 0 })),
+            pos: pos,
+            width: width,
+            height: height,
+            z: params.z,
+        };
+        if params.virtual_placement {
+            self.images.place_virtual(placement);
+            return Ok(())
+        }
+        self.screen.place_image(placement);
+        if params.move_cursor {
+            let Coords { x, .. } = self.cursor_position();
+            if height > 1 { self.move_cursor(NextLine(height - 1)); }
+            self.move_cursor(Column(x.saturating_add(width)));
+        }
+        Ok(())
+    }
+
+    /// The virtual placement which unicode placeholder cells showing tiles of this image use.
+    pub fn virtual_placement(&self, image: u32) -> Option<&Placement> {
+        self.images.virtual_placement(image)
+    }
+
+    /// Delete placed images. If `free` is set, the images whose placements were deleted are
+    /// also removed from the store, so they cannot be placed again.
+    pub fn delete_images(&mut self, spec: DeleteSpec, free: bool) {
+        fn covers(Coords { x, y }: Coords, placement: &Placement, cell: Coords) -> bool {
+            cell.x >= x && cell.x < x.saturating_add(placement.width) &&
+                cell.y >= y && cell.y < y.saturating_add(placement.height)
+        }
+        let window = self.window();
+        let cursor = self.cursor_position();
+        let deleted = match spec {
+            DeleteSpec::All             => {
+                self.images.delete_virtual(|_| true);
+                self.screen.delete_images(|_, _| true)
+            }
+            DeleteSpec::Image(id, 0)    => {
+                if free { self.images.remove(id); }
+                self.images.delete_virtual(|p| p.image == id);
+                self.screen.delete_images(|_, p| p.image == id)
+            }
+            DeleteSpec::Number(number)  => match self.images.by_number(number) {
+                Some(id)    => return self.delete_images(DeleteSpec::Image(id, 0), free),
+                None        => return,
+            },
+            DeleteSpec::Image(id, pid)  => {
+                self.images.delete_virtual(|p| p.image == id && p.id == pid);
+                self.screen.delete_images(|_, p| p.image == id && p.id == pid)
+            }
+            DeleteSpec::Cursor          => {
+                self.screen.delete_images(|coords, p| covers(coords, p, cursor))
+            }
+            DeleteSpec::Cell(cell)      => {
+                let cell = Coords {
+                    x: cell.x.saturating_add(window.left),
+                    y: cell.y.saturating_add(window.top),
+                };
+                self.screen.delete_images(|coords, p| covers(coords, p, cell))
+            }
+            DeleteSpec::Column(x)       => {
+                let x = x.saturating_add(window.left);
+                self.screen.delete_images(|coords, p| {
+                    x >= coords.x && x < coords.x.saturating_add(p.width)
+                })
+            }
+            DeleteSpec::Row(y)          => {
+                let y = y.saturating_add(window.top);
+                self.screen.delete_images(|coords, p| {
+                    y >= coords.y && y < coords.y.saturating_add(p.height)
+                })
+            }
+            DeleteSpec::ZIndex(z)       => self.screen.delete_images(|_, p| p.z == z),
+        };
+        if free {
+            for id in deleted { self.images.remove(id); }
+        }
+    }
+
+    /// Resize the terminal to a number of columns and rows, keeping the current size of any
+    /// dimension which is not given. The size of a cell in pixels, if given, is used to tell
+    /// programs the size of the text area in pixels.
+    pub fn set_winsize(&mut self, cols: Option<u32>, rows: Option<u32>,
+                       cell_size: Option<(u32, u32)>) -> io::Result<()> {
         let (cols, rows) = match (cols, rows) {
             (Some(w), Some(h)) if w > 0 && h > 0    => (w, h),
             (Some(w), _) if w > 0                   => (w, self.area().bottom),
@@ -99,7 +616,9 @@ impl Terminal {
             (_, _)                                  => (self.area().right, self.area().bottom),
         };
         self.resize(cols, rows);
-        self.tty.set_winsize(cols, rows)
+        if let Some(cell_size) = cell_size { self.cell_size = cell_size; }
+        let (x_pixels, y_pixels) = self.pixel_size();
+        self.tty.set_winsize(cols, rows, x_pixels, y_pixels)
     }
 
 }
@@ -116,3 +635,11 @@ impl DerefMut for Terminal {
         &mut self.screen
     }
 }
+
+// Divide rounding up, for sizes of at least one cell which fit in a u32. The sizes come from
+// programs and image headers, so nothing here may overflow.
+fn ceil_div(n: u64, d: u64) -> u32 {
+    let d = cmp::max(d, 1);
+    let quotient = n / d + if n % d == 0 { 0 } else { 1 };
+    cmp::min(cmp::max(quotient, 1), !0u32 as u64) as u32
+}
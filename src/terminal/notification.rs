@@ -0,0 +1,34 @@
+//  notty is a new kind of terminal emulator.
+//  Copyright (C) 2015 without boats
+//  
+//  This program is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU Affero General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//  
+//  This program is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU Affero General Public License for more details.
+//  
+//  You should have received a copy of the GNU Affero General Public License
+//  along with this program.  If not, see <http://www.gnu.org/licenses/>.
+/// A desktop notification requested by a program with OSC 9 or OSC 777.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Notification {
+    pub title: Option<String>,
+    pub body: String,
+}
+
+impl Notification {
+
+    /// An OSC 9 notification, which has only a body.
+    pub fn new(body: String) -> Notification {
+        Notification { title: None, body: body }
+    }
+
+    pub fn with_title(title: String, body: String) -> Notification {
+        Notification { title: Some(title), body: body }
+    }
+
+}
@@ -0,0 +1,188 @@
+//  notty is a new kind of terminal emulator.
+//  Copyright (C) 2015 without boats
+//  
+//  This program is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU Affero General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//  
+//  This program is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU Affero General Public License for more details.
+//  
+//  You should have received a copy of the GNU Affero General Public License
+//  along with this program.  If not, see <http://www.gnu.org/licenses/>.
+/// A color as red, green and blue components.
+pub type Rgb = (u8, u8, u8);
+
+/// The colors which can be changed by OSC 10, 11 and 12 in addition to the 256 color palette.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum DynamicColor {
+    Foreground,
+    Background,
+    Cursor,
+}
+
+impl DynamicColor {
+    /// The dynamic color set by the given OSC code.
+    pub fn from_osc(code: u32) -> Option<DynamicColor> {
+        match code {
+            10  => Some(DynamicColor::Foreground),
+            11  => Some(DynamicColor::Background),
+            12  => Some(DynamicColor::Cursor),
+            _   => None,
+        }
+    }
+
+    pub fn osc(&self) -> u32 {
+        match *self {
+            DynamicColor::Foreground    => 10,
+            DynamicColor::Background    => 11,
+            DynamicColor::Cursor        => 12,
+        }
+    }
+}
+
+/// The colors the terminal is drawn with. The frontend sets the default palette from its
+/// configuration, programs running in the terminal can change and query it with OSC codes, and
+/// renderers draw with the palette the terminal holds rather than their own configuration.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Palette {
+    pub fg_color: Rgb,
+    pub bg_color: Rgb,
+    pub cursor_color: Rgb,
+    colors: Vec<Rgb>,
+}
+
+impl Palette {
+
+    pub fn new(fg_color: Rgb, bg_color: Rgb, cursor_color: Rgb, colors: &[Rgb]) -> Palette {
+        assert_eq!(colors.len(), 256);
+        Palette {
+            fg_color: fg_color,
+            bg_color: bg_color,
+            cursor_color: cursor_color,
+            colors: colors.to_vec(),
+        }
+    }
+
+    pub fn color(&self, n: u8) -> Rgb {
+        self.colors[n as usize]
+    }
+
+    pub fn set_color(&mut self, n: u8, color: Rgb) {
+        self.colors[n as usize] = color;
+    }
+
+    pub fn dynamic_color(&self, color: DynamicColor) -> Rgb {
+        match color {
+            DynamicColor::Foreground    => self.fg_color,
+            DynamicColor::Background    => self.bg_color,
+            DynamicColor::Cursor        => self.cursor_color,
+        }
+    }
+
+    pub fn set_dynamic_color(&mut self, color: DynamicColor, rgb: Rgb) {
+        match color {
+            DynamicColor::Foreground    => self.fg_color = rgb,
+            DynamicColor::Background    => self.bg_color = rgb,
+            DynamicColor::Cursor        => self.cursor_color = rgb,
+        }
+    }
+
+}
+
+impl Default for Palette {
+    fn default() -> Palette {
+        static BASE: [Rgb; 16] = [
+            (0x00,0x00,0x00), (0x55,0x55,0xff), (0x55,0xff,0x55), (0x55,0xff,0xff),
+            (0xff,0x55,0x55), (0xff,0x55,0xff), (0xff,0xff,0x55), (0xbb,0xbb,0xbb),
+            (0x55,0x55,0x55), (0x55,0x55,0xff), (0x55,0xff,0x55), (0x55,0xff,0xff),
+            (0xff,0x55,0x55), (0xff,0x55,0xff), (0xff,0xff,0x55), (0xff,0xff,0xff),
+        ];
+        static LEVELS: [u8; 6] = [0x00, 0x5f, 0x87, 0xaf, 0xd7, 0xff];
+        let mut colors = BASE.to_vec();
+        for n in 0..216 {
+            colors.push((LEVELS[n / 36], LEVELS[(n / 6) % 6], LEVELS[n % 6]));
+        }
+        for n in 0..24 {
+            let level = 8 + 10 * n as u8;
+            colors.push((level, level, level));
+        }
+        Palette {
+            fg_color: (0xff, 0xff, 0xff),
+            bg_color: (0x00, 0x00, 0x00),
+            cursor_color: (0xbb, 0xbb, 0xbb),
+            colors: colors,
+        }
+    }
+}
+
+/// Parse a color in one of the formats accepted by XParseColor: `rgb:r/g/b` with one to four
+/// hex digits per component, or `#` followed by one to four hex digits per component. X11
+/// color names are not supported.
+pub fn parse_color(spec: &str) -> Option<Rgb> {
+    if spec.starts_with("rgb:") {
+        let parts = spec[4..].split('/').map(scale).collect::<Vec<_>>();
+        if parts.len() != 3 { return None }
+        match (parts[0], parts[1], parts[2]) {
+            (Some(r), Some(g), Some(b)) => Some((r, g, b)),
+            _                           => None,
+        }
+    } else if spec.starts_with('#') {
+        let digits = &spec[1..];
+        let n = digits.len() / 3;
+        if n == 0 || n > 4 || digits.len() % 3 != 0 { return None }
+        match (scale(&digits[..n]), scale(&digits[n..2*n]), scale(&digits[2*n..])) {
+            (Some(r), Some(g), Some(b)) => Some((r, g, b)),
+            _                           => None,
+        }
+    } else { None }
+}
+
+/// Format a color as the response to a color query, using four hex digits per component as
+/// xterm does.
+pub fn format_color((r, g, b): Rgb) -> String {
+    format!("rgb:{:02x}{:02x}/{:02x}{:02x}/{:02x}{:02x}", r, r, g, g, b, b)
+}
+
+// Scale a component of one to four hex digits to eight bits.
+fn scale(digits: &str) -> Option<u8> {
+    if digits.len() == 0 || digits.len() > 4 { return None }
+    let max = (1u32 << (4 * digits.len())) - 1;
+    u32::from_str_radix(digits, 16).ok().map(|n| (n * 0xff / max) as u8)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn default_palette() {
+        let palette = Palette::default();
+        assert_eq!(palette.color(16), (0x00, 0x00, 0x00));
+        assert_eq!(palette.color(67), (0x5f, 0x87, 0xaf));
+        assert_eq!(palette.color(231), (0xff, 0xff, 0xff));
+        assert_eq!(palette.color(232), (0x08, 0x08, 0x08));
+        assert_eq!(palette.color(255), (0xee, 0xee, 0xee));
+    }
+
+    #[test]
+    fn parse() {
+        assert_eq!(parse_color("rgb:ff/80/00"), Some((0xff, 0x80, 0x00)));
+        assert_eq!(parse_color("rgb:ffff/8080/0"), Some((0xff, 0x80, 0x00)));
+        assert_eq!(parse_color("rgb:f/8/0"), Some((0xff, 0x88, 0x00)));
+        assert_eq!(parse_color("#ff8000"), Some((0xff, 0x80, 0x00)));
+        assert_eq!(parse_color("#f80"), Some((0xff, 0x88, 0x00)));
+        assert_eq!(parse_color("rgb:ff/80"), None);
+        assert_eq!(parse_color("red"), None);
+    }
+
+    #[test]
+    fn format() {
+        assert_eq!(format_color((0xff, 0x80, 0x00)), "rgb:ffff/8080/0000");
+    }
+
+}
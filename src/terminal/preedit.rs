@@ -0,0 +1,95 @@
+//  notty is a new kind of terminal emulator.
+//  Copyright (C) 2015 without boats
+//  
+//  This program is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU Affero General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//  
+//  This program is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU Affero General Public License for more details.
+//  
+//  You should have received a copy of the GNU Affero General Public License
+//  along with this program.  If not, see <http://www.gnu.org/licenses/>.
+use std::cmp;
+
+use unicode_width::*;
+
+use terminal::{Styles, UseStyles};
+
+/// Text which an input method is composing but has not yet committed. It is drawn at the
+/// cursor, over the contents of the grid, and is not sent to the tty until it is committed.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Preedit {
+    text: String,
+    cursor: usize,
+    styles: UseStyles,
+}
+
+impl Preedit {
+
+    /// Create a preedit string with the cursor at the given offset, counted in chars. The text
+    /// is underlined to distinguish it from the contents of the grid.
+    pub fn new(text: String, cursor: usize) -> Preedit {
+        Preedit::with_styles(text, cursor, UseStyles::Custom(Styles {
+            underline: true,
+            ..Styles::new()
+        }))
+    }
+
+    pub fn with_styles(text: String, cursor: usize, styles: UseStyles) -> Preedit {
+        let cursor = cmp::min(cursor, text.chars().count());
+        Preedit {
+            text: text,
+            cursor: cursor,
+            styles: styles,
+        }
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn styles(&self) -> UseStyles {
+        self.styles
+    }
+
+    /// The offset of the cursor in chars from the beginning of the text.
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    /// The number of columns the text occupies.
+    pub fn width(&self) -> u32 {
+        self.text.width() as u32
+    }
+
+    /// The number of columns between the beginning of the text and the cursor.
+    pub fn cursor_column(&self) -> u32 {
+        self.text.chars().take(self.cursor).filter_map(|c| c.width()).sum::<usize>() as u32
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn cursor_column() {
+        let preedit = Preedit::new(String::from("にほんご"), 2);
+        assert_eq!(preedit.width(), 8);
+        assert_eq!(preedit.cursor_column(), 4);
+    }
+
+    #[test]
+    fn cursor_is_clamped() {
+        let preedit = Preedit::new(String::from("abc"), 7);
+        assert_eq!(preedit.cursor(), 3);
+        assert_eq!(preedit.cursor_column(), 3);
+    }
+
+}
@@ -1,7 +1,7 @@
 use std::ops::{Deref, DerefMut};
 
-use datatypes::{Region, SaveGrid, SplitKind, ResizeRule};
-use terminal::char_grid::CharGrid;
+use datatypes::{Coords, Region, SaveGrid, SplitKind, ResizeRule};
+use terminal::char_grid::{CharCell, CharGrid, WorkingDir};
 
 mod panel;
 mod section;
@@ -63,8 +63,12 @@ impl Screen {
 
     pub fn split(&mut self, save: SaveGrid, kind: SplitKind, rule: ResizeRule,
                  split_tag: Option<u64>, l_tag: u64, r_tag: u64, retain_offscreen_state: bool) {
+        let cwd = self.grid_cwd(split_tag);
         self.find_mut(split_tag).map(|section| section.split(save, kind, rule, l_tag, r_tag,
                                                              retain_offscreen_state));
+        // The new grid starts in the directory of the grid it was split from.
+        let new_tag = match save { SaveGrid::Left => r_tag, SaveGrid::Right => l_tag };
+        self.screen.find_mut(new_tag).map(|section| section.grid_mut().set_cwd(cwd));
         if split_tag.map_or(true, |tag| tag == self.active) {
             self.active = match save {
                 SaveGrid::Left  => l_tag,
@@ -87,7 +91,11 @@ impl Screen {
     }
 
     pub fn push(&mut self, tag: Option<u64>, retain_offscreen_state: bool) {
-        self.find_mut(tag).map(|section| section.push(retain_offscreen_state));
+        let cwd = self.grid_cwd(tag);
+        self.find_mut(tag).map(|section| {
+            section.push(retain_offscreen_state);
+            section.grid_mut().set_cwd(cwd);
+        });
     }
 
     pub fn pop(&mut self, tag: Option<u64>) {
@@ -102,6 +110,21 @@ impl Screen {
         self.find_mut(tag).map(ScreenSection::rotate_up);
     }
 
+    /// The working directory of the grid in the section with this tag, or of the active grid.
+    pub fn cwd_of(&self, tag: Option<u64>) -> Option<&WorkingDir> {
+        self.find(tag).into_iter().filter(|section| section.is_grid())
+                      .flat_map(|section| section.grid().cwd()).next()
+    }
+
+    /// Every grid on the screen, whether or not it is visible.
+    pub fn grids(&self) -> Vec<&CharGrid> {
+        self.screen.grids()
+    }
+
+    pub fn grids_mut(&mut self) -> Vec<&mut CharGrid> {
+        self.screen.grids_mut()
+    }
+
     pub fn cells(&self) -> Cells {
         self.screen.cells()
     }
@@ -110,6 +133,18 @@ impl Screen {
         self.screen.panels()
     }
 
+    /// Images which cover cells on the screen but were written in cells which are not on it,
+    /// such as images whose first rows have scrolled into the scrollback. Each is given with
+    /// the coordinates of the top left cell of its visible part, and the offset of that cell
+    /// from the cell the image was written in.
+    pub fn partial_images(&self) -> Vec<(Coords, Coords, &CharCell)> {
+        self.screen.partial_images()
+    }
+
+    fn grid_cwd(&self, tag: Option<u64>) -> Option<WorkingDir> {
+        self.cwd_of(tag).cloned()
+    }
+
     fn find(&self, tag: Option<u64>) -> Option<&ScreenSection> {
         self.screen.find(tag.unwrap_or(self.active))
     }
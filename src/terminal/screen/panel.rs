@@ -37,6 +37,26 @@ impl<T: GridFill> Panel<T> {
         } else { None }
     }
 
+    pub fn grids(&self) -> Vec<&T> {
+        match *self {
+            Grid(ref grid)                      => vec![grid],
+            Split { ref left, ref right, .. }   => {
+                left.grids().into_iter().chain(right.grids()).collect()
+            }
+            DeadGrid                            => vec![],
+        }
+    }
+
+    pub fn grids_mut(&mut self) -> Vec<&mut T> {
+        match *self {
+            Grid(ref mut grid)                          => vec![grid],
+            Split { ref mut left, ref mut right, .. }   => {
+                left.grids_mut().into_iter().chain(right.grids_mut()).collect()
+            }
+            DeadGrid                                    => vec![],
+        }
+    }
+
     pub fn resize(&mut self, old_area: Region, new_area: Region, rule: ResizeRule) {
         match *self {
             Grid(ref mut grid) => {
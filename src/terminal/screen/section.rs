@@ -92,6 +92,15 @@ impl<T: GridFill> ScreenSection<T> {
         }
     }
 
+    /// All of the grids in this section, including those in panels beneath the top one.
+    pub fn grids(&self) -> Vec<&T> {
+        self.ring.iter().flat_map(Panel::grids).collect()
+    }
+
+    pub fn grids_mut(&mut self) -> Vec<&mut T> {
+        self.ring.iter_mut().flat_map(Panel::grids_mut).collect()
+    }
+
     /// Get a reference to the children section of this section if it is split.
     pub fn children(&self) -> Option<(&ScreenSection<T>, &ScreenSection<T>)> {
         if let Split { ref left, ref right, .. } = self.ring.top {
@@ -200,6 +209,23 @@ impl ScreenSection {
         }
     }
 
+    /// The images which are partly visible in the panels of this section, with the coordinates
+    /// on the screen of the top left corner of their visible parts.
+    pub fn partial_images(&self) -> Vec<(Coords, Coords, &CharCell)> {
+        match self.ring.top {
+            Grid(ref grid)                      => {
+                let area = self.area;
+                grid.partial_images().into_iter().map(|(coords, offset, cell)| {
+                    (Coords { x: coords.x + area.left, y: coords.y + area.top }, offset, cell)
+                }).collect()
+            }
+            Split { ref left, ref right, .. }   => {
+                left.partial_images().into_iter().chain(right.partial_images()).collect()
+            }
+            DeadGrid                            => vec![],
+        }
+    }
+
 }
 
 impl Index<Coords> for ScreenSection {
@@ -0,0 +1,96 @@
+//  notty is a new kind of terminal emulator.
+//  Copyright (C) 2015 without boats
+//  
+//  This program is free software: you can redistribute it and/or modify
+//  it under the terms of the GNU Affero General Public License as published by
+//  the Free Software Foundation, either version 3 of the License, or
+//  (at your option) any later version.
+//  
+//  This program is distributed in the hope that it will be useful,
+//  but WITHOUT ANY WARRANTY; without even the implied warranty of
+//  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+//  GNU Affero General Public License for more details.
+//  
+//  You should have received a copy of the GNU Affero General Public License
+//  along with this program.  If not, see <http://www.gnu.org/licenses/>.
+/// A change to the window of the terminal requested by a program with `CSI t`, which the
+/// embedding application carries out if the window policy allows it.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum WindowOp {
+    /// Iconify the window, or restore it from being iconified.
+    Iconify(bool),
+    /// Move the top left corner of the window to a position in pixels.
+    Move(u32, u32),
+    /// Resize the text area of the window to a width and height in pixels. A missing dimension
+    /// should be left unchanged.
+    ResizePixels(Option<u32>, Option<u32>),
+    /// Resize the text area of the window to a number of columns and rows. A missing dimension
+    /// should be left unchanged.
+    ResizeCells(Option<u32>, Option<u32>),
+    Raise,
+    Lower,
+    /// Maximize the window horizontally, vertically, or both; if neither, restore it.
+    Maximize { horizontal: bool, vertical: bool },
+    /// Enter or leave fullscreen mode, or toggle it if `None`.
+    Fullscreen(Option<bool>),
+}
+
+/// A report about the window requested by a program with `CSI t`, which the terminal answers
+/// itself.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum WindowReport {
+    /// The size of the text area in pixels.
+    PixelSize,
+    /// The size of a cell in pixels.
+    CellSize,
+    /// The size of the text area in cells.
+    TextSize,
+    /// The size of the screen in cells, which is reported as the size of the text area.
+    ScreenSize,
+    /// The icon name, if the window policy allows it.
+    Icon,
+    /// The title, if the window policy allows it.
+    Title,
+    /// Whether the window is iconified.
+    Iconified,
+    /// The position of the window in pixels, if the window policy allows it.
+    Position,
+}
+
+/// Which of the icon name and the title are saved by `CSI 22 t` or restored by `CSI 23 t`.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum TitleKind {
+    Both,
+    Icon,
+    Title,
+}
+
+impl TitleKind {
+    pub fn icon(&self) -> bool {
+        *self != TitleKind::Title
+    }
+
+    pub fn title(&self) -> bool {
+        *self != TitleKind::Icon
+    }
+}
+
+/// Whether programs running in the terminal may change the window, and read back its title.
+/// The size of the window can always be reported.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum WindowPolicy {
+    Deny,
+    Allow,
+}
+
+impl WindowPolicy {
+    pub fn allows(&self) -> bool {
+        *self == WindowPolicy::Allow
+    }
+}
+
+impl Default for WindowPolicy {
+    fn default() -> WindowPolicy {
+        WindowPolicy::Deny
+    }
+}